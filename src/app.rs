@@ -1,195 +1,2052 @@
+use crate::cli::{self, Options};
+use crate::clipboard;
+use crate::color;
 use crate::color_picker::show_color_picker;
-use crate::color_window::{set_hide_callback, ColorWindow};
-use crate::config::{Config, HotkeyConfig};
+use crate::color_window::{
+    set_hide_callback, set_key_callback, set_wheel_callback, ColorWindow, HideTrigger,
+};
+use crate::config::{Config, HotkeyConfig, KeepAwake, OverlaySettings, Preset, Profile};
+use crate::control::{Client, ControlServer, WM_CONTROL};
+use crate::fade::{self, Fade};
+use crate::file_dialog::{show_open_dialog, show_save_dialog};
+use crate::generator::{Generator, GeneratorEvent, WM_GENERATOR};
+use crate::guard::{format_countdown, Deadlines, Due};
 use crate::hotkey_dialog::show_hotkey_dialog;
-use crate::monitor::enumerate_monitors;
+use crate::http_server::{HttpServer, WM_HTTP};
+use crate::icon::Badge;
+use crate::idle::{idle_step, IdleSource, IdleStep};
+use crate::input_dialog::show_input_dialog;
+use crate::instance;
+use crate::keymap::{Action, ChordResult, ChordState, Keymap};
+use crate::monitor::{enumerate_monitors, MonitorSelection};
+use crate::palette;
+use crate::patch::{Patch, PatchSize, PatchWindow};
+use crate::patch_set::PatchSet;
+use crate::pattern::Pattern;
+use crate::player::{Player, Tick};
+use crate::power::keep_display_on;
+use crate::protocol::{self, Command, Event, Reply, Request, State};
+use crate::schedule::{self, LocalTime};
 use crate::startup;
-use crate::tray::{update_hotkey_display, TrayEvent, TrayIcon};
-use std::cell::RefCell;
+use crate::tray::{
+    update_auto_hide, update_colors, update_dimmer, update_generator, update_hotkey_display,
+    update_profiles, update_sequences, update_window, TrayEvent, TrayIcon, MAX_PRESETS,
+};
+use crate::watcher::{watch_dir, WM_DIR_CHANGED};
+use std::cell::{Cell, RefCell};
 use std::mem::zeroed;
 use std::ptr::null_mut;
 use std::rc::Rc;
-use windows_sys::Win32::UI::Input::KeyboardAndMouse::{RegisterHotKey, UnregisterHotKey};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    GetKeyState, RegisterHotKey, UnregisterHotKey, VK_ADD, VK_CONTROL, VK_DOWN, VK_END, VK_HOME,
+    VK_LEFT, VK_NEXT, VK_OEM_MINUS, VK_OEM_PLUS, VK_PRIOR, VK_RIGHT, VK_SHIFT, VK_SPACE,
+    VK_SUBTRACT, VK_UP,
+};
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    DispatchMessageW, GetMessageW, PostQuitMessage, TranslateMessage, MSG, WM_HOTKEY,
+    DispatchMessageW, GetMessageW, KillTimer, MessageBoxW, PostQuitMessage, SetTimer,
+    TranslateMessage, MB_ICONWARNING, MB_OK, MSG, WM_HOTKEY, WM_TIMER,
 };
 
-const DEFAULT_COLOR: u32 = 0x00000000; // Black
-const HOTKEY_TOGGLE: i32 = 1;
+// Hotkey ids: leaders (first chords) start at 1, follow-up chords that are
+// only registered while a sequence is pending start at HOTKEY_FOLLOWER_BASE.
+const HOTKEY_LEADER_BASE: i32 = 1;
+const HOTKEY_FOLLOWER_BASE: i32 = 0x100;
+
+/// Quiet period after the last change in the config directory before the
+/// config is re-read; editors often write a file several times per save.
+const RELOAD_DELAY: Duration = Duration::from_millis(300);
+const GUARD_INTERVAL_MS: u32 = 1000;
+/// How often schedule rules are checked against the clock.
+const SCHEDULE_INTERVAL_MS: u32 = 30_000;
+/// How often idle time is polled; also how quickly input wakes the screen.
+const IDLE_INTERVAL_MS: u32 = 500;
+/// Frame time while fading, about 60 fps.
+const FADE_FRAME_MS: u32 = 16;
+/// How long the OSD stays after it last changed, and how long it then takes
+/// to fade away.
+const OSD_HOLD: Duration = Duration::from_secs(3);
+const OSD_FADE: Duration = Duration::from_secs(1);
+/// Brightness change per wheel notch or `+`/`-` press; 1 with Shift held.
+const BRIGHTNESS_STEP: i32 = 5;
 
 pub struct App {
-    windows: Rc<RefCell<Vec<ColorWindow>>>,
-    color: Rc<RefCell<u32>>,
-    visible: Rc<RefCell<bool>>,
-    hotkey: Rc<RefCell<HotkeyConfig>>,
+    windows: RefCell<Vec<ColorWindow>>,
+    overlay: RefCell<OverlaySettings>,
+    /// Profile the overlay settings were last loaded from, until changed.
+    active_profile: RefCell<Option<String>>,
+    visible: Cell<bool>,
+    config: RefCell<Config>,
+    keymap: RefCell<Keymap>,
+    chord: RefCell<ChordState>,
+    leaders: RefCell<Vec<HotkeyConfig>>,
+    followers: RefCell<Vec<HotkeyConfig>>,
+    chord_timer: Cell<usize>,
+    reload_timer: Cell<usize>,
+    /// Hides the overlay when a `--duration` runs out.
+    hide_timer: Cell<usize>,
+    /// When the overlay hides by itself.
+    deadlines: Cell<Deadlines>,
+    /// Checks the deadlines and refreshes the countdown every second.
+    guard_timer: Cell<usize>,
+    /// Set by `--exit-after`: quit the next time the overlay hides.
+    exit_on_hide: Cell<bool>,
+    tray: RefCell<Option<TrayIcon>>,
+    control: RefCell<Option<ControlServer>>,
+    http: RefCell<Option<HttpServer>>,
+    generator: RefCell<Option<Generator>>,
+    /// Latest patch from the pattern generator; overrides the overlay.
+    patch: Cell<Option<Patch>>,
+    /// Colors being stepped through, replacing the overlay color.
+    patch_set: RefCell<Option<PatchSet>>,
+    /// Overlay settings and profile from before a patch set or sequence
+    /// started, put back when it ends.
+    before_session: RefCell<Option<(OverlaySettings, Option<String>)>>,
+    /// Moves to the next patch while auto-advancing.
+    advance_timer: Cell<usize>,
+    /// Sequence being played, replacing the overlay color and pattern.
+    player: RefCell<Option<Player>>,
+    /// Fires when the current sequence step ends.
+    player_timer: Cell<usize>,
+    /// Checks the schedule while it has rules.
+    schedule_timer: Cell<usize>,
+    /// Index of the schedule rule in charge, if any.
+    scheduled: Cell<Option<usize>>,
+    /// Overlay settings and profile from before a rule started, put back
+    /// when it ends.
+    before_schedule: RefCell<Option<(OverlaySettings, Option<String>)>>,
+    idle: Box<dyn IdleSource>,
+    /// Polls the idle time while the idle overlay is enabled.
+    idle_timer: Cell<usize>,
+    /// Overlay settings and profile from before the idle overlay showed;
+    /// `Some` while it is up.
+    before_idle: RefCell<Option<(OverlaySettings, Option<String>)>>,
+    /// Whether display sleep is currently held off.
+    awake: Cell<bool>,
+    /// Brings `windows` up to the overlay opacity after showing.
+    fade_in: RefCell<Option<Fade>>,
+    /// Windows of a hidden overlay, fading out before they are dropped.
+    fade_out: RefCell<Option<(Fade, Vec<ColorWindow>)>>,
+    /// Steps the fades while either runs.
+    fade_timer: Cell<usize>,
+    /// Click-through dimmer windows; empty while the dimmer is off.
+    dimmer: RefCell<Vec<ColorWindow>>,
+    /// Dimmer opacity in percent, adjusted by `dimmer +` and `dimmer -`.
+    dimmer_level: Cell<u8>,
+    /// Shows the displayed color's values once brightness was adjusted on
+    /// the overlay, until it hides.
+    readout: Cell<bool>,
+    /// Whether the information OSD is on, toggled with `I`.
+    osd: Cell<bool>,
+    /// Fades the OSD out a while after it last changed.
+    osd_fade: Cell<Option<Fade>>,
+    /// Running on defaults because the config file failed to load; saving
+    /// would overwrite the user's file, so nothing is written until a
+    /// reload succeeds.
+    config_fallback: Cell<bool>,
 }
 
 impl App {
-    pub fn new(config: Config) -> Self {
+    /// `fallback` says `config` is the defaults standing in for a config
+    /// file that failed to load. `idle` reports the time since the last
+    /// input, for the idle overlay.
+    pub fn new(config: Config, fallback: bool, idle: Box<dyn IdleSource>) -> Self {
         App {
-            windows: Rc::new(RefCell::new(Vec::new())),
-            color: Rc::new(RefCell::new(DEFAULT_COLOR)),
-            visible: Rc::new(RefCell::new(false)),
-            hotkey: Rc::new(RefCell::new(config.hotkey)),
+            windows: RefCell::new(Vec::new()),
+            overlay: RefCell::new(config.overlay()),
+            active_profile: RefCell::new(None),
+            visible: Cell::new(false),
+            keymap: RefCell::new(config.keymap()),
+            chord: RefCell::new(ChordState::new(config.chord_timeout)),
+            dimmer_level: Cell::new(config.dimmer.level),
+            osd: Cell::new(config.osd),
+            config: RefCell::new(config),
+            leaders: RefCell::new(Vec::new()),
+            followers: RefCell::new(Vec::new()),
+            chord_timer: Cell::new(0),
+            reload_timer: Cell::new(0),
+            hide_timer: Cell::new(0),
+            deadlines: Cell::new(Deadlines::default()),
+            guard_timer: Cell::new(0),
+            exit_on_hide: Cell::new(false),
+            tray: RefCell::new(None),
+            control: RefCell::new(None),
+            http: RefCell::new(None),
+            generator: RefCell::new(None),
+            patch: Cell::new(None),
+            patch_set: RefCell::new(None),
+            before_session: RefCell::new(None),
+            advance_timer: Cell::new(0),
+            player: RefCell::new(None),
+            player_timer: Cell::new(0),
+            schedule_timer: Cell::new(0),
+            scheduled: Cell::new(None),
+            before_schedule: RefCell::new(None),
+            idle,
+            idle_timer: Cell::new(0),
+            before_idle: RefCell::new(None),
+            awake: Cell::new(false),
+            fade_in: RefCell::new(None),
+            fade_out: RefCell::new(None),
+            fade_timer: Cell::new(0),
+            dimmer: RefCell::new(Vec::new()),
+            readout: Cell::new(false),
+            osd_fade: Cell::new(None),
+            config_fallback: Cell::new(fallback),
         }
     }
 
-    pub fn run(self) {
-        // Register global hotkey
-        let hotkey = self.hotkey.borrow();
-        unsafe {
-            RegisterHotKey(
-                null_mut(),
-                HOTKEY_TOGGLE,
-                hotkey.modifiers,
-                hotkey.key,
-            );
+    pub fn run(self, options: Options, patches: Option<PatchSet>) {
+        let app = Rc::new(self);
+
+        if let Some(name) = &options.profile {
+            app.apply_profile(name);
         }
+        app.apply_overrides(&options);
+        update_window(app.overlay.borrow().window.map(|w| w.size));
 
-        let hotkey_display = hotkey.display();
-        drop(hotkey);
+        app.register_leaders();
+        app.warn_conflicts();
+
+        let hotkey_display = app.config.borrow().hotkey.display();
+
+        let app_for_hide = Rc::clone(&app);
+        set_hide_callback(move |trigger| {
+            app_for_hide.on_hide_trigger(trigger);
+        });
+        let app_for_keys = Rc::clone(&app);
+        set_key_callback(move |key| {
+            app_for_keys.on_key(key);
+        });
+        let app_for_wheel = Rc::clone(&app);
+        set_wheel_callback(move |notches| {
+            app_for_wheel.on_wheel(notches);
+        });
+
+        if !options.no_tray {
+            let app_for_tray = Rc::clone(&app);
+            let tray = TrayIcon::new(&hotkey_display, move |event| {
+                app_for_tray.handle_tray_event(event);
+            });
+            *app.tray.borrow_mut() = tray;
+            app.update_tray_icon();
+            app.update_profile_menu();
+            app.update_sequence_menu();
+            app.update_color_menu();
+            let config = app.config.borrow();
+            update_auto_hide(config.auto_hide, config.countdown);
+        }
 
-        let windows_clone = Rc::clone(&self.windows);
-        let color_clone = Rc::clone(&self.color);
-        let visible_clone = Rc::clone(&self.visible);
+        *app.control.borrow_mut() = Some(ControlServer::start());
+        app.restart_http();
 
-        let windows_for_hide = Rc::clone(&self.windows);
-        let visible_for_hide = Rc::clone(&self.visible);
+        let generator = app.config.borrow().generator.clone();
+        if let Some(address) = options.generator.clone() {
+            app.start_generator(&address);
+        } else if generator.enabled {
+            app.start_generator(&generator.address);
+        }
 
-        set_hide_callback(move || {
-            hide_all(&windows_for_hide, &visible_for_hide);
+        let app_for_instance = Rc::clone(&app);
+        instance::listen(move |actions| {
+            app_for_instance.on_forwarded(actions);
         });
 
-        let color_for_menu = Rc::clone(&self.color);
-        let windows_for_menu = Rc::clone(&self.windows);
-        let hotkey_for_menu = Rc::clone(&self.hotkey);
+        if let Some(dir) = Config::config_dir() {
+            if std::fs::create_dir_all(&dir).is_ok() {
+                watch_dir(&dir);
+            }
+        }
+
+        // Before the startup actions, so an explicit `--show` or `--color` wins
+        app.arm_schedule_timer();
+        app.check_schedule(false);
+        app.arm_idle_timer();
 
-        let _tray = TrayIcon::new(&hotkey_display, move |event| match event {
+        if options.show || options.toggle {
+            app.exit_on_hide.set(options.exit_after);
+            if let Some(patches) = patches {
+                app.start_patch_set(patches, options.advance);
+            } else if let Some(name) = &options.sequence {
+                app.play_sequence(name);
+            } else {
+                app.show_all();
+            }
+            if let Some(duration) = options.duration {
+                let ms = duration.as_millis().min(u32::MAX as u128) as u32;
+                app.hide_timer
+                    .set(unsafe { SetTimer(null_mut(), 0, ms, None) });
+                app.deadlines.set(Deadlines {
+                    duration: Some(Instant::now() + duration),
+                    ..app.deadlines.get()
+                });
+                app.arm_guard_timer();
+            }
+        }
+
+        app.message_loop();
+
+        // The callbacks keep `app` alive, so remove the icon explicitly
+        app.tray.borrow_mut().take();
+    }
+
+    fn handle_tray_event(&self, event: TrayEvent) {
+        match event {
             TrayEvent::DoubleClick => {
-                toggle(&windows_clone, &color_clone, &visible_clone);
+                self.toggle();
             }
             TrayEvent::SelectColor => {
-                let current = *color_for_menu.borrow();
-                if let Some(new_color) = show_color_picker(current) {
-                    *color_for_menu.borrow_mut() = new_color;
-                    update_color(&windows_for_menu, new_color);
+                let (mut presets, keep) = {
+                    let config = self.config.borrow();
+                    (config.presets.clone(), config.presets_in_use())
+                };
+                let current = self.overlay.borrow().color;
+                let picked = show_color_picker(current, &mut presets, keep);
+                let mut config = self.config.borrow_mut();
+                if config.presets != presets {
+                    config.presets = presets;
+                    self.save_config(&config);
+                }
+                drop(config);
+                self.update_color_menu();
+                if let Some(new_color) = picked {
+                    self.pick_color(new_color);
                 }
             }
-            TrayEvent::ConfigureHotkey => {
-                let current = hotkey_for_menu.borrow();
-                let (mods, key) = (current.modifiers, current.key);
-                drop(current);
-
-                if let Some((new_mods, new_key)) = show_hotkey_dialog(mods, key) {
-                    // Unregister old hotkey
-                    unsafe {
-                        UnregisterHotKey(null_mut(), HOTKEY_TOGGLE);
+            TrayEvent::SetColor(color) => self.pick_color(color),
+            TrayEvent::ImportPalette => {
+                let filters = [
+                    ("Palettes (*.gpl, *.ase, *.json)", "*.gpl;*.ase;*.json"),
+                    ("All files", "*.*"),
+                ];
+                if let Some(path) = show_open_dialog("Import Palette", &filters) {
+                    match palette::load(&path) {
+                        Ok(presets) => self.import_presets(presets),
+                        Err(err) => self.notify("Palette", &err),
                     }
-
-                    // Update and register new hotkey
-                    let mut hotkey = hotkey_for_menu.borrow_mut();
-                    hotkey.modifiers = new_mods;
-                    hotkey.key = new_key;
-
-                    unsafe {
-                        RegisterHotKey(null_mut(), HOTKEY_TOGGLE, new_mods, new_key);
+                }
+            }
+            TrayEvent::ExportPalette => {
+                let filters = [
+                    ("GIMP palette (*.gpl)", "*.gpl"),
+                    ("Adobe Swatch Exchange (*.ase)", "*.ase"),
+                    ("JSON palette (*.json)", "*.json"),
+                ];
+                if let Some(path) = show_save_dialog("Export Presets", &filters, "presets") {
+                    let result = palette::save(&path, &self.config.borrow().presets);
+                    if let Err(err) = result {
+                        self.notify("Palette", &err);
+                    }
+                }
+            }
+            TrayEvent::LoadPatchSet => {
+                let filters = [
+                    (
+                        "Patch sets (*.ti1, *.ti2, *.ti3, *.csv)",
+                        "*.ti1;*.ti2;*.ti3;*.csv",
+                    ),
+                    ("All files", "*.*"),
+                ];
+                if let Some(path) = show_open_dialog("Load Patch Set", &filters) {
+                    match PatchSet::load(&path) {
+                        Ok(patches) => self.start_patch_set(patches, None),
+                        Err(err) => self.notify("Patch set", &err),
                     }
+                }
+            }
+            TrayEvent::SetAutoHide(delay) => {
+                self.config.borrow_mut().auto_hide = delay;
+                self.save_hide_settings();
+            }
+            TrayEvent::ToggleCountdown => {
+                let mut config = self.config.borrow_mut();
+                config.countdown = !config.countdown;
+                drop(config);
+                self.save_hide_settings();
+            }
+            TrayEvent::ConfigureHotkey => {
+                let current = self.config.borrow().hotkey.clone();
 
-                    // Update display and save config
-                    let display = hotkey.display();
-                    update_hotkey_display(&display);
+                if let Some((new_mods, new_key)) =
+                    show_hotkey_dialog(current.modifiers, current.key)
+                {
+                    // Swap the old leader set for one built from the new hotkey
+                    self.unregister_leaders();
 
-                    let config = Config {
-                        hotkey: hotkey.clone(),
+                    let mut config = self.config.borrow_mut();
+                    config.hotkey = HotkeyConfig {
+                        modifiers: new_mods,
+                        key: new_key,
                     };
-                    let _ = config.save();
+                    *self.keymap.borrow_mut() = config.keymap();
+                    drop(config);
+
+                    self.register_leaders();
+
+                    // Update display and save config
+                    let config = self.config.borrow();
+                    update_hotkey_display(&config.hotkey.display());
+                    self.save_config(&config);
                 }
             }
+            TrayEvent::SelectProfile(index) => {
+                let name = self
+                    .config
+                    .borrow()
+                    .profiles
+                    .get(index)
+                    .map(|p| p.name.clone());
+                if let Some(name) = name {
+                    self.apply_profile(&name);
+                }
+            }
+            TrayEvent::SelectSequence(index) => {
+                let sequence = self.config.borrow().sequences.get(index).cloned();
+                if let Some(sequence) = sequence {
+                    let playing = self
+                        .player
+                        .borrow()
+                        .as_ref()
+                        .map(|p| p.name() == sequence.name);
+                    if playing == Some(true) {
+                        self.stop_sequence();
+                    } else {
+                        self.play_sequence(&sequence.name);
+                    }
+                }
+            }
+            TrayEvent::SaveProfile => {
+                self.save_profile();
+            }
             TrayEvent::ToggleStartup => {
                 let enabled = startup::is_startup_enabled();
                 startup::set_startup_enabled(!enabled);
             }
-            TrayEvent::Exit => {
-                unsafe {
-                    UnregisterHotKey(null_mut(), HOTKEY_TOGGLE);
-                    PostQuitMessage(0);
+            TrayEvent::ToggleDimmer => self.toggle_dimmer(),
+            TrayEvent::ToggleGenerator => {
+                if self.generator.borrow().is_some() {
+                    self.stop_generator();
+                } else {
+                    let address = self.config.borrow().generator.address.clone();
+                    self.start_generator(&address);
                 }
             }
-        });
+            TrayEvent::SelectWindow(area) => {
+                let window = area.map(|percent| {
+                    // Keep the placement and background of the current window
+                    let current = self.overlay.borrow().window.unwrap_or_default();
+                    PatchWindow {
+                        size: PatchSize::Area(percent),
+                        ..current
+                    }
+                });
+                self.set_window(window);
+            }
+            TrayEvent::Exit => self.quit(),
+        }
+    }
+
+    /// Command-line color, pattern and monitors take precedence over the
+    /// config and `--profile`.
+    fn apply_overrides(&self, options: &Options) {
+        let mut overlay = self.overlay.borrow_mut();
+        if let Some(color) = options.color {
+            overlay.color = color;
+        }
+        if let Some(pattern) = options.pattern {
+            overlay.pattern = pattern;
+        }
+        if !options.monitors.is_empty() {
+            overlay.monitors = MonitorSelection {
+                include: options.monitors.clone(),
+                exclude: Vec::new(),
+            };
+        }
+        let window = cli::patch_window(options);
+        if window.is_some() {
+            overlay.window = window;
+        }
 
-        // Message loop with hotkey handling
-        let windows_for_hotkey = Rc::clone(&self.windows);
-        let color_for_hotkey = Rc::clone(&self.color);
-        let visible_for_hotkey = Rc::clone(&self.visible);
+        let overridden = options.color.is_some()
+            || options.pattern.is_some()
+            || !options.monitors.is_empty()
+            || window.is_some();
+        if overridden {
+            *self.active_profile.borrow_mut() = None;
+        }
+    }
 
-        message_loop(windows_for_hotkey, color_for_hotkey, visible_for_hotkey);
+    fn quit(&self) {
+        self.end_sequence();
+        self.unregister_leaders();
+        unsafe {
+            PostQuitMessage(0);
+        }
     }
-}
 
-fn toggle(
-    windows: &Rc<RefCell<Vec<ColorWindow>>>,
-    color: &Rc<RefCell<u32>>,
-    visible: &Rc<RefCell<bool>>,
-) {
-    let is_visible = *visible.borrow();
-    if is_visible {
-        hide_all(windows, visible);
-    } else {
-        show_all(windows, color, visible);
+    fn schedule_reload(&self) {
+        unsafe {
+            // Passing the pending timer's id restarts it instead of adding one
+            let timer = SetTimer(
+                null_mut(),
+                self.reload_timer.get(),
+                RELOAD_DELAY.as_millis() as u32,
+                None,
+            );
+            self.reload_timer.set(timer);
+        }
     }
-}
 
-fn show_all(
-    windows: &Rc<RefCell<Vec<ColorWindow>>>,
-    color: &Rc<RefCell<u32>>,
-    visible: &Rc<RefCell<bool>>,
-) {
-    let current_color = *color.borrow();
-    let monitors = enumerate_monitors();
-    let mut wins = windows.borrow_mut();
+    /// Re-reads the config file. Invalid edits are reported and ignored so
+    /// the last good config stays in effect.
+    fn reload_config(&self) {
+        let config = match Config::read() {
+            Ok(Some(config)) => {
+                self.config_fallback.set(false);
+                config
+            }
+            // Deleted, or missing for a moment while being replaced: keep the
+            // last good settings instead of falling back to defaults
+            Ok(None) => return,
+            Err(err) => {
+                self.notify("Config not applied", &err.to_string());
+                return;
+            }
+        };
+
+        let changes = self.config.borrow().changes(&config);
+        if changes.is_empty() {
+            return;
+        }
+
+        self.apply_config(config);
+        self.notify(
+            "Config reloaded",
+            &format!("Updated {}.", changes.join(", ")),
+        );
+    }
+
+    fn apply_config(&self, config: Config) {
+        let old = self.config.replace(config);
+        let config = self.config.borrow();
+
+        if old.keys_changed(&config) {
+            self.end_sequence();
+            self.unregister_leaders();
+            *self.keymap.borrow_mut() = config.keymap();
+            *self.chord.borrow_mut() = ChordState::new(config.chord_timeout);
+            self.register_leaders();
+            update_hotkey_display(&config.hotkey.display());
+        }
+
+        if old.color != config.color {
+            self.set_color(config.color);
+        }
+        if old.opacity != config.opacity {
+            self.set_opacity(config.opacity);
+        }
+
+        let reshow = old.monitors != config.monitors && self.visible.get();
+        if old.monitors != config.monitors {
+            self.overlay.borrow_mut().monitors = config.monitors.clone();
+        }
+        if old.window != config.window {
+            self.overlay.borrow_mut().window = config.window;
+            for window in self.windows.borrow().iter() {
+                window.set_window(config.window);
+            }
+            update_window(config.window.map(|w| w.size));
+        }
+
+        if old.profiles != config.profiles {
+            let mut active = self.active_profile.borrow_mut();
+            if active
+                .as_deref()
+                .is_some_and(|name| config.profile(name).is_none())
+            {
+                *active = None;
+            }
+        }
+        let guard_changed = old.auto_hide != config.auto_hide
+            || old.failsafe != config.failsafe
+            || old.countdown != config.countdown;
+        let sequences_changed = old.sequences != config.sequences;
+        let schedule_changed = old.schedule != config.schedule;
+        let idle_changed = old.idle != config.idle;
+        let keep_awake_changed = old.keep_awake != config.keep_awake;
+        let dimmer_changed = old.dimmer != config.dimmer;
+        if dimmer_changed {
+            self.dimmer_level.set(config.dimmer.level);
+        }
+        let playing_removed = self
+            .player
+            .borrow()
+            .as_ref()
+            .is_some_and(|p| config.sequence(p.name()).is_none());
+
+        let http_changed = old.http != config.http;
+        let generator_changed = old.generator != config.generator;
+        let generator = config.generator.clone();
+        drop(config);
+        self.update_profile_menu();
+        self.update_color_menu();
+        if playing_removed {
+            self.stop_sequence();
+        }
+        if sequences_changed {
+            self.update_sequence_menu();
+        }
+        if guard_changed {
+            self.restart_guard();
+        }
+        if schedule_changed {
+            self.arm_schedule_timer();
+            self.check_schedule(true);
+        }
+        if dimmer_changed && !self.dimmer.borrow().is_empty() {
+            self.show_dimmer();
+        }
+        if keep_awake_changed {
+            self.update_keep_awake();
+        }
+        if idle_changed {
+            self.arm_idle_timer();
+            if self.before_idle.borrow().is_some() {
+                self.hide_all();
+            }
+        }
+        if http_changed {
+            self.restart_http();
+        }
+        if generator_changed {
+            self.stop_generator();
+            if generator.enabled {
+                self.start_generator(&generator.address);
+            }
+        }
+        if reshow {
+            self.show_all();
+        }
+    }
 
-    // Clear existing windows and recreate for current monitor configuration
-    wins.clear();
-    for monitor in &monitors {
-        if let Some(window) = ColorWindow::new(monitor, current_color) {
-            wins.push(window);
+    /// Writes `config` unless it stands in for a file that failed to load.
+    fn save_config(&self, config: &Config) {
+        if self.config_fallback.get() {
+            self.notify(
+                "Settings not saved",
+                "The config file has errors. Fix it to save changes again.",
+            );
+            return;
         }
+        let _ = config.save();
     }
 
-    for window in wins.iter() {
-        window.show();
+    fn notify(&self, title: &str, text: &str) {
+        if let Some(tray) = self.tray.borrow().as_ref() {
+            tray.notify(title, text);
+        }
     }
 
-    *visible.borrow_mut() = true;
-}
+    fn perform(&self, action: Action) {
+        match action {
+            Action::Toggle => self.toggle(),
+            Action::Show => {
+                if !self.visible.get() {
+                    self.show_all();
+                }
+            }
+            Action::Hide => self.hide_all(),
+            Action::Color(color) => self.pick_color(color),
+            Action::Preset(index) => {
+                let preset = self.config.borrow().presets.get(index).cloned();
+                if let Some(preset) = preset {
+                    self.pick_color(preset.color);
+                }
+            }
+            Action::Profile(name) => self.apply_profile(&name),
+            Action::Dimmer => self.toggle_dimmer(),
+            Action::DimmerStep(steps) => self.step_dimmer(steps),
+            Action::Quit => self.quit(),
+        }
+    }
 
-fn hide_all(windows: &Rc<RefCell<Vec<ColorWindow>>>, visible: &Rc<RefCell<bool>>) {
-    for window in windows.borrow().iter() {
-        window.hide();
+    /// Actions sent by a second launch of blanqr.
+    fn on_forwarded(&self, actions: Vec<Action>) {
+        if actions.is_empty() {
+            self.notify("Blanqr", "Blanqr is already running.");
+        }
+        for action in actions {
+            self.perform(action);
+        }
     }
-    *visible.borrow_mut() = false;
-}
 
-fn update_color(windows: &Rc<RefCell<Vec<ColorWindow>>>, color: u32) {
-    for window in windows.borrow().iter() {
-        window.set_color(color);
+    /// Answers requests queued by the control server.
+    fn on_control(&self) {
+        let requests = match self.control.borrow().as_ref() {
+            Some(control) => control.take(),
+            None => return,
+        };
+        for (client, request) in requests {
+            self.handle_request(&client, request);
+        }
     }
-}
 
-fn message_loop(
-    windows: Rc<RefCell<Vec<ColorWindow>>>,
-    color: Rc<RefCell<u32>>,
-    visible: Rc<RefCell<bool>>,
-) {
-    unsafe {
-        let mut msg: MSG = zeroed();
-        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
-            if msg.message == WM_HOTKEY && msg.wParam == HOTKEY_TOGGLE as usize {
-                toggle(&windows, &color, &visible);
+    fn handle_request(&self, client: &Arc<Client>, request: Request) {
+        let reply = match request.command {
+            Command::Subscribe(events) => {
+                client.subscribe(&events);
+                Reply::Done
+            }
+            Command::Unsubscribe(events) => {
+                client.unsubscribe(&events);
+                Reply::Done
+            }
+            command => self.execute(command),
+        };
+        client.send(&protocol::encode_response(request.id.as_ref(), Ok(reply)));
+    }
+
+    /// Answers commands queued by the HTTP server.
+    fn on_http(&self) {
+        let commands = match self.http.borrow().as_ref() {
+            Some(http) => http.take(),
+            None => return,
+        };
+        for (command, reply) in commands {
+            // The connection may have timed out meanwhile
+            let _ = reply.send(self.execute(command));
+        }
+    }
+
+    /// Runs a control command from either transport.
+    fn execute(&self, command: Command) -> Reply {
+        match command {
+            Command::Show => self.perform(Action::Show),
+            Command::Hide => self.perform(Action::Hide),
+            Command::Toggle => self.perform(Action::Toggle),
+            Command::SetColor(color) => self.set_color(color),
+            Command::SetPattern(pattern) => self.set_pattern(pattern),
+            Command::ListMonitors => {
+                return Reply::Monitors(enumerate_monitors().iter().map(Into::into).collect())
+            }
+            Command::GetState => return Reply::State(self.state()),
+            // Subscriptions belong to a pipe connection; see handle_request
+            Command::Subscribe(_) | Command::Unsubscribe(_) => {}
+        }
+        Reply::Done
+    }
+
+    /// Stops the HTTP API and starts it again with the current settings.
+    fn restart_http(&self) {
+        // Release the port before binding it again
+        self.http.borrow_mut().take();
+
+        let settings = self.config.borrow().http.clone();
+        if !settings.enabled {
+            return;
+        }
+        match HttpServer::start(settings.port, &settings.token) {
+            Ok(server) => *self.http.borrow_mut() = Some(server),
+            Err(err) => self.notify(
+                "HTTP API not started",
+                &format!("Could not listen on port {}: {}", settings.port, err),
+            ),
+        }
+    }
+
+    fn start_generator(&self, address: &str) {
+        self.generator.borrow_mut().take();
+        *self.generator.borrow_mut() = Some(Generator::start(address));
+        update_generator(true);
+        self.notify(
+            "Pattern generator",
+            &format!("Waiting for calibration software at {}.", address),
+        );
+    }
+
+    /// Ends the session and returns to the normal overlay.
+    fn stop_generator(&self) {
+        if self.generator.borrow_mut().take().is_none() {
+            return;
+        }
+        update_generator(false);
+        if self.patch.take().is_some() {
+            for window in self.windows.borrow().iter() {
+                window.set_patch(None);
+            }
+            self.hide_all();
+        }
+    }
+
+    fn on_generator(&self) {
+        let events = match self.generator.borrow().as_ref() {
+            Some(generator) => generator.take(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                GeneratorEvent::Connected => {
+                    self.notify("Pattern generator", "Connected to calibration software.")
+                }
+                GeneratorEvent::Disconnected(reason) => self.notify(
+                    "Pattern generator",
+                    &format!("Disconnected ({}). Reconnecting...", reason),
+                ),
+                GeneratorEvent::Patch(patch) => self.show_patch(patch),
+            }
+        }
+    }
+
+    fn show_patch(&self, patch: Patch) {
+        self.patch.set(Some(patch));
+        if self.visible.get() {
+            for window in self.windows.borrow().iter() {
+                window.set_patch(Some(patch));
+            }
+        } else {
+            self.show_all();
+        }
+        self.refresh_osd();
+        self.update_tray_icon();
+        self.update_keep_awake();
+    }
+
+    /// Shows the first patch of `patches`, stepping every `advance` (or the
+    /// configured interval) or with the arrow keys when that is zero.
+    fn start_patch_set(&self, patches: PatchSet, advance: Option<Duration>) {
+        self.end_patch_set();
+        self.stop_sequence();
+        let saved = (
+            self.overlay.borrow().clone(),
+            self.active_profile.borrow().clone(),
+        );
+        *self.before_session.borrow_mut() = Some(saved);
+        let color = patches.current();
+        *self.patch_set.borrow_mut() = Some(patches);
+
+        self.set_color(color);
+        if self.visible.get() {
+            self.refresh_caption();
+        } else {
+            self.show_all();
+        }
+        self.update_keep_awake();
+
+        let advance = advance.unwrap_or(self.config.borrow().patch_advance);
+        if !advance.is_zero() {
+            let ms = advance.as_millis().min(u32::MAX as u128) as u32;
+            self.advance_timer
+                .set(unsafe { SetTimer(null_mut(), 0, ms, None) });
+        }
+    }
+
+    fn step_patch_set(&self, delta: isize) {
+        let mut patch_set = self.patch_set.borrow_mut();
+        let Some(patches) = patch_set.as_mut() else {
+            return;
+        };
+        if !patches.step(delta) {
+            return;
+        }
+        let color = patches.current();
+        let finished = patches.is_last() && self.advance_timer.get() != 0;
+        let name = patches.name.clone();
+        drop(patch_set);
+
+        self.set_color(color);
+        self.refresh_caption();
+        if finished {
+            self.kill_advance_timer();
+            self.notify("Patch set", &format!("Reached the last patch of {}.", name));
+        }
+    }
+
+    fn end_patch_set(&self) {
+        if self.patch_set.borrow_mut().take().is_none() {
+            return;
+        }
+        self.kill_advance_timer();
+        self.end_session();
+        self.refresh_caption();
+        self.update_keep_awake();
+    }
+
+    /// Puts back the overlay settings a patch set or sequence replaced, on
+    /// the windows too in case the overlay stays up.
+    fn end_session(&self) {
+        let saved = self.before_session.take();
+        let Some((overlay, profile)) = saved else {
+            return;
+        };
+        self.restore_overlay(overlay, profile);
+        let displayed = self.displayed_color();
+        let pattern = self.overlay.borrow().pattern;
+        for window in self.windows.borrow().iter() {
+            window.set_color(displayed);
+            window.set_pattern(pattern);
+        }
+        self.refresh_osd();
+    }
+
+    fn kill_advance_timer(&self) {
+        let timer = self.advance_timer.replace(0);
+        if timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+        }
+    }
+
+    /// Plays the configured sequence `name` from its first step.
+    fn play_sequence(&self, name: &str) {
+        let Some(sequence) = self.config.borrow().sequence(name).cloned() else {
+            return;
+        };
+        self.stop_sequence();
+        self.end_patch_set();
+        let saved = (
+            self.overlay.borrow().clone(),
+            self.active_profile.borrow().clone(),
+        );
+        *self.before_session.borrow_mut() = Some(saved);
+        *self.player.borrow_mut() = Some(Player::start(sequence, Instant::now()));
+        self.update_sequence_menu();
+
+        self.show_step();
+        if !self.visible.get() {
+            self.show_all();
+        }
+        self.arm_player_timer();
+        self.update_keep_awake();
+        self.update_tray_icon();
+    }
+
+    fn stop_sequence(&self) {
+        if self.player.borrow_mut().take().is_none() {
+            return;
+        }
+        let timer = self.player_timer.replace(0);
+        if timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+        }
+        self.end_session();
+        self.update_sequence_menu();
+        self.refresh_caption();
+        self.update_keep_awake();
+        self.update_tray_icon();
+    }
+
+    fn show_step(&self) {
+        let Some(step) = self.player.borrow().as_ref().map(|p| p.step().clone()) else {
+            return;
+        };
+        self.set_color(step.color);
+        self.set_pattern(step.pattern);
+    }
+
+    fn on_player_timer(&self) {
+        let tick = match self.player.borrow_mut().as_mut() {
+            Some(player) => player.tick(Instant::now()),
+            None => return,
+        };
+        match tick {
+            Tick::Unchanged => self.arm_player_timer(),
+            Tick::Advanced => {
+                self.show_step();
+                self.arm_player_timer();
+            }
+            // One-shot sequences end with the overlay, like --duration
+            Tick::Finished => self.hide_all(),
+        }
+    }
+
+    /// Sets the timer for the end of the current step, or kills it while
+    /// paused.
+    fn arm_player_timer(&self) {
+        let remaining = self
+            .player
+            .borrow()
+            .as_ref()
+            .and_then(Player::deadline)
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        unsafe {
+            KillTimer(null_mut(), self.player_timer.get());
+            let timer = match remaining {
+                Some(remaining) => {
+                    let ms = remaining.as_millis().clamp(1, u32::MAX as u128) as u32;
+                    SetTimer(null_mut(), 0, ms, None)
+                }
+                None => 0,
+            };
+            self.player_timer.set(timer);
+        }
+    }
+
+    fn toggle_pause(&self) {
+        match self.player.borrow_mut().as_mut() {
+            Some(player) if player.is_paused() => player.resume(Instant::now()),
+            Some(player) => player.pause(Instant::now()),
+            None => return,
+        }
+        self.arm_player_timer();
+        self.refresh_caption();
+    }
+
+    fn update_sequence_menu(&self) {
+        let names: Vec<String> = self
+            .config
+            .borrow()
+            .sequences
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        let player = self.player.borrow();
+        update_sequences(&names, player.as_ref().map(Player::name));
+    }
+
+    /// Text in the corner of the overlay: the patch-set position or a note
+    /// that the sequence is paused, and the countdown if enabled.
+    fn caption(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(patches) = self.patch_set.borrow().as_ref() {
+            parts.push(patches.label());
+        }
+        if let Some(player) = self.player.borrow().as_ref().filter(|p| p.is_paused()) {
+            parts.push(format!("{} paused (Space to resume)", player.name()));
+        }
+        if self.readout.get() {
+            let color = self.displayed_color();
+            parts.push(format!(
+                "{}  RGB {}  {}%",
+                color::to_hex(color),
+                color::to_rgb_string(color),
+                self.brightness()
+            ));
+        }
+        if self.config.borrow().countdown {
+            let deadline = self.deadlines.get().next(self.session_active());
+            if let Some(deadline) = deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                parts.push(format!("Hides in {}", format_countdown(left)));
+            }
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("    "))
+        }
+    }
+
+    fn refresh_caption(&self) {
+        let caption = self.caption();
+        for window in self.windows.borrow().iter() {
+            window.set_caption(caption.as_deref());
+        }
+    }
+
+    /// Starts the auto-hide and failsafe clocks for a newly shown overlay.
+    fn start_guard(&self) {
+        let config = self.config.borrow();
+        let mut deadlines = self.deadlines.get();
+        deadlines.start(Instant::now(), config.auto_hide, config.failsafe);
+        self.deadlines.set(deadlines);
+        drop(config);
+        self.arm_guard_timer();
+    }
+
+    fn stop_guard(&self) {
+        self.deadlines.set(Deadlines::default());
+        self.arm_guard_timer();
+    }
+
+    /// Applies changed timeout settings to an overlay that is already up.
+    fn restart_guard(&self) {
+        let config = self.config.borrow();
+        update_auto_hide(config.auto_hide, config.countdown);
+        drop(config);
+        if self.visible.get() {
+            self.start_guard();
+        }
+        self.refresh_caption();
+    }
+
+    fn save_hide_settings(&self) {
+        self.save_config(&self.config.borrow());
+        self.restart_guard();
+    }
+
+    /// Runs the one-second timer while a visible overlay has a deadline.
+    fn arm_guard_timer(&self) {
+        let needed = self.visible.get() && !self.deadlines.get().is_empty();
+        let timer = self.guard_timer.get();
+        unsafe {
+            if needed && timer == 0 {
+                self.guard_timer
+                    .set(SetTimer(null_mut(), 0, GUARD_INTERVAL_MS, None));
+            } else if !needed && timer != 0 {
+                KillTimer(null_mut(), timer);
+                self.guard_timer.set(0);
+            }
+        }
+    }
+
+    fn on_guard_timer(&self) {
+        let (auto_hide, failsafe) = {
+            let config = self.config.borrow();
+            (config.auto_hide, config.failsafe)
+        };
+        let mut deadlines = self.deadlines.get();
+        let due = deadlines.check(Instant::now(), auto_hide, self.session_active());
+        self.deadlines.set(deadlines);
+        match due {
+            Due::Failsafe => {
+                self.hide_all();
+                self.notify(
+                    "Overlay hidden",
+                    &format!(
+                        "It was visible for the failsafe limit of {}.",
+                        cli::format_duration(failsafe.unwrap_or_default())
+                    ),
+                );
+                return;
+            }
+            Due::AutoHide => {
+                self.hide_all();
+                return;
+            }
+            Due::Nothing => {}
+        }
+        if self.config.borrow().countdown {
+            self.refresh_caption();
+        }
+    }
+
+    /// Pushes back the auto-hide deadline after input on the overlay.
+    fn touch(&self) {
+        let mut deadlines = self.deadlines.get();
+        deadlines.touch(Instant::now(), self.config.borrow().auto_hide);
+        self.deadlines.set(deadlines);
+    }
+
+    /// Whether a patch set, sequence or pattern generator is driving the
+    /// overlay.
+    fn session_active(&self) -> bool {
+        self.patch_set.borrow().is_some()
+            || self.player.borrow().is_some()
+            || self.patch.get().is_some()
+    }
+
+    /// Keys pressed on an overlay window, other than Escape.
+    fn on_key(&self, key: u32) {
+        self.touch();
+        let ctrl = unsafe { GetKeyState(VK_CONTROL as i32) } < 0;
+        match key as u16 {
+            VK_SPACE => return self.toggle_pause(),
+            VK_OEM_PLUS | VK_ADD => return self.step_brightness(1),
+            VK_OEM_MINUS | VK_SUBTRACT => return self.step_brightness(-1),
+            key if key == b'C' as u16 && ctrl => return self.copy_color(),
+            key if key == b'I' as u16 && !ctrl => return self.toggle_osd(),
+            _ => {}
+        }
+        let delta = match key as u16 {
+            VK_RIGHT | VK_DOWN | VK_NEXT => 1,
+            VK_LEFT | VK_UP | VK_PRIOR => -1,
+            VK_HOME => isize::MIN,
+            VK_END => isize::MAX,
+            _ => return,
+        };
+        self.step_patch_set(delta);
+    }
+
+    fn on_wheel(&self, notches: i32) {
+        self.touch();
+        self.step_brightness(notches);
+    }
+
+    /// Scales the overlay brightness up or down in linear light and shows
+    /// the resulting color values.
+    fn step_brightness(&self, steps: i32) {
+        let fine = unsafe { GetKeyState(VK_SHIFT as i32) } < 0;
+        let step = if fine { 1 } else { BRIGHTNESS_STEP };
+        let brightness = self.overlay.borrow().brightness as i32;
+        let brightness = (brightness + steps * step).clamp(1, 100) as u8;
+
+        self.overlay.borrow_mut().brightness = brightness;
+        *self.active_profile.borrow_mut() = None;
+        self.update_profile_menu();
+        self.readout.set(true);
+
+        let displayed = self.displayed_color();
+        for window in self.windows.borrow().iter() {
+            window.set_color(displayed);
+        }
+        self.refresh_caption();
+        self.refresh_osd();
+        self.update_tray_icon();
+    }
+
+    fn toggle_osd(&self) {
+        self.osd.set(!self.osd.get());
+        self.refresh_osd();
+    }
+
+    /// What the OSD lists above each window's monitor.
+    fn osd_lines(&self) -> Vec<String> {
+        let overlay = self.overlay.borrow();
+        let mut lines = Vec::new();
+        match self.patch.get() {
+            Some(patch) => {
+                lines.push(format!(
+                    "{}  RGB {}",
+                    color::to_hex(patch.foreground),
+                    color::to_rgb_string(patch.foreground)
+                ));
+                lines.push("Pattern generator".to_string());
+            }
+            None => {
+                let color = self.displayed_color();
+                let mut line = format!(
+                    "{}  RGB {}",
+                    color::to_hex(color),
+                    color::to_rgb_string(color)
+                );
+                let brightness = self.brightness();
+                if brightness < 100 {
+                    line += &format!("  {}%", brightness);
+                }
+                lines.push(line);
+                let mut pattern = format!("Pattern: {}", overlay.pattern.name());
+                if let Some(window) = overlay.window {
+                    pattern += &format!("  Window: {}", window.size.display());
+                }
+                lines.push(pattern);
+            }
+        }
+        if let Some(patches) = self.patch_set.borrow().as_ref() {
+            lines.push(format!("{} {}", patches.name, patches.label()));
+        }
+        if let Some(player) = self.player.borrow().as_ref() {
+            lines.push(player.label());
+        }
+        lines
+    }
+
+    /// Redraws the OSD after a change and restarts its fade-out.
+    fn refresh_osd(&self) {
+        if !self.osd.get() {
+            self.osd_fade.set(None);
+            for window in self.windows.borrow().iter() {
+                window.set_osd(None);
+            }
+            return;
+        }
+        if self.windows.borrow().is_empty() {
+            return;
+        }
+        let lines = self.osd_lines();
+        for window in self.windows.borrow().iter() {
+            window.set_osd(Some(&lines));
+            window.set_osd_level(1.0);
+        }
+        let now = Instant::now();
+        self.osd_fade
+            .set(Some(Fade::new(1.0, 0.0, OSD_FADE, now + OSD_HOLD)));
+        self.arm_fade_timer();
+    }
+
+    /// Shows the displayed color and what is running in the tray icon.
+    fn update_tray_icon(&self) {
+        let color = match self.patch.get() {
+            Some(patch) => patch.foreground,
+            None => self.displayed_color(),
+        };
+        let badge = if self.player.borrow().is_some() {
+            Badge::Playing
+        } else if self.visible.get() {
+            Badge::Visible
+        } else {
+            Badge::None
+        };
+        if let Some(tray) = self.tray.borrow().as_ref() {
+            tray.set_state(color, badge);
+        }
+    }
+
+    /// Puts the displayed color on the clipboard as `#RRGGBB`.
+    fn copy_color(&self) {
+        let hex = color::to_hex(self.displayed_color());
+        if clipboard::copy_text(&hex) {
+            self.notify("Color copied", &format!("Copied {} to the clipboard.", hex));
+        }
+    }
+
+    /// Runs the schedule timer while there are rules to check.
+    fn arm_schedule_timer(&self) {
+        let wanted = !self.config.borrow().schedule.is_empty();
+        let timer = self.schedule_timer.get();
+        if wanted && timer == 0 {
+            self.schedule_timer
+                .set(unsafe { SetTimer(null_mut(), 0, SCHEDULE_INTERVAL_MS, None) });
+        } else if !wanted && timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+            self.schedule_timer.set(0);
+        }
+    }
+
+    /// Starts or ends schedule rules when the one in charge changes. Only
+    /// changes act, so hiding the overlay by hand lasts until the next one.
+    /// `force` re-applies the current rule after the schedule was edited.
+    fn check_schedule(&self, force: bool) {
+        let active = schedule::active_rule(&self.config.borrow().schedule, LocalTime::now());
+        if active == self.scheduled.get() && !force {
+            return;
+        }
+        self.end_idle();
+
+        if self.scheduled.replace(active).is_some() {
+            if let Some((overlay, profile)) = self.before_schedule.take() {
+                self.restore_overlay(overlay, profile);
+            }
+            if active.is_none() && self.visible.get() {
+                self.hide_all();
+            }
+        }
+
+        if let Some(index) = active {
+            self.start_rule(index);
+        }
+    }
+
+    fn start_rule(&self, index: usize) {
+        let rule = self.config.borrow().schedule[index].clone();
+        let saved = (
+            self.overlay.borrow().clone(),
+            self.active_profile.borrow().clone(),
+        );
+        *self.before_schedule.borrow_mut() = Some(saved);
+
+        match &rule.profile {
+            Some(name) => self.apply_profile(name),
+            None => self.set_color(rule.color.unwrap_or(0x00000000)),
+        }
+        if !rule.monitors.is_empty() {
+            self.overlay.borrow_mut().monitors = MonitorSelection {
+                include: rule.monitors,
+                exclude: Vec::new(),
+            };
+        }
+        self.show_all();
+    }
+
+    /// Polls the idle time while the idle overlay is enabled.
+    fn arm_idle_timer(&self) {
+        let wanted = self.config.borrow().idle.after.is_some();
+        let timer = self.idle_timer.get();
+        if wanted && timer == 0 {
+            self.idle_timer
+                .set(unsafe { SetTimer(null_mut(), 0, IDLE_INTERVAL_MS, None) });
+        } else if !wanted && timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+            self.idle_timer.set(0);
+        }
+    }
+
+    /// Shows the overlay once the user has been away long enough and hides
+    /// it on the next input. An overlay that is already up is left alone.
+    fn on_idle_timer(&self) {
+        let Some(after) = self.config.borrow().idle.after else {
+            return;
+        };
+        let showing = self.before_idle.borrow().is_some();
+        match idle_step(self.idle.idle_time(), after, showing, self.visible.get()) {
+            IdleStep::Wait => {}
+            IdleStep::Hide => self.hide_all(),
+            IdleStep::Show => self.show_idle(),
+        }
+    }
+
+    /// Shows the overlay in the idle color, saving the settings it replaces.
+    fn show_idle(&self) {
+        let color = self.config.borrow().idle.color;
+        let saved = (
+            self.overlay.borrow().clone(),
+            self.active_profile.borrow().clone(),
+        );
+        *self.before_idle.borrow_mut() = Some(saved);
+        self.set_color(color);
+        self.show_all();
+    }
+
+    /// Holds off display sleep as `keep_awake` asks for the current state.
+    fn update_keep_awake(&self) {
+        let overlay = self.visible.get() && self.before_idle.borrow().is_none();
+        let wanted = match self.config.borrow().keep_awake {
+            KeepAwake::Off => false,
+            KeepAwake::Overlay => overlay,
+            KeepAwake::Sessions => overlay || self.session_active(),
+        };
+        if self.awake.replace(wanted) != wanted {
+            keep_display_on(wanted);
+        }
+    }
+
+    /// Puts back the overlay settings the idle overlay replaced.
+    fn end_idle(&self) {
+        let saved = self.before_idle.take();
+        if let Some((overlay, profile)) = saved {
+            self.restore_overlay(overlay, profile);
+        }
+    }
+
+    fn restore_overlay(&self, overlay: OverlaySettings, profile: Option<String>) {
+        let old_color = self.overlay.replace(overlay).color;
+        *self.active_profile.borrow_mut() = profile;
+        self.update_profile_menu();
+        update_window(self.overlay.borrow().window.map(|w| w.size));
+        self.update_tray_icon();
+        self.update_color_menu();
+
+        let color = self.overlay.borrow().color;
+        if color != old_color {
+            self.publish(Event::ColorChanged(color));
+        }
+    }
+
+    fn state(&self) -> State {
+        let overlay = self.overlay.borrow();
+        State {
+            visible: self.visible.get(),
+            color: overlay.color,
+            pattern: overlay.pattern,
+            brightness: overlay.brightness,
+            profile: self.active_profile.borrow().clone(),
+        }
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some(control) = self.control.borrow().as_ref() {
+            control.publish(event);
+        }
+    }
+
+    fn on_hide_trigger(&self, trigger: HideTrigger) {
+        let dismissal = self.overlay.borrow().dismissal;
+        let hide = match trigger {
+            HideTrigger::Click => dismissal.on_click,
+            HideTrigger::Escape => dismissal.on_escape,
+        };
+        if hide {
+            self.hide_all();
+        }
+    }
+
+    fn apply_profile(&self, name: &str) {
+        let Some(profile) = self.config.borrow().profile(name).cloned() else {
+            return;
+        };
+
+        let old_color = self.overlay.replace(profile.settings).color;
+        *self.active_profile.borrow_mut() = Some(profile.name);
+        self.update_profile_menu();
+        update_window(self.overlay.borrow().window.map(|w| w.size));
+        self.update_tray_icon();
+        self.update_color_menu();
+
+        let color = self.overlay.borrow().color;
+        if color != old_color {
+            self.publish(Event::ColorChanged(color));
+        }
+
+        // Monitor selection may differ, so rebuild rather than repaint
+        if self.visible.get() {
+            self.show_all();
+        }
+    }
+
+    fn save_profile(&self) {
+        let suggestion = self.active_profile.borrow().clone().unwrap_or_default();
+        let Some(name) = show_input_dialog("Save Profile", &suggestion) else {
+            return;
+        };
+
+        let profile = Profile {
+            name: name.clone(),
+            settings: self.overlay.borrow().clone(),
+        };
+
+        let mut config = self.config.borrow_mut();
+        match config.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => config.profiles.push(profile),
+        }
+        self.save_config(&config);
+        drop(config);
+
+        *self.active_profile.borrow_mut() = Some(name);
+        self.update_profile_menu();
+    }
+
+    fn update_profile_menu(&self) {
+        let names: Vec<String> = self
+            .config
+            .borrow()
+            .profiles
+            .iter()
+            .map(|p| p.name.clone())
+            .collect();
+        update_profiles(&names, self.active_profile.borrow().as_deref());
+    }
+
+    /// The overlay color after brightness scaling.
+    fn displayed_color(&self) -> u32 {
+        let color = self.overlay.borrow().color;
+        match self.brightness() {
+            100.. => color,
+            brightness => color::scale_brightness(color, brightness as f64 / 100.0),
+        }
+    }
+
+    /// The brightness in effect. Patch sets and sequences are for measuring
+    /// and testing, so their colors are shown exactly as given.
+    fn brightness(&self) -> u8 {
+        if self.patch_set.borrow().is_some() || self.player.borrow().is_some() {
+            100
+        } else {
+            self.overlay.borrow().brightness
+        }
+    }
+
+    fn toggle(&self) {
+        if self.visible.get() {
+            self.hide_all();
+        } else {
+            self.show_all();
+        }
+    }
+
+    fn show_all(&self) {
+        self.finish_fade_out();
+        if !self.visible.get() {
+            let duration = self.config.borrow().fade_in;
+            if !duration.is_zero() {
+                let fade = Fade::new(0.0, self.opacity(), duration, Instant::now());
+                *self.fade_in.borrow_mut() = Some(fade);
+            }
+        }
+        let alpha = fade::alpha(self.current_opacity());
+
+        let current_color = self.displayed_color();
+        let overlay = self.overlay.borrow().clone();
+        let monitors = enumerate_monitors();
+        let mut wins = self.windows.borrow_mut();
+
+        // Recreate the windows for the current monitor configuration. This
+        // also runs while the overlay is up; hiding the old windows first
+        // gives back the cursor they hid.
+        for window in wins.drain(..) {
+            window.hide();
+        }
+        for monitor in monitors.iter().filter(|m| overlay.monitors.selects(m)) {
+            if let Some(window) = ColorWindow::new(monitor, current_color, overlay.pattern) {
+                window.set_window(overlay.window);
+                window.set_caption(self.caption().as_deref());
+                window.set_patch(self.patch.get());
+                window.set_alpha(alpha);
+                wins.push(window);
+            }
+        }
+
+        for window in wins.iter() {
+            window.show();
+        }
+        drop(wins);
+        self.refresh_osd();
+        self.update_tray_icon();
+        self.arm_fade_timer();
+
+        if !self.visible.replace(true) {
+            self.publish(Event::Shown);
+            self.start_guard();
+        }
+        self.update_keep_awake();
+    }
+
+    fn hide_all(&self) {
+        let from = self.current_opacity();
+        self.fade_in.take();
+        let duration = self.config.borrow().fade_out;
+        if self.visible.get() && !duration.is_zero() {
+            self.finish_fade_out();
+            let windows = self.windows.take();
+            let fade = Fade::new(from, 0.0, duration, Instant::now());
+            *self.fade_out.borrow_mut() = Some((fade, windows));
+            self.arm_fade_timer();
+        } else {
+            for window in self.windows.borrow().iter() {
+                window.hide();
+            }
+        }
+        self.end_patch_set();
+        self.stop_sequence();
+        if self.visible.replace(false) {
+            self.publish(Event::Hidden);
+        }
+        self.stop_guard();
+        self.readout.set(false);
+        self.osd_fade.set(None);
+        self.end_idle();
+        self.update_keep_awake();
+        self.update_tray_icon();
+
+        let timer = self.hide_timer.replace(0);
+        if timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+        }
+        if self.exit_on_hide.get() {
+            self.quit();
+        }
+    }
+
+    fn toggle_dimmer(&self) {
+        if self.dimmer.borrow().is_empty() {
+            self.show_dimmer();
+        } else {
+            for window in self.dimmer.take() {
+                window.hide();
+            }
+            update_dimmer(false);
+        }
+    }
+
+    /// Covers the dimmer's monitors with click-through windows, replacing
+    /// any already up.
+    fn show_dimmer(&self) {
+        let settings = self.config.borrow().dimmer.clone();
+        let selection = if settings.monitors.is_empty() {
+            self.overlay.borrow().monitors.clone()
+        } else {
+            MonitorSelection {
+                include: settings.monitors,
+                exclude: Vec::new(),
+            }
+        };
+        let alpha = fade::alpha(self.dimmer_level.get() as f64 / 100.0);
+
+        let mut windows = self.dimmer.borrow_mut();
+        windows.clear();
+        for monitor in enumerate_monitors().iter().filter(|m| selection.selects(m)) {
+            if let Some(window) = ColorWindow::new(monitor, settings.color, Pattern::Solid) {
+                window.set_click_through();
+                window.set_alpha(alpha);
+                window.show();
+                windows.push(window);
+            }
+        }
+        update_dimmer(true);
+    }
+
+    /// Makes the dimmer darker or lighter, turning it on if it is off.
+    fn step_dimmer(&self, steps: i8) {
+        let level = self
+            .config
+            .borrow()
+            .dimmer
+            .step_level(self.dimmer_level.get(), steps);
+        self.dimmer_level.set(level);
+
+        if self.dimmer.borrow().is_empty() {
+            self.show_dimmer();
+            return;
+        }
+        let alpha = fade::alpha(level as f64 / 100.0);
+        for window in self.dimmer.borrow().iter() {
+            window.set_alpha(alpha);
+        }
+    }
+
+    fn set_opacity(&self, opacity: u8) {
+        self.overlay.borrow_mut().opacity = opacity;
+        if self.fade_in.borrow().is_some() {
+            // Picked up by the fade's last frame
+            return;
+        }
+        let alpha = fade::alpha(self.opacity());
+        for window in self.windows.borrow().iter() {
+            window.set_alpha(alpha);
+        }
+    }
+
+    /// The overlay opacity setting, 0.0-1.0.
+    fn opacity(&self) -> f64 {
+        self.overlay.borrow().opacity as f64 / 100.0
+    }
+
+    /// Opacity of the visible windows, partway through a fade-in.
+    fn current_opacity(&self) -> f64 {
+        match self.fade_in.borrow().as_ref() {
+            Some(fade) => fade.value(Instant::now()),
+            None => self.opacity(),
+        }
+    }
+
+    fn arm_fade_timer(&self) {
+        let wanted = self.fade_in.borrow().is_some()
+            || self.fade_out.borrow().is_some()
+            || self.osd_fade.get().is_some();
+        let timer = self.fade_timer.get();
+        if wanted && timer == 0 {
+            self.fade_timer
+                .set(unsafe { SetTimer(null_mut(), 0, FADE_FRAME_MS, None) });
+        } else if !wanted && timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+            self.fade_timer.set(0);
+        }
+    }
+
+    fn on_fade_timer(&self) {
+        let now = Instant::now();
+        let fade_in = *self.fade_in.borrow();
+        if let Some(fade) = fade_in {
+            let opacity = if fade.is_done(now) {
+                self.fade_in.take();
+                self.opacity()
             } else {
-                TranslateMessage(&msg);
-                DispatchMessageW(&msg);
+                fade.value(now)
+            };
+            for window in self.windows.borrow().iter() {
+                window.set_alpha(fade::alpha(opacity));
+            }
+        }
+
+        let done = match self.fade_out.borrow().as_ref() {
+            Some((fade, windows)) => {
+                let alpha = fade::alpha(fade.value(now));
+                for window in windows {
+                    window.set_alpha(alpha);
+                }
+                fade.is_done(now)
             }
+            None => false,
+        };
+        if done {
+            self.finish_fade_out();
         }
+
+        if let Some(fade) = self.osd_fade.get() {
+            if fade.is_done(now) {
+                self.osd_fade.set(None);
+                for window in self.windows.borrow().iter() {
+                    window.set_osd(None);
+                }
+            } else if fade.value(now) < 1.0 {
+                for window in self.windows.borrow().iter() {
+                    window.set_osd_level(fade.value(now));
+                }
+            }
+        }
+        self.arm_fade_timer();
+    }
+
+    /// Hides and drops the windows of a fade-out still running.
+    fn finish_fade_out(&self) {
+        let fade_out = self.fade_out.take();
+        if let Some((_, windows)) = fade_out {
+            for window in &windows {
+                window.hide();
+            }
+        }
+    }
+
+    fn set_color(&self, color: u32) {
+        let old = std::mem::replace(&mut self.overlay.borrow_mut().color, color);
+        *self.active_profile.borrow_mut() = None;
+        self.update_profile_menu();
+
+        let displayed = self.displayed_color();
+        for window in self.windows.borrow().iter() {
+            window.set_color(displayed);
+        }
+
+        self.refresh_osd();
+        self.update_tray_icon();
+        self.update_color_menu();
+
+        if old != color {
+            self.publish(Event::ColorChanged(color));
+        }
+    }
+
+    /// Appends the presets that aren't in the list yet, as many as the
+    /// Colors menu holds, and saves them.
+    fn import_presets(&self, presets: Vec<Preset>) {
+        let mut config = self.config.borrow_mut();
+        let merged = palette::merge(&mut config.presets, presets, MAX_PRESETS);
+        if merged.added > 0 {
+            self.save_config(&config);
+        }
+        drop(config);
+        self.update_color_menu();
+        self.notify("Palette", &merged.message(MAX_PRESETS));
     }
+
+    /// Sets a color the user chose and adds it to the recent colors.
+    fn pick_color(&self, color: u32) {
+        self.set_color(color);
+        let mut config = self.config.borrow_mut();
+        if config.remember_color(color) {
+            self.save_config(&config);
+        }
+        drop(config);
+        self.update_color_menu();
+    }
+
+    fn update_color_menu(&self) {
+        let config = self.config.borrow();
+        let presets: Vec<(String, u32)> = config
+            .presets
+            .iter()
+            .map(|p| (p.name.clone(), p.color))
+            .collect();
+        update_colors(&presets, &config.recent, self.overlay.borrow().color);
+    }
+
+    fn set_pattern(&self, pattern: Pattern) {
+        self.overlay.borrow_mut().pattern = pattern;
+        *self.active_profile.borrow_mut() = None;
+        self.update_profile_menu();
+
+        for window in self.windows.borrow().iter() {
+            window.set_pattern(pattern);
+        }
+        self.refresh_osd();
+    }
+
+    fn set_window(&self, window: Option<PatchWindow>) {
+        self.overlay.borrow_mut().window = window;
+        *self.active_profile.borrow_mut() = None;
+        self.update_profile_menu();
+        update_window(window.map(|w| w.size));
+
+        for color_window in self.windows.borrow().iter() {
+            color_window.set_window(window);
+        }
+        self.refresh_osd();
+    }
+
+    fn register_leaders(&self) {
+        let leaders = self.keymap.borrow().leaders();
+        for (i, chord) in leaders.iter().enumerate() {
+            unsafe {
+                RegisterHotKey(
+                    null_mut(),
+                    HOTKEY_LEADER_BASE + i as i32,
+                    chord.modifiers,
+                    chord.key,
+                );
+            }
+        }
+        *self.leaders.borrow_mut() = leaders;
+    }
+
+    fn unregister_leaders(&self) {
+        let mut leaders = self.leaders.borrow_mut();
+        for i in 0..leaders.len() {
+            unsafe {
+                UnregisterHotKey(null_mut(), HOTKEY_LEADER_BASE + i as i32);
+            }
+        }
+        leaders.clear();
+    }
+
+    fn warn_conflicts(&self) {
+        let problems: Vec<String> = self
+            .keymap
+            .borrow()
+            .conflicts()
+            .iter()
+            .filter(|c| c.is_error())
+            .map(|c| c.describe())
+            .collect();
+        if problems.is_empty() {
+            return;
+        }
+
+        let title = wide_str("Blanqr");
+        let text = wide_str(&format!(
+            "Key binding conflicts:\n\n{}",
+            problems.join("\n")
+        ));
+        unsafe {
+            MessageBoxW(
+                null_mut(),
+                text.as_ptr(),
+                title.as_ptr(),
+                MB_OK | MB_ICONWARNING,
+            );
+        }
+    }
+
+    fn chord_for_id(&self, id: i32) -> Option<HotkeyConfig> {
+        if id >= HOTKEY_FOLLOWER_BASE {
+            let index = (id - HOTKEY_FOLLOWER_BASE) as usize;
+            self.followers.borrow().get(index).cloned()
+        } else {
+            let index = (id - HOTKEY_LEADER_BASE) as usize;
+            self.leaders.borrow().get(index).cloned()
+        }
+    }
+
+    fn on_hotkey(&self, id: i32) {
+        let Some(chord) = self.chord_for_id(id) else {
+            return;
+        };
+
+        let result = self
+            .chord
+            .borrow_mut()
+            .feed(&self.keymap.borrow(), chord, Instant::now());
+
+        match result {
+            ChordResult::Pending(next) => self.await_followers(next),
+            ChordResult::Matched(action) => {
+                self.end_sequence();
+                self.perform(action);
+            }
+            ChordResult::Cancelled => self.end_sequence(),
+        }
+    }
+
+    fn on_chord_timeout(&self) {
+        let action = self
+            .chord
+            .borrow_mut()
+            .expire(&self.keymap.borrow(), Instant::now());
+        self.end_sequence();
+        if let Some(action) = action {
+            self.perform(action);
+        }
+    }
+
+    /// Registers the chords that may continue the pending sequence and arms
+    /// the timeout. Chords that are also leaders are already registered.
+    fn await_followers(&self, next: Vec<HotkeyConfig>) {
+        self.unregister_followers();
+
+        let leaders = self.leaders.borrow();
+        let mut followers = self.followers.borrow_mut();
+        for chord in next.into_iter().filter(|c| !leaders.contains(c)) {
+            unsafe {
+                RegisterHotKey(
+                    null_mut(),
+                    HOTKEY_FOLLOWER_BASE + followers.len() as i32,
+                    chord.modifiers,
+                    chord.key,
+                );
+            }
+            followers.push(chord);
+        }
+        drop(followers);
+        drop(leaders);
+
+        let remaining = self
+            .chord
+            .borrow()
+            .deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+        unsafe {
+            KillTimer(null_mut(), self.chord_timer.get());
+            let timer = SetTimer(null_mut(), 0, remaining.as_millis() as u32, None);
+            self.chord_timer.set(timer);
+        }
+    }
+
+    fn unregister_followers(&self) {
+        let mut followers = self.followers.borrow_mut();
+        for i in 0..followers.len() {
+            unsafe {
+                UnregisterHotKey(null_mut(), HOTKEY_FOLLOWER_BASE + i as i32);
+            }
+        }
+        followers.clear();
+    }
+
+    fn end_sequence(&self) {
+        self.chord.borrow_mut().reset();
+        self.unregister_followers();
+        let timer = self.chord_timer.replace(0);
+        if timer != 0 {
+            unsafe {
+                KillTimer(null_mut(), timer);
+            }
+        }
+    }
+
+    fn on_thread_timer(&self, id: usize) {
+        if id == 0 {
+            return;
+        }
+        if id == self.chord_timer.get() {
+            self.on_chord_timeout();
+        } else if id == self.reload_timer.get() {
+            unsafe {
+                KillTimer(null_mut(), id);
+            }
+            self.reload_timer.set(0);
+            self.reload_config();
+        } else if id == self.hide_timer.get() {
+            self.hide_all();
+        } else if id == self.advance_timer.get() {
+            self.step_patch_set(1);
+        } else if id == self.player_timer.get() {
+            self.on_player_timer();
+        } else if id == self.guard_timer.get() {
+            self.on_guard_timer();
+        } else if id == self.schedule_timer.get() {
+            self.check_schedule(false);
+        } else if id == self.fade_timer.get() {
+            self.on_fade_timer();
+        } else if id == self.idle_timer.get() {
+            self.on_idle_timer();
+        }
+    }
+
+    fn message_loop(&self) {
+        unsafe {
+            let mut msg: MSG = zeroed();
+            while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+                if msg.message == WM_HOTKEY {
+                    self.on_hotkey(msg.wParam as i32);
+                } else if msg.message == WM_DIR_CHANGED {
+                    self.schedule_reload();
+                } else if msg.message == WM_CONTROL {
+                    self.on_control();
+                } else if msg.message == WM_HTTP {
+                    self.on_http();
+                } else if msg.message == WM_GENERATOR {
+                    self.on_generator();
+                } else if msg.message == WM_TIMER && msg.hwnd.is_null() {
+                    self.on_thread_timer(msg.wParam);
+                } else {
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+        }
+    }
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
 }
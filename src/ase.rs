@@ -0,0 +1,307 @@
+//! Adobe Swatch Exchange (`.ase`) files: a big-endian list of blocks, each a
+//! named color or the start or end of a group. Groups are flattened on
+//! import and not written on export.
+
+use crate::color;
+use crate::config::Preset;
+
+const SIGNATURE: &[u8; 4] = b"ASEF";
+const BLOCK_COLOR: u16 = 0x0001;
+const BLOCK_GROUP_START: u16 = 0xC001;
+const BLOCK_GROUP_END: u16 = 0xC002;
+/// Color type written on export; 0 is global and 1 spot.
+const TYPE_NORMAL: u16 = 2;
+/// Longest name in UTF-16 units: the length field is a `u16` that counts
+/// the terminating nul too.
+const MAX_NAME: usize = u16::MAX as usize - 1;
+
+/// Reads the colors of a swatch file. RGB and gray are taken as sRGB; CMYK
+/// is converted naively and Lab assuming D50, as Adobe tools use.
+pub fn parse(data: &[u8]) -> Result<Vec<Preset>, String> {
+    let mut reader = Reader { data, offset: 0 };
+    if reader.bytes(4)? != SIGNATURE {
+        return Err("not an Adobe Swatch Exchange file".to_string());
+    }
+    let major = reader.u16()?;
+    reader.u16()?;
+    if major != 1 {
+        return Err(format!("unsupported version {}", major));
+    }
+
+    let count = reader.u32()?;
+    let mut presets = Vec::new();
+    for _ in 0..count {
+        let kind = reader.u16()?;
+        let length = reader.u32()? as usize;
+        let mut block = Reader {
+            data: reader.bytes(length)?,
+            offset: 0,
+        };
+        match kind {
+            BLOCK_COLOR => presets.push(block.color()?),
+            BLOCK_GROUP_START | BLOCK_GROUP_END => {}
+            _ => return Err(format!("unknown block type 0x{:04X}", kind)),
+        }
+    }
+    Ok(presets)
+}
+
+/// Writes `presets` as RGB swatches. Names too long for the format are
+/// clipped to `MAX_NAME` units, at a character boundary.
+pub fn write(presets: &[Preset]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(SIGNATURE);
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(presets.len() as u32).to_be_bytes());
+
+    for preset in presets {
+        let mut name = Vec::new();
+        for c in preset.name.chars() {
+            if name.len() + c.len_utf16() > MAX_NAME {
+                break;
+            }
+            name.extend(c.encode_utf16(&mut [0; 2]).iter());
+        }
+        name.push(0);
+        let mut block = Vec::new();
+        block.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        for unit in name {
+            block.extend_from_slice(&unit.to_be_bytes());
+        }
+        block.extend_from_slice(b"RGB ");
+        let (r, g, b) = color::colorref_to_rgb(preset.color);
+        for channel in [r, g, b] {
+            block.extend_from_slice(&(channel as f32 / 255.0).to_be_bytes());
+        }
+        block.extend_from_slice(&TYPE_NORMAL.to_be_bytes());
+
+        out.extend_from_slice(&BLOCK_COLOR.to_be_bytes());
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        out.extend_from_slice(&block);
+    }
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .offset
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| "file is truncated".to_string())?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f64, String> {
+        let b = self.bytes(4)?;
+        Ok(f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64)
+    }
+
+    /// A color block: name, model and values. The color type that follows
+    /// is ignored.
+    fn color(&mut self) -> Result<Preset, String> {
+        let length = self.u16()? as usize;
+        let units: Vec<u16> = (0..length).map(|_| self.u16()).collect::<Result<_, _>>()?;
+        let name = String::from_utf16_lossy(&units)
+            .trim_end_matches('\0')
+            .to_string();
+
+        let model = self.bytes(4)?;
+        let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let color = match model {
+            b"RGB " => {
+                let (r, g, b) = (self.f32()?, self.f32()?, self.f32()?);
+                color::rgb_to_colorref(channel(r), channel(g), channel(b))
+            }
+            b"Gray" => {
+                let v = channel(self.f32()?);
+                color::rgb_to_colorref(v, v, v)
+            }
+            b"CMYK" => {
+                let (c, m, y, k) = (self.f32()?, self.f32()?, self.f32()?, self.f32()?);
+                let ink = |v: f64| channel((1.0 - v) * (1.0 - k));
+                color::rgb_to_colorref(ink(c), ink(m), ink(y))
+            }
+            b"LAB " => {
+                let (l, a, b) = (self.f32()?, self.f32()?, self.f32()?);
+                lab_to_srgb(l * 100.0, a, b)
+            }
+            _ => {
+                return Err(format!(
+                    "unknown color model `{}`",
+                    String::from_utf8_lossy(model)
+                ))
+            }
+        };
+
+        let name = if name.is_empty() {
+            color::to_hex(color)
+        } else {
+            name
+        };
+        Ok(Preset { name, color })
+    }
+}
+
+/// CIE Lab relative to D50 to an sRGB `COLORREF`, with Bradford adaptation
+/// to D65.
+fn lab_to_srgb(l: f64, a: f64, b: f64) -> u32 {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let inverse = |f: f64| {
+        if f.powi(3) > 216.0 / 24389.0 {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) * 27.0 / 24389.0
+        }
+    };
+    let x = 0.96422 * inverse(fx);
+    let y = inverse(fy);
+    let z = 0.82521 * inverse(fz);
+
+    let r = 3.1338561 * x - 1.6168667 * y - 0.4906146 * z;
+    let g = -0.9787684 * x + 1.9161415 * y + 0.0334540 * z;
+    let b = 0.0719453 * x - 0.2289914 * y + 1.4052427 * z;
+    color::rgb_to_colorref(
+        color::linear_to_srgb(r),
+        color::linear_to_srgb(g),
+        color::linear_to_srgb(b),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str, r: u8, g: u8, b: u8) -> Preset {
+        Preset {
+            name: name.to_string(),
+            color: color::rgb_to_colorref(r, g, b),
+        }
+    }
+
+    /// A file holding one block of `kind`.
+    fn file_with(kind: u16, block: &[u8]) -> Vec<u8> {
+        let mut data = b"ASEF\0\x01\0\0\0\0\0\x01".to_vec();
+        data.extend_from_slice(&kind.to_be_bytes());
+        data.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        data.extend_from_slice(block);
+        data
+    }
+
+    #[test]
+    fn flattens_groups_and_converts_models() {
+        let presets = parse(include_bytes!("../testdata/swatches.ase")).unwrap();
+        assert_eq!(
+            presets,
+            [
+                preset("Red CMYK", 255, 0, 0),
+                preset("Paper", 255, 255, 255),
+                preset("Mid gray", 128, 128, 128),
+                preset("Sky", 51, 153, 255),
+                preset("#000000", 0, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn converts_cmyk_and_lab() {
+        let cmyk = |c: f32, m: f32, y: f32, k: f32| {
+            let mut block = vec![0, 1, 0, 0];
+            block.extend_from_slice(b"CMYK");
+            for v in [c, m, y, k] {
+                block.extend_from_slice(&v.to_be_bytes());
+            }
+            block.extend_from_slice(&TYPE_NORMAL.to_be_bytes());
+            parse(&file_with(BLOCK_COLOR, &block)).unwrap()[0].color
+        };
+        assert_eq!(cmyk(0.0, 0.0, 0.0, 0.0), 0xFFFFFF);
+        assert_eq!(
+            cmyk(0.2, 0.0, 0.0, 0.5),
+            color::rgb_to_colorref(102, 128, 128)
+        );
+        assert_eq!(cmyk(0.0, 0.0, 0.0, 1.0), 0);
+
+        assert_eq!(lab_to_srgb(0.0, 0.0, 0.0), 0);
+        assert_eq!(
+            lab_to_srgb(50.0, 0.0, 0.0),
+            color::rgb_to_colorref(119, 119, 119)
+        );
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert_eq!(
+            parse(b"GIMP Palette"),
+            Err("not an Adobe Swatch Exchange file".to_string())
+        );
+        assert_eq!(
+            parse(b"ASEF\0\x02\0\0\0\0\0\0"),
+            Err("unsupported version 2".to_string())
+        );
+        assert_eq!(
+            parse(b"ASEF\0\x01\0\0\0\0\0\x02"),
+            Err("file is truncated".to_string())
+        );
+        assert_eq!(
+            parse(&file_with(0x0003, &[])),
+            Err("unknown block type 0x0003".to_string())
+        );
+        let mut hsv = vec![0, 1, 0, 0];
+        hsv.extend_from_slice(b"HSV ");
+        assert_eq!(
+            parse(&file_with(BLOCK_COLOR, &hsv)),
+            Err("unknown color model `HSV `".to_string())
+        );
+        let full = include_bytes!("../testdata/swatches.ase");
+        assert_eq!(
+            parse(&full[..full.len() - 1]),
+            Err("file is truncated".to_string())
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let presets = vec![
+            preset("Black", 0, 0, 0),
+            preset("Sky", 51, 153, 255),
+            preset("Grün ✓ 🎨", 1, 2, 3),
+        ];
+        assert_eq!(parse(&write(&presets)), Ok(presets));
+        assert_eq!(parse(&write(&[])), Ok(vec![]));
+    }
+
+    #[test]
+    fn clips_long_names() {
+        // One unit short of the limit, then a character that needs two
+        let name = format!("{}🎨", "a".repeat(MAX_NAME - 1));
+        let data = write(&[Preset { name, color: 0 }]);
+        let length = u16::from_be_bytes([data[18], data[19]]) as usize;
+        assert_eq!(length, MAX_NAME);
+        let presets = parse(&data).unwrap();
+        assert_eq!(presets[0].name, "a".repeat(MAX_NAME - 1));
+
+        let name = "b".repeat(MAX_NAME + 10);
+        let presets = parse(&write(&[Preset { name, color: 0 }])).unwrap();
+        assert_eq!(presets[0].name, "b".repeat(MAX_NAME));
+    }
+}
@@ -0,0 +1,252 @@
+//! Command-line client for the blanqr control pipe.
+
+#[allow(dead_code)]
+#[path = "../color.rs"]
+mod color;
+#[allow(dead_code)]
+#[path = "../json.rs"]
+mod json;
+#[allow(dead_code)]
+#[path = "../pattern.rs"]
+mod pattern;
+#[allow(dead_code)]
+#[path = "../protocol.rs"]
+mod protocol;
+
+use json::Value;
+use pattern::Pattern;
+use protocol::{Command, EventKind, Request};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ptr::null_mut;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_PIPE_BUSY, GENERIC_READ, GENERIC_WRITE, HANDLE,
+    INVALID_HANDLE_VALUE,
+};
+use windows_sys::Win32::Storage::FileSystem::{CreateFileW, ReadFile, WriteFile, OPEN_EXISTING};
+use windows_sys::Win32::System::Pipes::WaitNamedPipeW;
+
+const PIPE_NAME: &str = r"\\.\pipe\blanqr";
+const BUSY_WAIT_MS: u32 = 2000;
+
+const USAGE: &str = "\
+Usage: blanqrctl <COMMAND> [ARGS]
+
+Controls a running blanqr over its control pipe.
+
+Commands:
+  show                   Show the overlay
+  hide                   Hide the overlay
+  toggle                 Toggle the overlay
+  set_color <#RRGGBB>    Change the overlay color
+  set_pattern <NAME>     solid, gradient, checkerboard or grid
+  get_state              Print the current state as JSON
+  list_monitors          Print the monitors as JSON
+  watch [EVENT...]       Print events as JSON lines until interrupted
+                         (shown, hidden, color_changed; default all)
+";
+
+struct Pipe(HANDLE);
+
+impl Pipe {
+    fn open() -> Option<Pipe> {
+        let name: Vec<u16> = PIPE_NAME.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            loop {
+                let handle = CreateFileW(
+                    name.as_ptr(),
+                    GENERIC_READ | GENERIC_WRITE,
+                    0,
+                    null_mut(),
+                    OPEN_EXISTING,
+                    0,
+                    null_mut(),
+                );
+                if handle != INVALID_HANDLE_VALUE {
+                    return Some(Pipe(handle));
+                }
+                // All instances busy: wait for the server to open another
+                if GetLastError() != ERROR_PIPE_BUSY
+                    || WaitNamedPipeW(name.as_ptr(), BUSY_WAIT_MS) == 0
+                {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Read for &Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        let ok = unsafe {
+            ReadFile(
+                self.0,
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+                &mut read,
+                null_mut(),
+            )
+        };
+        // A closed pipe reads as end of file
+        Ok(if ok == 0 { 0 } else { read as usize })
+    }
+}
+
+impl Write for &Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        let ok = unsafe {
+            WriteFile(
+                self.0,
+                buf.as_ptr(),
+                buf.len() as u32,
+                &mut written,
+                null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "-h" || args[0] == "--help" {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let command = match parse_command(&args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("blanqrctl: {}\n\n{}", message, USAGE.trim_end());
+            std::process::exit(2);
+        }
+    };
+    std::process::exit(run(command));
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    let name = args[0].replace('-', "_");
+    let rest = &args[1..];
+    let single = || match rest {
+        [value] => Ok(value.as_str()),
+        _ => Err(format!("{} takes exactly one argument", name)),
+    };
+
+    let command = match name.as_str() {
+        "show" => Command::Show,
+        "hide" => Command::Hide,
+        "toggle" => Command::Toggle,
+        "get_state" => Command::GetState,
+        "list_monitors" => Command::ListMonitors,
+        "set_color" => {
+            let hex = single()?;
+            Command::SetColor(
+                color::parse_hex(hex)
+                    .ok_or_else(|| format!("invalid color `{}`, expected #RRGGBB", hex))?,
+            )
+        }
+        "set_pattern" => {
+            let value = single()?;
+            Command::SetPattern(Pattern::parse(value).ok_or_else(|| {
+                format!(
+                    "invalid pattern `{}`, expected one of {}",
+                    value,
+                    Pattern::names()
+                )
+            })?)
+        }
+        "watch" => {
+            let events = if rest.is_empty() {
+                EventKind::ALL.to_vec()
+            } else {
+                rest.iter()
+                    .map(|e| EventKind::parse(e).ok_or_else(|| format!("unknown event `{}`", e)))
+                    .collect::<Result<_, _>>()?
+            };
+            return Ok(Command::Subscribe(events));
+        }
+        other => return Err(format!("unknown command `{}`", other)),
+    };
+
+    if !matches!(command, Command::SetColor(_) | Command::SetPattern(_)) && !rest.is_empty() {
+        return Err(format!("{} takes no arguments", name));
+    }
+    Ok(command)
+}
+
+/// Sends one request and prints the outcome. Returns the exit code.
+fn run(command: Command) -> i32 {
+    let Some(pipe) = Pipe::open() else {
+        eprintln!("blanqrctl: blanqr is not running");
+        return 1;
+    };
+
+    let watch = matches!(command, Command::Subscribe(_));
+    let request = Request {
+        id: Some(Value::Number(1.0)),
+        command,
+    };
+    let line = protocol::encode_request(&request) + "\n";
+    if let Err(err) = (&pipe).write_all(line.as_bytes()) {
+        eprintln!("blanqrctl: {}", err);
+        return 1;
+    }
+
+    let mut lines = BufReader::new(&pipe).lines();
+    let response = loop {
+        match lines.next() {
+            Some(Ok(line)) => match json::parse(&line) {
+                // Skip events that raced ahead of the response
+                Ok(value) if value.get("event").is_none() => break value,
+                Ok(_) => continue,
+                Err(err) => {
+                    eprintln!("blanqrctl: invalid response: {}", err);
+                    return 1;
+                }
+            },
+            _ => {
+                eprintln!("blanqrctl: connection closed");
+                return 1;
+            }
+        }
+    };
+
+    if response.get("ok") != Some(&Value::Bool(true)) {
+        let message = response
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("request failed");
+        eprintln!("blanqrctl: {}", message);
+        return 1;
+    }
+    if let Some(result) = response.get("result") {
+        println!("{}", result);
+    }
+
+    if watch {
+        let stdout = io::stdout();
+        for line in lines.map_while(Result::ok) {
+            let mut out = stdout.lock();
+            if writeln!(out, "{}", line).and_then(|_| out.flush()).is_err() {
+                break;
+            }
+        }
+    }
+    0
+}
@@ -0,0 +1,297 @@
+//! CGATS.17 text files as written by ArgyllCMS (`.ti1`, `.ti2`, `.ti3`) and
+//! most measurement software:
+//!
+//! ```text
+//! CTI1
+//! KEYWORD "COLOR_REP"
+//! COLOR_REP "RGB"
+//! NUMBER_OF_FIELDS 4
+//! BEGIN_DATA_FORMAT
+//! SAMPLE_ID RGB_R RGB_G RGB_B
+//! END_DATA_FORMAT
+//! NUMBER_OF_SETS 2
+//! BEGIN_DATA
+//! 1 100.00 100.00 100.00
+//! 2 0.0000 0.0000 0.0000
+//! END_DATA
+//! ```
+//!
+//! A file may hold several tables, each starting with its own file type line.
+//! Pure; `patch_set` picks the columns it needs.
+
+use std::fmt;
+
+/// One table: column names and the rows under them, as text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub fields: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Index of the column named `name`, ignoring case.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .position(|f| f.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn error(line: usize, message: impl Into<String>) -> Error {
+    Error {
+        line,
+        message: message.into(),
+    }
+}
+
+enum Section {
+    Header,
+    Format,
+    Data,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Table>, Error> {
+    let mut tables = Vec::new();
+    let mut table: Option<Table> = None;
+    let mut section = Section::Header;
+    let mut expected_sets = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let tokens = tokenize(line).map_err(|message| error(number, message))?;
+        let Some(first) = tokens.first() else {
+            continue;
+        };
+
+        match section {
+            Section::Header => match first.as_str() {
+                "BEGIN_DATA_FORMAT" => {
+                    let current = table.get_or_insert_with(Table::default);
+                    current.fields.clear();
+                    section = Section::Format;
+                }
+                "BEGIN_DATA" => {
+                    let current = table.get_or_insert_with(Table::default);
+                    if current.fields.is_empty() {
+                        return Err(error(number, "BEGIN_DATA before the data format"));
+                    }
+                    section = Section::Data;
+                }
+                "NUMBER_OF_SETS" => {
+                    let sets = tokens.get(1).and_then(|n| n.parse::<usize>().ok());
+                    if sets.is_none() {
+                        return Err(error(number, "NUMBER_OF_SETS needs a count"));
+                    }
+                    expected_sets = sets;
+                }
+                // A bare word on a line of its own after a finished table
+                // starts the next one, e.g. `CTI1`
+                _ if tokens.len() == 1 && table.as_ref().is_some_and(|t| !t.rows.is_empty()) => {
+                    tables.extend(table.take());
+                    expected_sets = None;
+                }
+                // Other keywords (`DESCRIPTOR`, `COLOR_REP`, ...) aren't needed
+                _ => {}
+            },
+            Section::Format => {
+                if first == "END_DATA_FORMAT" {
+                    section = Section::Header;
+                } else if let Some(current) = table.as_mut() {
+                    current.fields.extend(tokens);
+                }
+            }
+            Section::Data => {
+                let Some(current) = table.as_mut() else {
+                    continue;
+                };
+                if first == "END_DATA" {
+                    if let Some(sets) = expected_sets {
+                        if sets != current.rows.len() {
+                            return Err(error(
+                                number,
+                                format!(
+                                    "NUMBER_OF_SETS is {} but {} rows were found",
+                                    sets,
+                                    current.rows.len()
+                                ),
+                            ));
+                        }
+                    }
+                    section = Section::Header;
+                } else if tokens.len() != current.fields.len() {
+                    return Err(error(
+                        number,
+                        format!(
+                            "expected {} values, found {}",
+                            current.fields.len(),
+                            tokens.len()
+                        ),
+                    ));
+                } else {
+                    current.rows.push(tokens);
+                }
+            }
+        }
+    }
+
+    match section {
+        Section::Header => {}
+        Section::Format => return Err(error(text.lines().count(), "missing END_DATA_FORMAT")),
+        Section::Data => return Err(error(text.lines().count(), "missing END_DATA")),
+    }
+    tables.extend(table.filter(|t| !t.fields.is_empty()));
+    Ok(tables)
+}
+
+/// Splits on whitespace, keeping double-quoted strings together. `#` starts
+/// a comment outside quotes.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn parse_error(text: &str) -> Error {
+        parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn reads_every_table() {
+        let tables = parse(include_str!("../testdata/patches.ti1")).unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(
+            tables[0].fields,
+            fields(&[
+                "SAMPLE_ID",
+                "RGB_R",
+                "RGB_G",
+                "RGB_B",
+                "XYZ_X",
+                "XYZ_Y",
+                "XYZ_Z"
+            ])
+        );
+        assert_eq!(tables[0].rows.len(), 4);
+        assert_eq!(tables[0].rows[3][1..4], ["50.000", "25.000", "75.000"]);
+        assert_eq!(
+            tables[1].fields,
+            fields(&["INDEX", "XYZ_X", "XYZ_Y", "XYZ_Z"])
+        );
+        assert_eq!(
+            tables[1].rows,
+            [fields(&["0", "95.046", "100.00", "108.91"])]
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_keywords() {
+        let tables = parse(include_str!("../testdata/measured.ti3")).unwrap();
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].column("rgb_g"), Some(2));
+        assert_eq!(tables[0].column("LAB_L"), None);
+        assert_eq!(tables[0].rows.len(), 3);
+    }
+
+    #[test]
+    fn tokenizes_quotes_and_comments() {
+        assert_eq!(
+            tokenize(r#"  DESCRIPTOR "two words" # note"#),
+            Ok(fields(&["DESCRIPTOR", "two words"]))
+        );
+        assert_eq!(tokenize(r##"A "#" B"##), Ok(fields(&["A", "#", "B"])));
+        assert_eq!(tokenize("# only a comment"), Ok(vec![]));
+        assert_eq!(
+            tokenize(r#"A "open"#),
+            Err("unterminated string".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_broken_tables() {
+        let err = parse_error("CTI1\nDESCRIPTOR \"oops\n");
+        assert_eq!(err.to_string(), "line 2: unterminated string");
+        assert_eq!(
+            parse_error("CTI1\nBEGIN_DATA\n1\nEND_DATA\n"),
+            error(2, "BEGIN_DATA before the data format")
+        );
+        assert_eq!(
+            parse_error("CTI1\nNUMBER_OF_SETS many\n"),
+            error(2, "NUMBER_OF_SETS needs a count")
+        );
+        let header = "CTI1\nBEGIN_DATA_FORMAT\nA B\nEND_DATA_FORMAT\n";
+        assert_eq!(
+            parse_error(&format!(
+                "{}NUMBER_OF_SETS 2\nBEGIN_DATA\n1 2\nEND_DATA\n",
+                header
+            )),
+            error(8, "NUMBER_OF_SETS is 2 but 1 rows were found")
+        );
+        assert_eq!(
+            parse_error(&format!("{}BEGIN_DATA\n1 2 3\nEND_DATA\n", header)),
+            error(6, "expected 2 values, found 3")
+        );
+        assert_eq!(
+            parse_error(&format!("{}BEGIN_DATA\n1 2\n", header)),
+            error(6, "missing END_DATA")
+        );
+        assert_eq!(
+            parse_error("CTI1\nBEGIN_DATA_FORMAT\nA B\n"),
+            error(3, "missing END_DATA_FORMAT")
+        );
+    }
+
+    #[test]
+    fn ignores_files_without_tables() {
+        assert_eq!(parse(""), Ok(vec![]));
+        assert_eq!(parse("CTI1\nDESCRIPTOR \"empty\"\n"), Ok(vec![]));
+    }
+}
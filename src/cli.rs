@@ -0,0 +1,572 @@
+//! Command-line parsing. Pure so scripts get predictable validation; `main`
+//! and `App` act on the result.
+
+use crate::color;
+use crate::keymap::Action;
+use crate::patch::{PatchSize, PatchWindow};
+use crate::pattern::Pattern;
+use crate::resolve;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: blanqr [OPTIONS]
+
+Fills the screen with a single color. Without options, starts in the tray.
+If blanqr is already running, --show, --hide, --toggle, --quit, --color and
+--profile are sent to the running instance instead.
+
+Options:
+  --color <#RRGGBB>    Overlay color
+  --pattern <NAME>     solid, gradient, checkerboard or grid
+  --monitor <N>        Cover only monitor N (repeatable)
+  --window <SIZE>      Draw a patch instead of filling the screen: a share of
+                       the screen such as 10%, or pixels such as 400x300
+  --window-position <X,Y>
+                       Patch placement in percent, default 50,50 (centered)
+  --background <#RRGGBB>
+                       Color around the patch, default black
+  --profile <NAME>     Start from a profile in the config
+  --show               Show the overlay immediately
+  --hide               Hide the overlay
+  --toggle             Show the overlay if hidden, otherwise hide it
+  --quit               Exit the running instance
+  --patches <FILE>     Step through the colors in a .ti1/.ti3 or CSV file
+                       with the arrow keys (shows the overlay)
+  --advance <TIME>     Move to the next patch every TIME instead
+  --sequence <NAME>    Play a color sequence from the config (shows the
+                       overlay; Space pauses)
+  --duration <TIME>    Hide the overlay after TIME, e.g. 30s, 5m, 1h, 500ms
+  --exit-after         Exit once the overlay is hidden
+  --no-tray            Don't add a tray icon (requires --exit-after)
+  --generator <ADDR>   Connect to calibration software at host:port
+                       (Resolve pattern generator protocol)
+  --config <PATH>      Use this config file or directory
+  --check-config       Validate the config file and exit
+  -h, --help           Show this help
+";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub color: Option<u32>,
+    pub pattern: Option<Pattern>,
+    pub monitors: Vec<u32>,
+    pub window: Option<PatchSize>,
+    pub window_position: Option<(u8, u8)>,
+    pub background: Option<u32>,
+    pub profile: Option<String>,
+    /// Patch set file to step through.
+    pub patches: Option<PathBuf>,
+    pub advance: Option<Duration>,
+    /// Sequence from the config to play.
+    pub sequence: Option<String>,
+    pub show: bool,
+    pub hide: bool,
+    pub toggle: bool,
+    pub quit: bool,
+    pub duration: Option<Duration>,
+    pub exit_after: bool,
+    pub no_tray: bool,
+    /// `host:port` of calibration software to take patches from.
+    pub generator: Option<String>,
+    pub config: Option<PathBuf>,
+    pub check_config: bool,
+    pub help: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses arguments, excluding the program name. Accepts both
+/// `--flag value` and `--flag=value`.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.into())),
+            _ => (arg, None),
+        };
+
+        let takes_value = matches!(
+            flag.as_str(),
+            "--color"
+                | "--pattern"
+                | "--monitor"
+                | "--window"
+                | "--window-position"
+                | "--background"
+                | "--profile"
+                | "--patches"
+                | "--advance"
+                | "--sequence"
+                | "--duration"
+                | "--generator"
+                | "--config"
+        );
+        let value = if takes_value {
+            match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(CliError(format!("{} needs a value", flag))),
+            }
+        } else if inline.is_some() {
+            return Err(CliError(format!("{} does not take a value", flag)));
+        } else {
+            String::new()
+        };
+
+        match flag.as_str() {
+            "--color" => {
+                let color = color::parse_hex(&value).ok_or_else(|| {
+                    CliError(format!("invalid color `{}`, expected #RRGGBB", value))
+                })?;
+                options.color = Some(color);
+            }
+            "--pattern" => {
+                let pattern = Pattern::parse(&value).ok_or_else(|| {
+                    CliError(format!(
+                        "invalid pattern `{}`, expected one of {}",
+                        value,
+                        Pattern::names()
+                    ))
+                })?;
+                options.pattern = Some(pattern);
+            }
+            "--monitor" => match value.parse::<u32>() {
+                Ok(n) if n >= 1 => options.monitors.push(n),
+                _ => {
+                    return Err(CliError(format!(
+                        "invalid monitor `{}`, expected a number starting at 1",
+                        value
+                    )))
+                }
+            },
+            "--window" => {
+                let size = PatchSize::parse(&value).ok_or_else(|| {
+                    CliError(format!(
+                        "invalid window size `{}`, expected e.g. 10% or 400x300",
+                        value
+                    ))
+                })?;
+                options.window = Some(size);
+            }
+            "--window-position" => {
+                let position = PatchWindow::parse_position(&value).ok_or_else(|| {
+                    CliError(format!(
+                        "invalid window position `{}`, expected X,Y in percent, e.g. 50,50",
+                        value
+                    ))
+                })?;
+                options.window_position = Some(position);
+            }
+            "--background" => {
+                let color = color::parse_hex(&value).ok_or_else(|| {
+                    CliError(format!("invalid color `{}`, expected #RRGGBB", value))
+                })?;
+                options.background = Some(color);
+            }
+            "--profile" => options.profile = Some(value),
+            "--patches" => options.patches = Some(PathBuf::from(value)),
+            "--sequence" => options.sequence = Some(value),
+            "--duration" | "--advance" => {
+                let duration = parse_duration(&value).ok_or_else(|| {
+                    CliError(format!(
+                        "invalid duration `{}`, expected e.g. 30s, 5m, 1h or 500ms",
+                        value
+                    ))
+                })?;
+                if flag == "--advance" {
+                    options.advance = Some(duration);
+                } else {
+                    options.duration = Some(duration);
+                }
+            }
+            "--generator" => {
+                let address = if value.contains(':') {
+                    value
+                } else {
+                    // A bare host uses the usual port
+                    format!("{}:{}", value, resolve::DEFAULT_PORT)
+                };
+                options.generator = Some(address);
+            }
+            "--config" => options.config = Some(PathBuf::from(value)),
+            "--show" => options.show = true,
+            "--hide" => options.hide = true,
+            "--toggle" => options.toggle = true,
+            "--quit" => options.quit = true,
+            "--exit-after" => options.exit_after = true,
+            "--no-tray" => options.no_tray = true,
+            "--check-config" => options.check_config = true,
+            "-h" | "--help" => options.help = true,
+            other => return Err(CliError(format!("unknown option `{}`", other))),
+        }
+    }
+
+    // Patches and sequences need the overlay on screen
+    if options.patches.is_some() || options.sequence.is_some() {
+        options.show = true;
+    }
+    validate(&options)?;
+    Ok(options)
+}
+
+fn validate(options: &Options) -> Result<(), CliError> {
+    if options.help || options.check_config {
+        return Ok(());
+    }
+    if options.patches.is_some() && options.sequence.is_some() {
+        return Err(CliError(
+            "--patches and --sequence are mutually exclusive".to_string(),
+        ));
+    }
+    let player = match (&options.patches, &options.sequence) {
+        (Some(_), _) => Some("--patches"),
+        (_, Some(_)) => Some("--sequence"),
+        _ => None,
+    };
+    if let Some(flag) = player {
+        if options.hide || options.toggle || options.quit {
+            return Err(CliError(format!(
+                "{} shows the overlay, so it can't be combined with --hide, --toggle or --quit",
+                flag
+            )));
+        }
+    }
+    let commands = [options.show, options.hide, options.toggle, options.quit];
+    if commands.iter().filter(|&&set| set).count() > 1 {
+        return Err(CliError(
+            "--show, --hide, --toggle and --quit are mutually exclusive".to_string(),
+        ));
+    }
+    if options.window.is_none() {
+        if options.window_position.is_some() {
+            return Err(CliError("--window-position requires --window".to_string()));
+        }
+        if options.background.is_some() {
+            return Err(CliError("--background requires --window".to_string()));
+        }
+    }
+    if options.advance.is_some() && options.patches.is_none() {
+        return Err(CliError("--advance requires --patches".to_string()));
+    }
+    if options.duration.is_some() && !options.show {
+        return Err(CliError("--duration requires --show".to_string()));
+    }
+    if options.exit_after && !options.show {
+        return Err(CliError("--exit-after requires --show".to_string()));
+    }
+    if options.no_tray && !options.exit_after {
+        // Otherwise the only way to stop blanqr would be Task Manager
+        return Err(CliError("--no-tray requires --exit-after".to_string()));
+    }
+    Ok(())
+}
+
+/// Refuses `--monitor` numbers that aren't connected, given the numbers
+/// that are. Covering nothing would leave an overlay that can't be seen or,
+/// with `--no-tray`, a process that can't be stopped.
+pub fn check_monitors(options: &Options, connected: &[u32]) -> Result<(), CliError> {
+    match options.monitors.iter().find(|n| !connected.contains(n)) {
+        Some(n) => Err(CliError(format!(
+            "monitor {} does not exist ({} connected)",
+            n,
+            connected.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// The actions to send to an already running instance, in the order it
+/// should apply them. Options that only make sense at startup are refused
+/// rather than silently dropped.
+pub fn forwarded_actions(options: &Options) -> Result<Vec<Action>, CliError> {
+    let startup_only = [
+        ("--pattern", options.pattern.is_some()),
+        ("--monitor", !options.monitors.is_empty()),
+        ("--window", options.window.is_some()),
+        ("--patches", options.patches.is_some()),
+        ("--advance", options.advance.is_some()),
+        ("--sequence", options.sequence.is_some()),
+        ("--duration", options.duration.is_some()),
+        ("--exit-after", options.exit_after),
+        ("--no-tray", options.no_tray),
+        ("--generator", options.generator.is_some()),
+    ];
+    if let Some((flag, _)) = startup_only.iter().find(|(_, set)| *set) {
+        return Err(CliError(format!(
+            "blanqr is already running; {} only applies when starting it",
+            flag
+        )));
+    }
+
+    let mut actions = Vec::new();
+    if let Some(name) = &options.profile {
+        actions.push(Action::Profile(name.clone()));
+    }
+    if let Some(color) = options.color {
+        actions.push(Action::Color(color));
+    }
+    if options.show {
+        actions.push(Action::Show);
+    } else if options.hide {
+        actions.push(Action::Hide);
+    } else if options.toggle {
+        actions.push(Action::Toggle);
+    } else if options.quit {
+        actions.push(Action::Quit);
+    }
+    Ok(actions)
+}
+
+/// The patch window asked for with `--window`, if any.
+pub fn patch_window(options: &Options) -> Option<PatchWindow> {
+    let size = options.window?;
+    let default = PatchWindow::default();
+    let (x, y) = options.window_position.unwrap_or((default.x, default.y));
+    Some(PatchWindow {
+        size,
+        x,
+        y,
+        background: options.background.unwrap_or(default.background),
+    })
+}
+
+/// The shortest exact form `parse_duration` reads back, e.g. `90s`, `5m`.
+pub fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_millis();
+    if !ms.is_multiple_of(1000) {
+        format!("{}ms", ms)
+    } else if ms.is_multiple_of(3_600_000) {
+        format!("{}h", ms / 3_600_000)
+    } else if ms.is_multiple_of(60_000) {
+        format!("{}m", ms / 60_000)
+    } else {
+        format!("{}s", ms / 1000)
+    }
+}
+
+/// `30s`, `5m`, `1h`, `500ms`; a bare number means seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let n: u64 = number.parse().ok()?;
+    let duration = match unit.trim() {
+        "ms" => Duration::from_millis(n),
+        "" | "s" => Duration::from_secs(n),
+        "m" => Duration::from_secs(n.checked_mul(60)?),
+        "h" => Duration::from_secs(n.checked_mul(3600)?),
+        _ => return None,
+    };
+    if duration.is_zero() {
+        None
+    } else {
+        Some(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Options, CliError> {
+        parse(s.split_whitespace().map(String::from))
+    }
+
+    fn error(s: &str) -> String {
+        args(s).unwrap_err().0
+    }
+
+    #[test]
+    fn no_arguments_start_in_the_tray() {
+        assert_eq!(args(""), Ok(Options::default()));
+    }
+
+    #[test]
+    fn parses_flags_and_values() {
+        let options = args(
+            "--color #FF8000 --pattern=grid --monitor 2 --monitor=3 --show \
+             --duration 90s --exit-after --no-tray",
+        )
+        .unwrap();
+        assert_eq!(options.color, Some(0x0080FF));
+        assert_eq!(options.pattern, Some(Pattern::parse("grid").unwrap()));
+        assert_eq!(options.monitors, vec![2, 3]);
+        assert!(options.show && options.exit_after && options.no_tray);
+        assert_eq!(options.duration, Some(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn patches_and_sequences_show_the_overlay() {
+        assert!(args("--patches set.ti1").unwrap().show);
+        assert!(args("--sequence warmup").unwrap().show);
+        assert_eq!(
+            args("--patches set.csv --advance 2s").unwrap().advance,
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn generator_gets_the_default_port() {
+        let options = args("--generator 192.168.1.5").unwrap();
+        assert_eq!(
+            options.generator,
+            Some(format!("192.168.1.5:{}", resolve::DEFAULT_PORT))
+        );
+        assert_eq!(
+            args("--generator host:1234").unwrap().generator.as_deref(),
+            Some("host:1234")
+        );
+    }
+
+    #[test]
+    fn builds_the_patch_window() {
+        let options = args("--window 10% --window-position 25,75 --background #808080").unwrap();
+        let window = patch_window(&options).unwrap();
+        assert_eq!(window.size, PatchSize::Area(10));
+        assert_eq!((window.x, window.y), (25, 75));
+        assert_eq!(window.background, 0x808080);
+        assert_eq!(patch_window(&args("").unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(error("--color red").contains("invalid color"));
+        assert!(error("--pattern stripes").contains("invalid pattern"));
+        assert!(error("--monitor 0").contains("invalid monitor"));
+        assert!(error("--monitor two").contains("invalid monitor"));
+        assert!(error("--window huge").contains("invalid window size"));
+        assert!(error("--window 10% --window-position 50").contains("invalid window position"));
+        assert!(error("--show --duration soon").contains("invalid duration"));
+        assert_eq!(error("--color"), "--color needs a value");
+        assert_eq!(error("--show=yes"), "--show does not take a value");
+        assert_eq!(error("--frobnicate"), "unknown option `--frobnicate`");
+    }
+
+    #[test]
+    fn rejects_conflicting_flags() {
+        assert!(error("--show --hide").contains("mutually exclusive"));
+        assert!(error("--toggle --quit").contains("mutually exclusive"));
+        assert!(error("--patches a.ti1 --sequence b").contains("mutually exclusive"));
+        assert!(error("--sequence b --hide").contains("can't be combined"));
+        assert_eq!(
+            error("--window-position 1,2"),
+            "--window-position requires --window"
+        );
+        assert_eq!(
+            error("--background #000000"),
+            "--background requires --window"
+        );
+        assert_eq!(error("--advance 1s"), "--advance requires --patches");
+        assert_eq!(error("--duration 5s"), "--duration requires --show");
+        assert_eq!(error("--exit-after"), "--exit-after requires --show");
+        assert_eq!(error("--show --no-tray"), "--no-tray requires --exit-after");
+    }
+
+    #[test]
+    fn help_and_check_skip_validation() {
+        assert!(args("--help --exit-after").unwrap().help);
+        assert!(args("--check-config --no-tray").unwrap().check_config);
+    }
+
+    #[test]
+    fn checks_monitor_numbers() {
+        let options = args("--monitor 2 --show --exit-after --no-tray").unwrap();
+        assert_eq!(check_monitors(&options, &[1, 2]), Ok(()));
+        assert_eq!(
+            check_monitors(&options, &[1]),
+            Err(CliError(
+                "monitor 2 does not exist (1 connected)".to_string()
+            ))
+        );
+        assert_eq!(check_monitors(&args("").unwrap(), &[]), Ok(()));
+    }
+
+    #[test]
+    fn forwards_commands_in_order() {
+        let options = args("--profile night --color #000000 --show").unwrap();
+        assert_eq!(
+            forwarded_actions(&options),
+            Ok(vec![
+                Action::Profile("night".to_string()),
+                Action::Color(0),
+                Action::Show,
+            ])
+        );
+        assert_eq!(
+            forwarded_actions(&args("--hide").unwrap()),
+            Ok(vec![Action::Hide])
+        );
+        assert_eq!(
+            forwarded_actions(&args("--toggle").unwrap()),
+            Ok(vec![Action::Toggle])
+        );
+        assert_eq!(
+            forwarded_actions(&args("--quit").unwrap()),
+            Ok(vec![Action::Quit])
+        );
+        assert_eq!(forwarded_actions(&args("").unwrap()), Ok(vec![]));
+    }
+
+    #[test]
+    fn refuses_to_forward_startup_options() {
+        for flags in [
+            "--pattern grid",
+            "--monitor 2",
+            "--window 10%",
+            "--patches a.ti1",
+            "--sequence warmup",
+            "--show --duration 5s",
+            "--show --exit-after",
+            "--generator host",
+        ] {
+            let err = forwarded_actions(&args(flags).unwrap()).unwrap_err();
+            assert!(err.0.contains("only applies when starting"), "{}", flags);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("45"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration(" 500ms "), Some(Duration::from_millis(500)));
+        for bad in [
+            "",
+            "0s",
+            "s",
+            "-5s",
+            "1.5s",
+            "5d",
+            "ms",
+            "99999999999999999999h",
+        ] {
+            assert_eq!(parse_duration(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn durations_round_trip() {
+        let cases = [
+            (Duration::from_millis(250), "250ms"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_secs(90), "90s"),
+            (Duration::from_secs(300), "5m"),
+            (Duration::from_secs(7200), "2h"),
+        ];
+        for (duration, text) in cases {
+            assert_eq!(format_duration(duration), text);
+            assert_eq!(parse_duration(text), Some(duration));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::ptr::null_mut;
+use windows_sys::Win32::Foundation::GlobalFree;
+use windows_sys::Win32::System::DataExchange::{
+    CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
+};
+use windows_sys::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+
+/// `CF_UNICODETEXT`: nul-terminated UTF-16.
+const CF_UNICODETEXT: u32 = 13;
+
+/// Replaces the clipboard contents with `text`. Returns false if the
+/// clipboard is in use by another program.
+pub fn copy_text(text: &str) -> bool {
+    let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        if OpenClipboard(null_mut()) == 0 {
+            return false;
+        }
+        EmptyClipboard();
+
+        let size = wide.len() * std::mem::size_of::<u16>();
+        let memory = GlobalAlloc(GMEM_MOVEABLE, size);
+        let mut copied = false;
+        if !memory.is_null() {
+            let dst = GlobalLock(memory) as *mut u16;
+            if !dst.is_null() {
+                std::ptr::copy_nonoverlapping(wide.as_ptr(), dst, wide.len());
+                GlobalUnlock(memory);
+                // The clipboard owns the memory once this succeeds
+                copied = !SetClipboardData(CF_UNICODETEXT, memory).is_null();
+            }
+            if !copied {
+                GlobalFree(memory);
+            }
+        }
+
+        CloseClipboard();
+        copied
+    }
+}
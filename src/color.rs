@@ -0,0 +1,149 @@
+/// Parses `#RRGGBB` (or bare `RRGGBB`) into a GDI `COLORREF` (`0x00BBGGRR`).
+pub fn parse_hex(s: &str) -> Option<u32> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let (r, g, b) = ((rgb >> 16) & 0xFF, (rgb >> 8) & 0xFF, rgb & 0xFF);
+    Some(rgb_to_colorref(r as u8, g as u8, b as u8))
+}
+
+/// Formats a `COLORREF` as `#RRGGBB`.
+pub fn to_hex(color: u32) -> String {
+    let (r, g, b) = colorref_to_rgb(color);
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// Formats a `COLORREF` as decimal `R, G, B`, for readouts.
+pub fn to_rgb_string(color: u32) -> String {
+    let (r, g, b) = colorref_to_rgb(color);
+    format!("{}, {}, {}", r, g, b)
+}
+
+pub fn rgb_to_colorref(r: u8, g: u8, b: u8) -> u32 {
+    (r as u32) | ((g as u32) << 8) | ((b as u32) << 16)
+}
+
+pub fn colorref_to_rgb(color: u32) -> (u8, u8, u8) {
+    (
+        (color & 0xFF) as u8,
+        ((color >> 8) & 0xFF) as u8,
+        ((color >> 16) & 0xFF) as u8,
+    )
+}
+
+/// Blends from `a` (at 0.0) to `b` (at 1.0), per channel in code values.
+pub fn mix(a: u32, b: u32, t: f64) -> u32 {
+    let t = t.clamp(0.0, 1.0);
+    let (a, b) = (colorref_to_rgb(a), colorref_to_rgb(b));
+    let blend = |x: u8, y: u8| (x as f64 + (y as f64 - x as f64) * t).round() as u8;
+    rgb_to_colorref(blend(a.0, b.0), blend(a.1, b.1), blend(a.2, b.2))
+}
+
+/// Decodes an sRGB channel value to linear light in `0.0..=1.0`.
+pub fn srgb_to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes linear light back to an sRGB channel value, clamping.
+pub fn linear_to_srgb(l: f64) -> u8 {
+    let l = l.clamp(0.0, 1.0);
+    let c = if l <= 0.0031308 {
+        l * 12.92
+    } else {
+        1.055 * l.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// Scales a color's brightness in linear light, so 50% emits half the light
+/// rather than halving the code values.
+pub fn scale_brightness(color: u32, factor: f64) -> u32 {
+    let (r, g, b) = colorref_to_rgb(color);
+    let scale = |c: u8| linear_to_srgb(srgb_to_linear(c) * factor);
+    rgb_to_colorref(scale(r), scale(g), scale(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(parse_hex("#FF8000"), Some(rgb_to_colorref(255, 128, 0)));
+        assert_eq!(parse_hex(" ff8000 "), Some(0x0080FF));
+        assert_eq!(parse_hex("#000000"), Some(0));
+        for bad in [
+            "", "#", "#FFF", "#FF80001", "#GG0000", "#+F0000", "##FF0000",
+        ] {
+            assert_eq!(parse_hex(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        for color in [0x000000, 0xFFFFFF, 0x0080FF, 0x123456] {
+            assert_eq!(parse_hex(&to_hex(color)), Some(color));
+        }
+        assert_eq!(to_hex(rgb_to_colorref(255, 128, 0)), "#FF8000");
+        assert_eq!(to_hex(0xFF0080FF), "#FF8000");
+        assert_eq!(to_rgb_string(rgb_to_colorref(255, 128, 0)), "255, 128, 0");
+    }
+
+    #[test]
+    fn colorref_is_bgr() {
+        assert_eq!(rgb_to_colorref(0x12, 0x34, 0x56), 0x563412);
+        assert_eq!(colorref_to_rgb(0x563412), (0x12, 0x34, 0x56));
+    }
+
+    #[test]
+    fn mixes_code_values() {
+        let (black, white) = (0x000000, 0xFFFFFF);
+        assert_eq!(mix(black, white, 0.0), black);
+        assert_eq!(mix(black, white, 1.0), white);
+        assert_eq!(mix(black, white, 0.5), rgb_to_colorref(128, 128, 128));
+        assert_eq!(
+            mix(
+                rgb_to_colorref(255, 0, 100),
+                rgb_to_colorref(0, 255, 200),
+                0.25
+            ),
+            rgb_to_colorref(191, 64, 125)
+        );
+        assert_eq!(mix(black, white, -1.0), black);
+        assert_eq!(mix(black, white, 2.0), white);
+    }
+
+    #[test]
+    fn linear_round_trips() {
+        for c in 0..=255 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(c)), c);
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(128) - 0.2159).abs() < 1e-4);
+        assert_eq!(linear_to_srgb(-0.5), 0);
+        assert_eq!(linear_to_srgb(1.5), 255);
+    }
+
+    #[test]
+    fn scales_brightness_in_linear_light() {
+        let white = 0xFFFFFF;
+        assert_eq!(scale_brightness(white, 1.0), white);
+        assert_eq!(scale_brightness(white, 0.0), 0);
+        // Half the light, not half the code value
+        assert_eq!(scale_brightness(white, 0.5), rgb_to_colorref(188, 188, 188));
+        assert_eq!(
+            scale_brightness(rgb_to_colorref(128, 200, 10), 0.5),
+            rgb_to_colorref(92, 146, 5)
+        );
+        assert_eq!(scale_brightness(rgb_to_colorref(200, 0, 0), 0.25), 106);
+    }
+}
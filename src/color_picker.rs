@@ -1,21 +1,28 @@
+use crate::color;
+use crate::config::Preset;
+use crate::input_dialog::show_input_dialog;
 use std::mem::zeroed;
 use std::ptr::null_mut;
-use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM};
+use std::sync::Mutex;
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, WPARAM};
 use windows_sys::Win32::Graphics::Gdi::{
-    BeginPaint, CreatePen, CreateRoundRectRgn, CreateSolidBrush, DeleteObject, EndPaint, FillRect,
-    FrameRect, InvalidateRect, RoundRect, SelectObject, SetBkMode, SetTextColor, SetWindowRgn,
-    TextOutW, PAINTSTRUCT, PS_SOLID, TRANSPARENT,
+    BeginPaint, ClientToScreen, CreatePen, CreateRoundRectRgn, CreateSolidBrush, DeleteObject,
+    DrawTextW, EndPaint, FillRect, FrameRect, InvalidateRect, RoundRect, SelectObject, SetBkMode,
+    SetTextColor, SetWindowRgn, TextOutW, DT_CENTER, DT_SINGLELINE, DT_VCENTER, PAINTSTRUCT,
+    PS_SOLID, TRANSPARENT,
 };
 use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows_sys::Win32::UI::Controls::Dialogs::{
     ChooseColorW, CC_FULLOPEN, CC_RGBINIT, CHOOSECOLORW,
 };
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetClientRect, GetMessageW,
-    GetSystemMetrics, IsWindow, RegisterClassW, SetWindowPos, ShowWindow, TranslateMessage,
-    CS_HREDRAW, CS_VREDRAW, HWND_TOPMOST, MSG, SM_CXSCREEN, SM_CYSCREEN, SWP_NOMOVE, SWP_NOSIZE,
-    SWP_SHOWWINDOW, SW_SHOW, WM_CLOSE, WM_DESTROY, WM_LBUTTONDOWN, WM_MOUSEMOVE, WM_PAINT,
-    WNDCLASSW, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
+    DispatchMessageW, GetClientRect, GetMessageW, GetSystemMetrics, IsWindow, RegisterClassW,
+    SetWindowPos, ShowWindow, TrackPopupMenu, TranslateMessage, CS_HREDRAW, CS_VREDRAW,
+    HWND_TOPMOST, MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, SM_CXSCREEN, SM_CYSCREEN, SWP_NOMOVE,
+    SWP_NOSIZE, SWP_NOZORDER, SWP_SHOWWINDOW, SW_HIDE, SW_SHOW, TPM_RETURNCMD, TPM_RIGHTBUTTON,
+    WHEEL_DELTA, WM_CLOSE, WM_DESTROY, WM_LBUTTONDOWN, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_PAINT,
+    WM_RBUTTONUP, WNDCLASSW, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
 };
 
 static CLASS_NAME: &[u16] = &[
@@ -23,31 +30,44 @@ static CLASS_NAME: &[u16] = &[
     'c' as u16, 'k' as u16, 'e' as u16, 'r' as u16, 0,
 ];
 
-const WINDOW_WIDTH: i32 = 280;
-const WINDOW_HEIGHT: i32 = 400;
 const PADDING: i32 = 20;
+/// Room for the title above the grid.
+const HEADER_HEIGHT: i32 = 50;
 const COLOR_SIZE: i32 = 50;
+/// Swatches shrink down to this when the rows don't fit on screen.
+const MIN_COLOR_SIZE: i32 = 20;
 const COLOR_GAP: i32 = 12;
+const MIN_COLUMNS: usize = 4;
+const MAX_COLUMNS: usize = 10;
 const CORNER_RADIUS: i32 = 16;
 const CUSTOM_BUTTON_HEIGHT: i32 = 36;
-
-pub const PRESET_COLORS: &[u32] = &[
-    0x00000000, // Black
-    0x00FFFFFF, // White
-    0x00303030, // Dark Gray
-    0x00808080, // Gray
-    0x0040A0FF, // Amber
-    0x0060C0FF, // Warm
-    0x0080D0FF, // Soft
-    0x00A0E0FF, // Cream
-    0x00507DCD, // Sunset
-    0x00889EDE, // Blush
-    0x008EBCF0, // Peach
-    0x00B5D8F5, // Apricot
-    0x00CD9E7D, // Sky
-    0x00E0C090, // Light Blue
-    0x00F0D8A0, // Pale Blue
-    0x00F5E6C8, // Ice
+const CUSTOM_BUTTON_MARGIN: i32 = 10;
+
+// Commands in the swatch context menu
+const CMD_RENAME: usize = 1;
+const CMD_REPLACE_CURRENT: usize = 2;
+const CMD_REPLACE_CUSTOM: usize = 3;
+const CMD_MOVE_EARLIER: usize = 4;
+const CMD_MOVE_LATER: usize = 5;
+const CMD_DELETE: usize = 6;
+
+pub const PRESET_COLORS: &[(&str, u32)] = &[
+    ("Black", 0x00000000),
+    ("White", 0x00FFFFFF),
+    ("Dark Gray", 0x00303030),
+    ("Gray", 0x00808080),
+    ("Amber", 0x0040A0FF),
+    ("Warm", 0x0060C0FF),
+    ("Soft", 0x0080D0FF),
+    ("Cream", 0x00A0E0FF),
+    ("Sunset", 0x00507DCD),
+    ("Blush", 0x00889EDE),
+    ("Peach", 0x008EBCF0),
+    ("Apricot", 0x00B5D8F5),
+    ("Sky", 0x00CD9E7D),
+    ("Light Blue", 0x00E0C090),
+    ("Pale Blue", 0x00F0D8A0),
+    ("Ice", 0x00F5E6C8),
 ];
 
 // Result codes stored in GWLP_USERDATA high bits
@@ -58,25 +78,93 @@ const RESULT_CUSTOM: isize = 2;
 struct PickerState {
     selected_color: u32,
     result_type: isize,
+    /// Tile under the mouse; the one past the last preset is the add tile.
     hover_index: i32,
     hover_custom: bool,
+    /// Overlay color when the picker opened, for adding and replacing.
+    current_color: u32,
+    /// Presets the keymap refers to by number, which can't be deleted.
+    keep: usize,
+    grid: Grid,
+    /// First row shown when there are more rows than fit on screen.
+    scroll: usize,
+    screen_height: i32,
+}
+
+/// Swatch grid sized for the number of tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Grid {
+    columns: usize,
+    /// Rows shown at once; the rest scroll with the mouse wheel.
+    rows: usize,
+    size: i32,
+    width: i32,
+    height: i32,
 }
 
+impl Grid {
+    /// How far `tiles` tiles can scroll, in rows.
+    fn max_scroll(self, tiles: usize) -> usize {
+        tiles.div_ceil(self.columns).saturating_sub(self.rows)
+    }
+
+    /// Row and column of tile `index` on screen, or `None` while it is
+    /// scrolled out of view.
+    fn cell(self, index: usize, scroll: usize) -> Option<(usize, usize)> {
+        let row = (index / self.columns).checked_sub(scroll)?;
+        (row < self.rows).then_some((row, index % self.columns))
+    }
+}
+
+/// Presets being edited; copied back to the caller when the picker closes.
+static PICKER_PRESETS: Mutex<Vec<Preset>> = Mutex::new(Vec::new());
+
 static mut PICKER_STATE: PickerState = PickerState {
     selected_color: 0,
     result_type: RESULT_NONE,
     hover_index: -1,
     hover_custom: false,
+    current_color: 0,
+    keep: 0,
+    grid: Grid {
+        columns: MIN_COLUMNS,
+        rows: 1,
+        size: COLOR_SIZE,
+        width: 0,
+        height: 0,
+    },
+    scroll: 0,
+    screen_height: 0,
 };
 
-pub fn show_color_picker(current_color: u32) -> Option<u32> {
+/// Shows the preset grid. Presets can be renamed, replaced, moved and
+/// deleted from their context menu, and the add tile saves `current_color`
+/// as a new one; edits are written back to `presets`. The first `keep`
+/// presets can't be deleted.
+pub fn show_color_picker(
+    current_color: u32,
+    presets: &mut Vec<Preset>,
+    keep: usize,
+) -> Option<u32> {
+    if let Ok(mut list) = PICKER_PRESETS.lock() {
+        *list = presets.clone();
+    }
+
     unsafe {
+        let screen_width = GetSystemMetrics(SM_CXSCREEN);
+        let screen_height = GetSystemMetrics(SM_CYSCREEN);
         PICKER_STATE = PickerState {
             selected_color: 0,
             result_type: RESULT_NONE,
             hover_index: -1,
             hover_custom: false,
+            current_color,
+            keep,
+            grid: grid_for(presets.len() + 1, screen_height),
+            scroll: 0,
+            screen_height,
         };
+        let grid = PICKER_STATE.grid;
 
         let hinstance = GetModuleHandleW(null_mut());
         if hinstance.is_null() {
@@ -99,10 +187,8 @@ pub fn show_color_picker(current_color: u32) -> Option<u32> {
         RegisterClassW(&wc);
 
         // Center on screen
-        let screen_width = GetSystemMetrics(SM_CXSCREEN);
-        let screen_height = GetSystemMetrics(SM_CYSCREEN);
-        let x = (screen_width - WINDOW_WIDTH) / 2;
-        let y = (screen_height - WINDOW_HEIGHT) / 2;
+        let x = (screen_width - grid.width) / 2;
+        let y = (screen_height - grid.height) / 2;
 
         let hwnd = CreateWindowExW(
             WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
@@ -111,8 +197,8 @@ pub fn show_color_picker(current_color: u32) -> Option<u32> {
             WS_POPUP,
             x,
             y,
-            WINDOW_WIDTH,
-            WINDOW_HEIGHT,
+            grid.width,
+            grid.height,
             null_mut(),
             null_mut(),
             hinstance,
@@ -124,7 +210,7 @@ pub fn show_color_picker(current_color: u32) -> Option<u32> {
         }
 
         // Rounded corners
-        let rgn = CreateRoundRectRgn(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT, CORNER_RADIUS, CORNER_RADIUS);
+        let rgn = CreateRoundRectRgn(0, 0, grid.width, grid.height, CORNER_RADIUS, CORNER_RADIUS);
         SetWindowRgn(hwnd, rgn, 1);
 
         SetWindowPos(
@@ -155,6 +241,10 @@ pub fn show_color_picker(current_color: u32) -> Option<u32> {
             }
         }
 
+        if let Ok(list) = PICKER_PRESETS.lock() {
+            *presets = list.clone();
+        }
+
         // Check result
         match PICKER_STATE.result_type {
             RESULT_PRESET => Some(PICKER_STATE.selected_color),
@@ -185,37 +275,70 @@ fn show_system_color_picker(current_color: u32) -> Option<u32> {
     }
 }
 
-fn get_color_rect(index: usize) -> RECT {
-    let cols = 4;
-    let row = (index / cols) as i32;
-    let col = (index % cols) as i32;
+/// Lays out `tiles` swatches in a roughly square grid, at least
+/// `MIN_COLUMNS` wide, shrinking them if the window would not fit on a
+/// screen `screen_height` pixels tall. Rows that still don't fit scroll.
+fn grid_for(tiles: usize, screen_height: i32) -> Grid {
+    let columns = ((tiles as f64).sqrt().ceil() as usize).clamp(MIN_COLUMNS, MAX_COLUMNS);
+    let all_rows = tiles.div_ceil(columns).max(1);
+
+    let chrome = 2 * PADDING + HEADER_HEIGHT + CUSTOM_BUTTON_MARGIN + CUSTOM_BUTTON_HEIGHT;
+    let available = (screen_height * 9 / 10 - chrome).max(0);
+    let size = (available / all_rows as i32 - COLOR_GAP).clamp(MIN_COLOR_SIZE, COLOR_SIZE);
+    let fit = (available / (size + COLOR_GAP)).max(1) as usize;
+    let rows = all_rows.min(fit);
+
+    let cols = columns as i32;
+    Grid {
+        columns,
+        rows,
+        size,
+        width: 2 * PADDING + cols * size + (cols - 1) * COLOR_GAP,
+        height: chrome + rows as i32 * (size + COLOR_GAP),
+    }
+}
 
-    let x = PADDING + col * (COLOR_SIZE + COLOR_GAP);
-    let y = PADDING + 50 + row * (COLOR_SIZE + COLOR_GAP);
+/// Where tile `index` is drawn, or `None` while it is scrolled out of view.
+fn get_color_rect(index: usize) -> Option<RECT> {
+    let (grid, scroll) = unsafe { (PICKER_STATE.grid, PICKER_STATE.scroll) };
+    let (row, col) = grid.cell(index, scroll)?;
 
-    RECT {
+    let x = PADDING + col as i32 * (grid.size + COLOR_GAP);
+    let y = PADDING + HEADER_HEIGHT + row as i32 * (grid.size + COLOR_GAP);
+
+    Some(RECT {
         left: x,
         top: y,
-        right: x + COLOR_SIZE,
-        bottom: y + COLOR_SIZE,
-    }
+        right: x + grid.size,
+        bottom: y + grid.size,
+    })
+}
+
+fn picker_presets() -> Vec<Preset> {
+    PICKER_PRESETS.lock().map(|p| p.clone()).unwrap_or_default()
 }
 
 fn get_custom_button_rect() -> RECT {
-    let rows = PRESET_COLORS.len().div_ceil(4);
-    let y = PADDING + 50 + (rows as i32) * (COLOR_SIZE + COLOR_GAP) + 10;
+    let grid = unsafe { PICKER_STATE.grid };
+    let y = PADDING
+        + HEADER_HEIGHT
+        + (grid.rows as i32) * (grid.size + COLOR_GAP)
+        + CUSTOM_BUTTON_MARGIN;
 
     RECT {
         left: PADDING,
         top: y,
-        right: WINDOW_WIDTH - PADDING,
+        right: grid.width - PADDING,
         bottom: y + CUSTOM_BUTTON_HEIGHT,
     }
 }
 
+/// Index of the tile at `(x, y)`, counting the add tile, or -1.
 fn hit_test(x: i32, y: i32) -> i32 {
-    for (i, _) in PRESET_COLORS.iter().enumerate() {
-        let rect = get_color_rect(i);
+    for i in 0..=picker_presets().len() {
+        let Some(rect) = get_color_rect(i) else {
+            continue;
+        };
         if x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom {
             return i as i32;
         }
@@ -228,6 +351,140 @@ fn hit_test_custom(x: i32, y: i32) -> bool {
     x >= rect.left && x < rect.right && y >= rect.top && y < rect.bottom
 }
 
+/// Resizes the window after presets were added or removed.
+unsafe fn relayout(hwnd: HWND) {
+    let tiles = picker_presets().len() + 1;
+    let grid = grid_for(tiles, PICKER_STATE.screen_height);
+    PICKER_STATE.grid = grid;
+    PICKER_STATE.scroll = PICKER_STATE.scroll.min(grid.max_scroll(tiles));
+    PICKER_STATE.hover_index = -1;
+    SetWindowPos(
+        hwnd,
+        null_mut(),
+        0,
+        0,
+        grid.width,
+        grid.height,
+        SWP_NOMOVE | SWP_NOZORDER,
+    );
+    let rgn = CreateRoundRectRgn(0, 0, grid.width, grid.height, CORNER_RADIUS, CORNER_RADIUS);
+    SetWindowRgn(hwnd, rgn, 1);
+    InvalidateRect(hwnd, null_mut(), 0);
+}
+
+/// Runs a dialog with the picker hidden, since the picker stays on top.
+unsafe fn with_picker_hidden<T>(hwnd: HWND, dialog: impl FnOnce() -> T) -> T {
+    ShowWindow(hwnd, SW_HIDE);
+    let result = dialog();
+    ShowWindow(hwnd, SW_SHOW);
+    result
+}
+
+/// Saves the color the picker was opened with as a new preset.
+unsafe fn add_preset(hwnd: HWND) {
+    let color = PICKER_STATE.current_color;
+    let name = with_picker_hidden(hwnd, || {
+        show_input_dialog("New Preset", &color::to_hex(color))
+    });
+    if let Some(name) = name {
+        if let Ok(mut list) = PICKER_PRESETS.lock() {
+            list.push(Preset { name, color });
+        }
+        // Keep the add tile in view
+        PICKER_STATE.scroll = usize::MAX;
+        relayout(hwnd);
+    }
+}
+
+/// Shows the context menu for preset `index` at client point `(x, y)`.
+unsafe fn edit_preset(hwnd: HWND, index: usize, x: i32, y: i32) {
+    let count = picker_presets().len();
+    let flags = |enabled: bool| {
+        if enabled {
+            MF_STRING
+        } else {
+            MF_STRING | MF_GRAYED
+        }
+    };
+
+    let menu = CreatePopupMenu();
+    if menu.is_null() {
+        return;
+    }
+    let items = [
+        (CMD_RENAME, "Rename...", true),
+        (CMD_REPLACE_CURRENT, "Replace with Current Color", true),
+        (CMD_REPLACE_CUSTOM, "Replace with Custom Color...", true),
+        (CMD_MOVE_EARLIER, "Move Earlier", index > 0),
+        (CMD_MOVE_LATER, "Move Later", index + 1 < count),
+    ];
+    let mut labels = Vec::new();
+    for (id, text, enabled) in items {
+        let label = wide_str(text);
+        AppendMenuW(menu, flags(enabled), id, label.as_ptr());
+        labels.push(label);
+    }
+    AppendMenuW(menu, MF_SEPARATOR, 0, null_mut());
+    let delete = wide_str("Delete");
+    AppendMenuW(
+        menu,
+        flags(count > PICKER_STATE.keep),
+        CMD_DELETE,
+        delete.as_ptr(),
+    );
+
+    let mut pt = POINT { x, y };
+    ClientToScreen(hwnd, &mut pt);
+    let command = TrackPopupMenu(
+        menu,
+        TPM_RETURNCMD | TPM_RIGHTBUTTON,
+        pt.x,
+        pt.y,
+        0,
+        hwnd,
+        null_mut(),
+    );
+    DestroyMenu(menu);
+
+    let Some(preset) = picker_presets().get(index).cloned() else {
+        return;
+    };
+    let edit = |apply: &dyn Fn(&mut Vec<Preset>)| {
+        if let Ok(mut list) = PICKER_PRESETS.lock() {
+            apply(&mut list);
+        }
+    };
+    match command as usize {
+        CMD_RENAME => {
+            if let Some(name) =
+                with_picker_hidden(hwnd, || show_input_dialog("Rename Preset", &preset.name))
+            {
+                edit(&|list| list[index].name = name.clone());
+            }
+        }
+        CMD_REPLACE_CURRENT => {
+            let color = PICKER_STATE.current_color;
+            edit(&|list| list[index].color = color);
+        }
+        CMD_REPLACE_CUSTOM => {
+            if let Some(color) = with_picker_hidden(hwnd, || show_system_color_picker(preset.color))
+            {
+                edit(&|list| list[index].color = color);
+            }
+        }
+        CMD_MOVE_EARLIER => edit(&|list| list.swap(index, index - 1)),
+        CMD_MOVE_LATER => edit(&|list| list.swap(index, index + 1)),
+        CMD_DELETE => {
+            edit(&|list| {
+                list.remove(index);
+            });
+            relayout(hwnd);
+        }
+        _ => {}
+    }
+    InvalidateRect(hwnd, null_mut(), 0);
+}
+
 unsafe extern "system" fn picker_window_proc(
     hwnd: HWND,
     msg: u32,
@@ -247,15 +504,35 @@ unsafe extern "system" fn picker_window_proc(
             FillRect(hdc, &client_rect, bg_brush);
             DeleteObject(bg_brush as _);
 
-            // Title
+            // Title, naming the tile under the mouse
             SetBkMode(hdc, TRANSPARENT as i32);
             SetTextColor(hdc, 0x00FFFFFF);
-            let title = wide_str("Color");
-            TextOutW(hdc, PADDING, PADDING, title.as_ptr(), 5);
+            let presets = picker_presets();
+            let hover = PICKER_STATE.hover_index;
+            let title = if hover >= 0 && (hover as usize) < presets.len() {
+                let preset = &presets[hover as usize];
+                format!("{}  {}", preset.name, color::to_hex(preset.color))
+            } else if hover as usize == presets.len() {
+                "Add Current Color".to_string()
+            } else if PICKER_STATE.grid.max_scroll(presets.len() + 1) > 0 {
+                "Color  (scroll for more)".to_string()
+            } else {
+                "Color".to_string()
+            };
+            let title = wide_str(&title);
+            TextOutW(
+                hdc,
+                PADDING,
+                PADDING,
+                title.as_ptr(),
+                title.len() as i32 - 1,
+            );
 
             // Color swatches
-            for (i, &color) in PRESET_COLORS.iter().enumerate() {
-                let rect = get_color_rect(i);
+            for (i, &color) in presets.iter().map(|p| &p.color).enumerate() {
+                let Some(rect) = get_color_rect(i) else {
+                    continue;
+                };
                 let is_hover = PICKER_STATE.hover_index == i as i32;
 
                 if is_hover {
@@ -289,9 +566,47 @@ unsafe extern "system" fn picker_window_proc(
                 }
             }
 
+            // Add tile, once scrolled into view
+            if let Some(mut add_rect) = get_color_rect(presets.len()) {
+                let add_color = if hover as usize == presets.len() {
+                    0x00404040
+                } else {
+                    0x00353535
+                };
+                let add_brush = CreateSolidBrush(add_color);
+                let old_brush = SelectObject(hdc, add_brush as _);
+                let pen = CreatePen(PS_SOLID, 1, 0x00505050);
+                let old_pen = SelectObject(hdc, pen as _);
+                RoundRect(
+                    hdc,
+                    add_rect.left,
+                    add_rect.top,
+                    add_rect.right,
+                    add_rect.bottom,
+                    8,
+                    8,
+                );
+                SelectObject(hdc, old_brush);
+                SelectObject(hdc, old_pen);
+                DeleteObject(add_brush as _);
+                DeleteObject(pen as _);
+                let plus = wide_str("+");
+                DrawTextW(
+                    hdc,
+                    plus.as_ptr(),
+                    1,
+                    &mut add_rect,
+                    DT_CENTER | DT_VCENTER | DT_SINGLELINE,
+                );
+            }
+
             // Custom color button
             let custom_rect = get_custom_button_rect();
-            let btn_color = if PICKER_STATE.hover_custom { 0x00404040 } else { 0x00353535 };
+            let btn_color = if PICKER_STATE.hover_custom {
+                0x00404040
+            } else {
+                0x00353535
+            };
             let btn_brush = CreateSolidBrush(btn_color);
             let old_brush = SelectObject(hdc, btn_brush as _);
             let pen = CreatePen(PS_SOLID, 1, 0x00505050);
@@ -316,7 +631,13 @@ unsafe extern "system" fn picker_window_proc(
             let btn_text = wide_str("Custom Color...");
             let text_x = custom_rect.left + (custom_rect.right - custom_rect.left) / 2 - 50;
             let text_y = custom_rect.top + (CUSTOM_BUTTON_HEIGHT - 16) / 2;
-            TextOutW(hdc, text_x, text_y, btn_text.as_ptr(), btn_text.len() as i32 - 1);
+            TextOutW(
+                hdc,
+                text_x,
+                text_y,
+                btn_text.as_ptr(),
+                btn_text.len() as i32 - 1,
+            );
 
             EndPaint(hwnd, &ps);
             0
@@ -327,7 +648,9 @@ unsafe extern "system" fn picker_window_proc(
             let new_hover = hit_test(x, y);
             let new_hover_custom = hit_test_custom(x, y);
 
-            if new_hover != PICKER_STATE.hover_index || new_hover_custom != PICKER_STATE.hover_custom {
+            if new_hover != PICKER_STATE.hover_index
+                || new_hover_custom != PICKER_STATE.hover_custom
+            {
                 PICKER_STATE.hover_index = new_hover;
                 PICKER_STATE.hover_custom = new_hover_custom;
                 InvalidateRect(hwnd, null_mut(), 0);
@@ -338,24 +661,47 @@ unsafe extern "system" fn picker_window_proc(
             let x = (lparam & 0xFFFF) as i16 as i32;
             let y = ((lparam >> 16) & 0xFFFF) as i16 as i32;
             let index = hit_test(x, y);
+            let presets = picker_presets();
 
-            if index >= 0 && (index as usize) < PRESET_COLORS.len() {
-                PICKER_STATE.selected_color = PRESET_COLORS[index as usize];
+            if index >= 0 && (index as usize) < presets.len() {
+                PICKER_STATE.selected_color = presets[index as usize].color;
                 PICKER_STATE.result_type = RESULT_PRESET;
                 DestroyWindow(hwnd);
+            } else if index as usize == presets.len() {
+                add_preset(hwnd);
             } else if hit_test_custom(x, y) {
                 PICKER_STATE.result_type = RESULT_CUSTOM;
                 DestroyWindow(hwnd);
             }
             0
         }
-        WM_CLOSE => {
-            DestroyWindow(hwnd);
+        WM_MOUSEWHEEL => {
+            let delta = ((wparam >> 16) & 0xFFFF) as u16 as i16 as i32;
+            let notches = delta / WHEEL_DELTA as i32;
+            let max = PICKER_STATE.grid.max_scroll(picker_presets().len() + 1);
+            // Wheel up (positive) shows earlier rows
+            let scroll = (PICKER_STATE.scroll as i32 - notches).clamp(0, max as i32) as usize;
+            if scroll != PICKER_STATE.scroll {
+                PICKER_STATE.scroll = scroll;
+                PICKER_STATE.hover_index = -1;
+                InvalidateRect(hwnd, null_mut(), 0);
+            }
+            0
+        }
+        WM_RBUTTONUP => {
+            let x = (lparam & 0xFFFF) as i16 as i32;
+            let y = ((lparam >> 16) & 0xFFFF) as i16 as i32;
+            let index = hit_test(x, y);
+            if index >= 0 && (index as usize) < picker_presets().len() {
+                edit_preset(hwnd, index as usize, x, y);
+            }
             0
         }
-        WM_DESTROY => {
+        WM_CLOSE => {
+            DestroyWindow(hwnd);
             0
         }
+        WM_DESTROY => 0,
         _ => DefWindowProcW(hwnd, msg, wparam, lparam),
     }
 }
@@ -363,3 +709,80 @@ unsafe extern "system" fn picker_window_proc(
 fn wide_str(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_grow_with_the_tile_count() {
+        let columns = |tiles| grid_for(tiles, 1080).columns;
+        assert_eq!(columns(1), MIN_COLUMNS);
+        assert_eq!(columns(16), 4);
+        assert_eq!(columns(17), 5);
+        assert_eq!(columns(50), 8);
+        assert_eq!(columns(101), MAX_COLUMNS);
+        assert_eq!(columns(501), MAX_COLUMNS);
+    }
+
+    #[test]
+    fn tiles_shrink_before_rows_scroll() {
+        assert_eq!(
+            grid_for(1, 1080),
+            Grid {
+                columns: 4,
+                rows: 1,
+                size: COLOR_SIZE,
+                width: 276,
+                height: 198,
+            }
+        );
+        let grid = grid_for(101, 1080);
+        assert_eq!((grid.rows, grid.size), (11, COLOR_SIZE));
+        let grid = grid_for(200, 1080);
+        assert_eq!((grid.rows, grid.size), (20, 29));
+        assert_eq!(grid.max_scroll(200), 0);
+
+        let grid = grid_for(501, 1080);
+        assert_eq!((grid.rows, grid.size), (26, MIN_COLOR_SIZE));
+        assert_eq!(grid.max_scroll(501), 51 - 26);
+    }
+
+    #[test]
+    fn window_fits_on_screen() {
+        for screen_height in [480, 768, 1080, 2160] {
+            for tiles in 1..=501 {
+                let grid = grid_for(tiles, screen_height);
+                assert!(
+                    grid.height <= screen_height * 9 / 10,
+                    "{} tiles on {}",
+                    tiles,
+                    screen_height
+                );
+                let all_rows = tiles.div_ceil(grid.columns);
+                assert_eq!(grid.rows + grid.max_scroll(tiles), all_rows);
+            }
+        }
+        // Too small for even one row: still shows one
+        assert_eq!(grid_for(20, 100).rows, 1);
+    }
+
+    #[test]
+    fn scrolled_out_tiles_have_no_cell() {
+        let grid = Grid {
+            columns: 4,
+            rows: 2,
+            size: COLOR_SIZE,
+            width: 0,
+            height: 0,
+        };
+        assert_eq!(grid.cell(0, 0), Some((0, 0)));
+        assert_eq!(grid.cell(7, 0), Some((1, 3)));
+        assert_eq!(grid.cell(8, 0), None);
+        assert_eq!(grid.cell(3, 1), None);
+        assert_eq!(grid.cell(9, 1), Some((1, 1)));
+        assert_eq!(grid.cell(12, 1), None);
+        assert_eq!(grid.max_scroll(9), 1);
+        assert_eq!(grid.max_scroll(8), 0);
+    }
+}
@@ -1,17 +1,26 @@
-use crate::monitor::MonitorInfo;
+use crate::color;
+use crate::monitor::{self, MonitorInfo};
+use crate::patch::{Patch, PatchWindow};
+use crate::pattern::Pattern;
+use std::cell::{Cell, RefCell};
 use std::mem::zeroed;
 use std::ptr::null_mut;
-use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM};
 use windows_sys::Win32::Graphics::Gdi::{
-    BeginPaint, CreateSolidBrush, DeleteObject, EndPaint, FillRect, InvalidateRect, PAINTSTRUCT,
+    BeginPaint, CreateSolidBrush, DeleteObject, DrawTextW, EndPaint, FillRect, GetDC,
+    GetStockObject, InvalidateRect, ReleaseDC, SelectObject, SetBkMode, SetTextColor,
+    DEFAULT_GUI_FONT, DT_BOTTOM, DT_CALCRECT, DT_LEFT, DT_NOPREFIX, DT_SINGLELINE, DT_TOP, HDC,
+    PAINTSTRUCT, TRANSPARENT,
 };
 use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    CreateWindowExW, DefWindowProcW, DestroyWindow, GetWindowLongPtrW, RegisterClassW,
-    SetWindowLongPtrW, SetWindowPos, ShowCursor, ShowWindow, CS_HREDRAW, CS_VREDRAW,
-    GWLP_USERDATA, HWND_TOPMOST, SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW, SW_HIDE, SW_SHOW,
-    WM_KEYDOWN, WM_LBUTTONDOWN, WM_PAINT, WM_RBUTTONDOWN, WM_SETCURSOR, WNDCLASSW, WS_EX_TOPMOST,
-    WS_POPUP,
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetClientRect, GetWindowLongPtrW,
+    RegisterClassW, SetCursor, SetLayeredWindowAttributes, SetWindowLongPtrW, SetWindowPos,
+    ShowCursor, ShowWindow, CS_HREDRAW, CS_VREDRAW, GWLP_USERDATA, GWL_EXSTYLE, HWND_TOPMOST,
+    LWA_ALPHA, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE, SWP_SHOWWINDOW, SW_HIDE, SW_SHOW,
+    SW_SHOWNOACTIVATE, WHEEL_DELTA, WM_KEYDOWN, WM_LBUTTONDOWN, WM_MOUSEWHEEL, WM_PAINT,
+    WM_RBUTTONDOWN, WM_SETCURSOR, WNDCLASSW, WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
+    WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
 };
 
 static CLASS_NAME: &[u16] = &[
@@ -20,14 +29,65 @@ static CLASS_NAME: &[u16] = &[
 
 const VK_ESCAPE: i32 = 0x1B;
 
-static mut HIDE_CALLBACK: Option<Box<dyn Fn()>> = None;
+/// Cell size in pixels for the checkerboard and grid patterns.
+const PATTERN_CELL: i32 = 64;
+const GRADIENT_STEPS: i32 = 256;
+/// Distance of the caption from the bottom-left corner, and of the OSD from
+/// the top-left one.
+const CAPTION_MARGIN: i32 = 8;
+const EMPTY_RECT: RECT = RECT {
+    left: 0,
+    top: 0,
+    right: 0,
+    bottom: 0,
+};
+
+static mut HIDE_CALLBACK: Option<Box<dyn Fn(HideTrigger)>> = None;
+/// Receives the virtual-key code of keys other than Escape.
+static mut KEY_CALLBACK: Option<Box<dyn Fn(u32)>> = None;
+/// Receives mouse wheel turns in notches, positive away from the user.
+static mut WHEEL_CALLBACK: Option<Box<dyn Fn(i32)>> = None;
+
+/// Input on an overlay window that may hide it, depending on the active
+/// dismissal rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HideTrigger {
+    Click,
+    Escape,
+}
+
+/// Paint state, pointed to by the window's GWLP_USERDATA.
+struct WindowState {
+    color: Cell<u32>,
+    pattern: Cell<Pattern>,
+    /// Confines color and pattern to a patch while set.
+    window: Cell<Option<PatchWindow>>,
+    /// Replaces color, pattern and window while set.
+    patch: Cell<Option<Patch>>,
+    /// Small text in the bottom-left corner, nul-terminated.
+    caption: RefCell<Option<Vec<u16>>>,
+    /// Lets input through to the windows underneath, for the dimmer.
+    click_through: Cell<bool>,
+    /// Information text in the top-left corner, nul-terminated.
+    osd: RefCell<Option<Vec<u16>>>,
+    /// How strongly the OSD shows, 0.0-1.0, while it fades out.
+    osd_level: Cell<f64>,
+}
 
 pub struct ColorWindow {
     hwnd: HWND,
+    /// Monitor number and resolution, for the OSD.
+    monitor: String,
+    // Boxed so the pointer stored in the window stays valid
+    state: Box<WindowState>,
+    /// Whether `show` hid the cursor, so `hide` and drop restore it once.
+    hides_cursor: Cell<bool>,
+    /// Corner the OSD text covers, repainted when only the OSD changes.
+    osd_area: Cell<RECT>,
 }
 
 impl ColorWindow {
-    pub fn new(monitor: &MonitorInfo, color: u32) -> Option<Self> {
+    pub fn new(monitor: &MonitorInfo, color: u32, pattern: Pattern) -> Option<Self> {
         unsafe {
             let hinstance = GetModuleHandleW(null_mut());
             if hinstance.is_null() {
@@ -49,8 +109,9 @@ impl ColorWindow {
 
             RegisterClassW(&wc);
 
+            // Layered for fades and partial opacity
             let hwnd = CreateWindowExW(
-                WS_EX_TOPMOST,
+                WS_EX_TOPMOST | WS_EX_LAYERED,
                 CLASS_NAME.as_ptr(),
                 CLASS_NAME.as_ptr(),
                 WS_POPUP,
@@ -68,14 +129,57 @@ impl ColorWindow {
                 return None;
             }
 
-            let window = ColorWindow { hwnd };
-            window.set_color(color);
+            let state = Box::new(WindowState {
+                color: Cell::new(color),
+                pattern: Cell::new(pattern),
+                window: Cell::new(None),
+                patch: Cell::new(None),
+                caption: RefCell::new(None),
+                click_through: Cell::new(false),
+                osd: RefCell::new(None),
+                osd_level: Cell::new(1.0),
+            });
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, &*state as *const WindowState as isize);
+
+            let monitor = format!(
+                "Monitor {}  {}x{}{}",
+                monitor.number,
+                monitor.rect.width(),
+                monitor.rect.height(),
+                if monitor.is_primary { " (primary)" } else { "" }
+            );
+            let window = ColorWindow {
+                hwnd,
+                monitor,
+                state,
+                hides_cursor: Cell::new(false),
+                osd_area: Cell::new(EMPTY_RECT),
+            };
+            // A layered window stays invisible until it has an alpha
+            window.set_alpha(255);
+            window.redraw();
 
             Some(window)
         }
     }
 
     pub fn show(&self) {
+        if self.state.click_through.get() {
+            // Don't take focus or the cursor from what is being dimmed
+            unsafe {
+                SetWindowPos(
+                    self.hwnd,
+                    HWND_TOPMOST as HWND,
+                    0,
+                    0,
+                    0,
+                    0,
+                    SWP_SHOWWINDOW | SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+                );
+                ShowWindow(self.hwnd, SW_SHOWNOACTIVATE);
+            }
+            return;
+        }
         unsafe {
             SetWindowPos(
                 self.hwnd,
@@ -87,26 +191,113 @@ impl ColorWindow {
                 SWP_SHOWWINDOW | SWP_NOMOVE | SWP_NOSIZE,
             );
             ShowWindow(self.hwnd, SW_SHOW);
-            ShowCursor(0);
+            // ShowCursor keeps a count; showing twice must not hide it twice
+            if !self.hides_cursor.replace(true) {
+                ShowCursor(0);
+            }
         }
     }
 
     pub fn hide(&self) {
         unsafe {
             ShowWindow(self.hwnd, SW_HIDE);
-            ShowCursor(1);
+        }
+        self.restore_cursor();
+    }
+
+    fn restore_cursor(&self) {
+        if self.hides_cursor.replace(false) {
+            unsafe {
+                ShowCursor(1);
+            }
+        }
+    }
+
+    /// Passes mouse and keyboard input through to the windows underneath.
+    /// Call before `show`.
+    pub fn set_click_through(&self) {
+        self.state.click_through.set(true);
+        unsafe {
+            let style = GetWindowLongPtrW(self.hwnd, GWL_EXSTYLE);
+            let extra = WS_EX_TRANSPARENT | WS_EX_NOACTIVATE | WS_EX_TOOLWINDOW;
+            SetWindowLongPtrW(self.hwnd, GWL_EXSTYLE, style | extra as isize);
+        }
+    }
+
+    /// Opacity of the whole window, 0 (invisible) to 255.
+    pub fn set_alpha(&self, alpha: u8) {
+        unsafe {
+            SetLayeredWindowAttributes(self.hwnd, 0, alpha, LWA_ALPHA);
         }
     }
 
     pub fn set_color(&self, color: u32) {
+        self.state.color.set(color);
+        self.redraw();
+    }
+
+    pub fn set_pattern(&self, pattern: Pattern) {
+        self.state.pattern.set(pattern);
+        self.redraw();
+    }
+
+    pub fn set_window(&self, window: Option<PatchWindow>) {
+        self.state.window.set(window);
+        self.redraw();
+    }
+
+    pub fn set_patch(&self, patch: Option<Patch>) {
+        self.state.patch.set(patch);
+        self.redraw();
+    }
+
+    pub fn set_caption(&self, caption: Option<&str>) {
+        *self.state.caption.borrow_mut() = caption.map(wide_str);
+        self.redraw();
+    }
+
+    /// Shows `lines` and this window's monitor in the top-left corner, or
+    /// clears the OSD with `None`.
+    pub fn set_osd(&self, lines: Option<&[String]>) {
+        let text = lines.map(|lines| {
+            let mut text = lines.join("\n");
+            text.push('\n');
+            text += &self.monitor;
+            wide_str(&text)
+        });
+        // Clear what the old text covered, then paint what the new one does
+        self.redraw_osd();
+        let area = match &text {
+            Some(text) => unsafe { measure_osd(self.hwnd, text) },
+            None => EMPTY_RECT,
+        };
+        self.osd_area.set(area);
+        *self.state.osd.borrow_mut() = text;
+        self.redraw_osd();
+    }
+
+    /// Fades the OSD text toward the background; 0.0 hides it.
+    pub fn set_osd_level(&self, level: f64) {
+        self.state.osd_level.set(level);
+        self.redraw_osd();
+    }
+
+    fn redraw_osd(&self) {
+        unsafe {
+            InvalidateRect(self.hwnd, &self.osd_area.get(), 1);
+        }
+    }
+
+    fn redraw(&self) {
         unsafe {
-            SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, color as isize);
             InvalidateRect(self.hwnd, null_mut(), 1);
         }
     }
 
     pub fn destroy(&self) {
+        self.restore_cursor();
         unsafe {
+            SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
             DestroyWindow(self.hwnd);
         }
     }
@@ -118,12 +309,210 @@ impl Drop for ColorWindow {
     }
 }
 
-pub fn set_hide_callback<F: Fn() + 'static>(callback: F) {
+pub fn set_hide_callback<F: Fn(HideTrigger) + 'static>(callback: F) {
     unsafe {
         HIDE_CALLBACK = Some(Box::new(callback));
     }
 }
 
+pub fn set_key_callback<F: Fn(u32) + 'static>(callback: F) {
+    unsafe {
+        KEY_CALLBACK = Some(Box::new(callback));
+    }
+}
+
+pub fn set_wheel_callback<F: Fn(i32) + 'static>(callback: F) {
+    unsafe {
+        WHEEL_CALLBACK = Some(Box::new(callback));
+    }
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+unsafe fn fill(hdc: HDC, rect: &RECT, color: u32) {
+    let brush = CreateSolidBrush(color);
+    FillRect(hdc, rect, brush);
+    DeleteObject(brush as _);
+}
+
+fn win32_rect(rect: monitor::Rect) -> RECT {
+    RECT {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    }
+}
+
+unsafe fn paint_patch(hdc: HDC, rect: &RECT, patch: &Patch) {
+    fill(hdc, rect, patch.background);
+    let area = patch.window.to_rect(&monitor::Rect::from(*rect));
+    fill(hdc, &win32_rect(area), patch.foreground);
+}
+
+unsafe fn paint_window(hdc: HDC, rect: &RECT, color: u32, pattern: Pattern, window: PatchWindow) {
+    fill(hdc, rect, window.background);
+    let area = window.rect(&monitor::Rect::from(*rect));
+    paint_pattern(hdc, &win32_rect(area), color, pattern);
+}
+
+/// Black or white, whichever reads better on `behind`.
+fn ink_for(behind: u32) -> u32 {
+    let (r, g, b) = color::colorref_to_rgb(behind);
+    let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+    if luma > 128.0 {
+        0x00000000
+    } else {
+        0x00FFFFFF
+    }
+}
+
+unsafe fn paint_caption(hdc: HDC, rect: &RECT, text: &[u16], behind: u32) {
+    let ink = ink_for(behind);
+
+    let mut area = RECT {
+        left: rect.left + CAPTION_MARGIN,
+        top: rect.top,
+        right: rect.right - CAPTION_MARGIN,
+        bottom: rect.bottom - CAPTION_MARGIN,
+    };
+    let old_font = SelectObject(hdc, GetStockObject(DEFAULT_GUI_FONT));
+    SetBkMode(hdc, TRANSPARENT as i32);
+    SetTextColor(hdc, ink);
+    DrawTextW(
+        hdc,
+        text.as_ptr(),
+        -1,
+        &mut area,
+        DT_LEFT | DT_BOTTOM | DT_SINGLELINE | DT_NOPREFIX,
+    );
+    SelectObject(hdc, old_font);
+}
+
+/// The corner `text` covers when painted by `paint_osd`, margins included.
+unsafe fn measure_osd(hwnd: HWND, text: &[u16]) -> RECT {
+    let mut area = RECT {
+        left: CAPTION_MARGIN,
+        top: CAPTION_MARGIN,
+        right: CAPTION_MARGIN,
+        bottom: CAPTION_MARGIN,
+    };
+    let hdc = GetDC(hwnd);
+    let old_font = SelectObject(hdc, GetStockObject(DEFAULT_GUI_FONT));
+    DrawTextW(
+        hdc,
+        text.as_ptr(),
+        -1,
+        &mut area,
+        DT_LEFT | DT_TOP | DT_NOPREFIX | DT_CALCRECT,
+    );
+    SelectObject(hdc, old_font);
+    ReleaseDC(hwnd, hdc);
+    RECT {
+        left: 0,
+        top: 0,
+        right: area.right + CAPTION_MARGIN,
+        bottom: area.bottom + CAPTION_MARGIN,
+    }
+}
+
+unsafe fn paint_osd(hdc: HDC, rect: &RECT, text: &[u16], behind: u32, level: f64) {
+    if level <= 0.0 {
+        return;
+    }
+    let ink = color::mix(behind, ink_for(behind), level);
+
+    let mut area = RECT {
+        left: rect.left + CAPTION_MARGIN,
+        top: rect.top + CAPTION_MARGIN,
+        right: rect.right - CAPTION_MARGIN,
+        bottom: rect.bottom - CAPTION_MARGIN,
+    };
+    let old_font = SelectObject(hdc, GetStockObject(DEFAULT_GUI_FONT));
+    SetBkMode(hdc, TRANSPARENT as i32);
+    SetTextColor(hdc, ink);
+    DrawTextW(
+        hdc,
+        text.as_ptr(),
+        -1,
+        &mut area,
+        DT_LEFT | DT_TOP | DT_NOPREFIX,
+    );
+    SelectObject(hdc, old_font);
+}
+
+unsafe fn paint_pattern(hdc: HDC, rect: &RECT, color: u32, pattern: Pattern) {
+    match pattern {
+        Pattern::Solid => fill(hdc, rect, color),
+        Pattern::Gradient => {
+            let width = rect.right - rect.left;
+            for step in 0..GRADIENT_STEPS {
+                let band = RECT {
+                    left: rect.left + width * step / GRADIENT_STEPS,
+                    right: rect.left + width * (step + 1) / GRADIENT_STEPS,
+                    ..*rect
+                };
+                let level = |shift: u32| {
+                    ((color >> shift) & 0xFF) * step as u32 / (GRADIENT_STEPS - 1) as u32
+                };
+                fill(hdc, &band, level(0) | (level(8) << 8) | (level(16) << 16));
+            }
+        }
+        Pattern::Checkerboard => {
+            fill(hdc, rect, 0x00000000);
+            let brush = CreateSolidBrush(color);
+            for (row, top) in (rect.top..rect.bottom)
+                .step_by(PATTERN_CELL as usize)
+                .enumerate()
+            {
+                for (col, left) in (rect.left..rect.right)
+                    .step_by(PATTERN_CELL as usize)
+                    .enumerate()
+                {
+                    if (row + col) % 2 == 0 {
+                        let cell = RECT {
+                            left,
+                            top,
+                            right: (left + PATTERN_CELL).min(rect.right),
+                            bottom: (top + PATTERN_CELL).min(rect.bottom),
+                        };
+                        FillRect(hdc, &cell, brush);
+                    }
+                }
+            }
+            DeleteObject(brush as _);
+        }
+        Pattern::Grid => {
+            fill(hdc, rect, 0x00000000);
+            let brush = CreateSolidBrush(color);
+            for x in (rect.left..rect.right).step_by(PATTERN_CELL as usize) {
+                let line = RECT {
+                    left: x,
+                    right: x + 1,
+                    ..*rect
+                };
+                FillRect(hdc, &line, brush);
+            }
+            for y in (rect.top..rect.bottom).step_by(PATTERN_CELL as usize) {
+                let line = RECT {
+                    top: y,
+                    bottom: y + 1,
+                    ..*rect
+                };
+                FillRect(hdc, &line, brush);
+            }
+            DeleteObject(brush as _);
+        }
+    }
+}
+
+unsafe fn is_click_through(hwnd: HWND) -> bool {
+    let state = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowState;
+    state.as_ref().is_some_and(|s| s.click_through.get())
+}
+
 unsafe extern "system" fn window_proc(
     hwnd: HWND,
     msg: u32,
@@ -132,30 +521,73 @@ unsafe extern "system" fn window_proc(
 ) -> LRESULT {
     match msg {
         WM_PAINT => {
-            let color = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as u32;
+            let state = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const WindowState;
             let mut ps: PAINTSTRUCT = zeroed();
             let hdc = BeginPaint(hwnd, &mut ps);
-            let brush = CreateSolidBrush(color);
-            FillRect(hdc, &ps.rcPaint, brush);
-            DeleteObject(brush as _);
+            if let Some(state) = state.as_ref() {
+                let mut rect: RECT = zeroed();
+                GetClientRect(hwnd, &mut rect);
+                let (color, pattern) = (state.color.get(), state.pattern.get());
+                // What the caption sits on, to pick a readable ink
+                let corner = match (state.patch.get(), state.window.get()) {
+                    (Some(patch), _) => {
+                        paint_patch(hdc, &rect, &patch);
+                        patch.background
+                    }
+                    (None, Some(window)) => {
+                        paint_window(hdc, &rect, color, pattern, window);
+                        window.background
+                    }
+                    (None, None) => {
+                        paint_pattern(hdc, &rect, color, pattern);
+                        color
+                    }
+                };
+                if let Some(caption) = state.caption.borrow().as_ref() {
+                    paint_caption(hdc, &rect, caption, corner);
+                }
+                if let Some(osd) = state.osd.borrow().as_ref() {
+                    paint_osd(hdc, &rect, osd, corner, state.osd_level.get());
+                }
+            }
             EndPaint(hwnd, &ps);
             0
         }
+        // A click-through window has no input of its own to act on
+        WM_SETCURSOR | WM_KEYDOWN | WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MOUSEWHEEL
+            if is_click_through(hwnd) =>
+        {
+            DefWindowProcW(hwnd, msg, wparam, lparam)
+        }
         WM_SETCURSOR => {
-            SetWindowLongPtrW(hwnd, -20, 0);
+            // Keep the cursor hidden; the extended style must stay intact or
+            // the window loses its layering and opacity
+            SetCursor(null_mut());
             1
         }
         WM_KEYDOWN => {
             if wparam as i32 == VK_ESCAPE {
                 if let Some(ref cb) = HIDE_CALLBACK {
-                    cb();
+                    cb(HideTrigger::Escape);
                 }
+            } else if let Some(ref cb) = KEY_CALLBACK {
+                cb(wparam as u32);
             }
             0
         }
         WM_LBUTTONDOWN | WM_RBUTTONDOWN => {
             if let Some(ref cb) = HIDE_CALLBACK {
-                cb();
+                cb(HideTrigger::Click);
+            }
+            0
+        }
+        WM_MOUSEWHEEL => {
+            let delta = ((wparam >> 16) & 0xFFFF) as u16 as i16 as i32;
+            let notches = delta / WHEEL_DELTA as i32;
+            if notches != 0 {
+                if let Some(ref cb) = WHEEL_CALLBACK {
+                    cb(notches);
+                }
             }
             0
         }
@@ -1,10 +1,40 @@
+use crate::cli;
+use crate::color;
+use crate::color_picker::PRESET_COLORS;
+use crate::keymap::{Action, Binding, KeySequence, Keymap, DEFAULT_CHORD_TIMEOUT};
+use crate::monitor::MonitorSelection;
+use crate::patch::{PatchSize, PatchWindow};
+use crate::pattern::Pattern;
+use crate::player::{Sequence, Step};
+use crate::resolve;
+use crate::schedule::{self, Rule};
+use crate::toml::{self, Item, Key, Pos, Table, Value};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 const APP_NAME: &str = "Blanqr";
-const CONFIG_FILE: &str = "config.ini";
+/// Directory name under `$XDG_CONFIG_HOME` on non-Windows systems.
+const XDG_APP_NAME: &str = "blanqr";
+/// Environment variable naming the config file to use.
+const CONFIG_ENV: &str = "BLANQR_CONFIG";
+const CONFIG_FILE: &str = "config.toml";
+/// Pre-TOML `key = value` config, migrated on first load.
+const LEGACY_CONFIG_FILE: &str = "config.ini";
+/// Dimmer steps stop short of invisible and of fully opaque.
+const MIN_DIMMER_LEVEL: u8 = 5;
+const MAX_DIMMER_LEVEL: u8 = 95;
+/// How many colors the tray's Recent section keeps.
+pub const MAX_RECENT_COLORS: usize = 8;
 
-#[derive(Clone)]
+/// Set once from `--config <path>` before the config is first loaded.
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+static RESOLVED_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HotkeyConfig {
     pub modifiers: u32,
     pub key: u32,
@@ -49,99 +79,2029 @@ impl HotkeyConfig {
         parts.push(&key_name);
         parts.join("+")
     }
+
+    pub fn parse(s: &str) -> Option<HotkeyConfig> {
+        let parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        if parts.is_empty() {
+            return None;
+        }
+
+        let mut modifiers = 0u32;
+        let mut key = 0u32;
+
+        for part in &parts {
+            let upper = part.to_uppercase();
+            match upper.as_str() {
+                "CTRL" | "CONTROL" => modifiers |= 0x0002,
+                "ALT" => modifiers |= 0x0001,
+                "SHIFT" => modifiers |= 0x0004,
+                "WIN" | "WINDOWS" => modifiers |= 0x0008,
+                _ => {
+                    // Exactly one key; anything unrecognised (e.g. "Shfit")
+                    // rejects the whole hotkey instead of being skipped.
+                    if key != 0 {
+                        return None;
+                    }
+                    if upper.len() == 1 {
+                        let c = upper.chars().next().unwrap();
+                        if c.is_ascii_alphanumeric() {
+                            key = c as u32;
+                        }
+                    } else if let Some(suffix) = upper.strip_prefix('F') {
+                        if let Ok(n) = suffix.parse::<u32>() {
+                            if (1..=12).contains(&n) {
+                                key = 0x6F + n; // VK_F1 = 0x70
+                            }
+                        }
+                    }
+                    if key == 0 {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        if key != 0 {
+            Some(HotkeyConfig { modifiers, key })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub color: u32,
+}
+
+/// Which input hides the overlay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dismissal {
+    pub on_click: bool,
+    pub on_escape: bool,
+}
+
+impl Default for Dismissal {
+    fn default() -> Self {
+        Dismissal {
+            on_click: true,
+            on_escape: true,
+        }
+    }
+}
+
+/// Everything that decides what the overlay looks like and how it behaves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlaySettings {
+    pub color: u32,
+    pub pattern: Pattern,
+    pub monitors: MonitorSelection,
+    pub dismissal: Dismissal,
+    /// Percent, 1-100, applied in linear light.
+    pub brightness: u8,
+    /// Percent, 1-100; below 100 the desktop shows through.
+    pub opacity: u8,
+    /// Draw a patch on a background instead of filling the monitor.
+    pub window: Option<PatchWindow>,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        OverlaySettings {
+            color: 0x00000000, // Black
+            pattern: Pattern::Solid,
+            monitors: MonitorSelection::default(),
+            dismissal: Dismissal::default(),
+            brightness: 100,
+            opacity: 100,
+            window: None,
+        }
+    }
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub settings: OverlaySettings,
+}
+
+/// Loopback HTTP API, off unless enabled with a token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Required as `Authorization: Bearer <token>` on every request.
+    pub token: String,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            enabled: false,
+            port: 8754,
+            token: String::new(),
+        }
+    }
+}
+
+/// Connection to calibration software driving blanqr as a pattern generator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratorSettings {
+    /// Connect at startup.
+    pub enabled: bool,
+    /// `host:port` the calibration software listens on.
+    pub address: String,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            enabled: false,
+            address: format!("127.0.0.1:{}", resolve::DEFAULT_PORT),
+        }
+    }
+}
+
+/// When to stop the display from going to sleep.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeepAwake {
+    /// Leave it to power management.
+    #[default]
+    Off,
+    /// While the overlay is up, except when it was shown for being idle.
+    Overlay,
+    /// Like `Overlay`, and also while a patch set, sequence or pattern
+    /// generator session runs.
+    Sessions,
+}
+
+impl KeepAwake {
+    pub const ALL: &'static [KeepAwake] =
+        &[KeepAwake::Off, KeepAwake::Overlay, KeepAwake::Sessions];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeepAwake::Off => "off",
+            KeepAwake::Overlay => "overlay",
+            KeepAwake::Sessions => "sessions",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|k| k.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// Showing the overlay after a stretch without input, like a screensaver.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdleSettings {
+    /// Idle time before the overlay shows; `None` turns it off.
+    pub after: Option<Duration>,
+    /// Black unless set.
+    pub color: u32,
+}
+
+/// A see-through overlay that dims or tints a monitor without blocking
+/// input to the windows under it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DimmerSettings {
+    /// Turns the dimmer on and off, separately from the overlay hotkey.
+    pub hotkey: Option<HotkeyConfig>,
+    pub color: u32,
+    /// Opacity in percent when turned on.
+    pub level: u8,
+    /// Percent added or removed by `dimmer +` and `dimmer -`.
+    pub step: u8,
+    /// Monitors to dim; empty follows the overlay's monitor selection.
+    pub monitors: Vec<u32>,
+}
+
+impl Default for DimmerSettings {
+    fn default() -> Self {
+        DimmerSettings {
+            hotkey: None,
+            color: 0x00000000, // Black
+            level: 50,
+            step: 10,
+            monitors: Vec::new(),
+        }
+    }
+}
+
+impl DimmerSettings {
+    /// `level` after `steps` presses of `dimmer +`, or of `dimmer -` when
+    /// negative.
+    pub fn step_level(&self, level: u8, steps: i8) -> u8 {
+        (level as i32 + steps as i32 * self.step as i32)
+            .clamp(MIN_DIMMER_LEVEL as i32, MAX_DIMMER_LEVEL as i32) as u8
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub struct Config {
     pub hotkey: HotkeyConfig,
+    pub chord_timeout: Duration,
+    /// Time between patch-set steps; zero steps with the arrow keys instead.
+    pub patch_advance: Duration,
+    /// Hide the overlay after it has been up this long without input.
+    pub auto_hide: Option<Duration>,
+    /// Hide the overlay after this long no matter what is running.
+    pub failsafe: Option<Duration>,
+    /// Show the time left before hiding in the corner of the overlay.
+    pub countdown: bool,
+    /// Show the information OSD when the overlay comes up; `I` toggles it.
+    pub osd: bool,
+    /// Keep the display from sleeping during tests.
+    pub keep_awake: KeepAwake,
+    /// How long showing and hiding the overlay take; zero snaps.
+    pub fade_in: Duration,
+    pub fade_out: Duration,
+    /// Overlay opacity at startup, percent.
+    pub opacity: u8,
+    /// Overlay color at startup.
+    pub color: u32,
+    pub presets: Vec<Preset>,
+    /// Colors picked lately, newest first, at most `MAX_RECENT_COLORS`.
+    pub recent: Vec<u32>,
+    pub monitors: MonitorSelection,
+    /// Window mode at startup, before any profile is picked.
+    pub window: Option<PatchWindow>,
+    /// Extra leader-key sequences, in addition to the toggle hotkey.
+    pub bindings: Vec<Binding>,
+    pub profiles: Vec<Profile>,
+    pub sequences: Vec<Sequence>,
+    /// Time-of-day rules that show the overlay on their own.
+    pub schedule: Vec<Rule>,
+    pub idle: IdleSettings,
+    pub dimmer: DimmerSettings,
+    pub http: HttpSettings,
+    pub generator: GeneratorSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hotkey: HotkeyConfig::default(),
+            chord_timeout: DEFAULT_CHORD_TIMEOUT,
+            patch_advance: Duration::ZERO,
+            auto_hide: None,
+            failsafe: None,
+            countdown: false,
+            osd: false,
+            keep_awake: KeepAwake::Off,
+            fade_in: Duration::ZERO,
+            fade_out: Duration::ZERO,
+            opacity: 100,
+            color: 0x00000000, // Black
+            presets: PRESET_COLORS
+                .iter()
+                .map(|&(name, color)| Preset {
+                    name: name.to_string(),
+                    color,
+                })
+                .collect(),
+            recent: Vec::new(),
+            monitors: MonitorSelection::default(),
+            window: None,
+            bindings: Vec::new(),
+            profiles: Vec::new(),
+            sequences: Vec::new(),
+            schedule: Vec::new(),
+            idle: IdleSettings::default(),
+            dimmer: DimmerSettings::default(),
+            http: HttpSettings::default(),
+            generator: GeneratorSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: Option<PathBuf>,
+    pub pos: Option<Pos>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn at(pos: Pos, message: impl Into<String>) -> Self {
+        ConfigError {
+            path: None,
+            pos: Some(pos),
+            message: message.into(),
+        }
+    }
+
+    fn in_file(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(pos) = self.pos {
+            write!(f, "{}:{}:", pos.line, pos.column)?;
+        }
+        if self.path.is_some() || self.pos.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
 }
 
 impl Config {
-    pub fn load() -> Self {
-        let path = Self::config_path();
-        if let Ok(content) = fs::read_to_string(&path) {
-            Self::parse(&content)
-        } else {
-            Config::default()
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::read()? {
+            Some(config) => Ok(config),
+            None => Ok(Self::migrate_legacy().unwrap_or_default()),
         }
     }
 
-    pub fn save(&self) -> std::io::Result<()> {
+    /// Parses the config file without migrating or writing anything.
+    /// Returns `None` if there is no file.
+    pub fn read() -> Result<Option<Self>, ConfigError> {
         let path = Self::config_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content)
+                .map(Some)
+                .map_err(|err| err.in_file(&path)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ConfigError {
+                path: Some(path),
+                pos: None,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// Parses the old `config.ini` without migrating it. Returns `None` if
+    /// there is none, otherwise the config and the lines migrating would skip.
+    pub fn read_legacy() -> Result<Option<(Self, Vec<ConfigError>)>, ConfigError> {
+        let path = Self::legacy_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let (config, skipped) = Self::parse_legacy(&content);
+                let skipped = skipped.into_iter().map(|err| err.in_file(&path));
+                Ok(Some((config, skipped.collect())))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ConfigError {
+                path: Some(path),
+                pos: None,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::config_path())
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let content = format!("hotkey = {}\n", self.hotkey.display());
-        fs::write(&path, content)
+        fs::write(path, self.to_toml())
+    }
+
+    /// Uses `path` instead of the default location. Must be called before
+    /// the config is first loaded or saved.
+    pub fn set_path_override(path: PathBuf) {
+        let _ = PATH_OVERRIDE.set(path);
     }
 
     pub fn config_dir() -> Option<PathBuf> {
-        std::env::var("APPDATA").ok().map(|p| PathBuf::from(p).join(APP_NAME))
+        Self::config_path().parent().map(Path::to_path_buf)
+    }
+
+    /// Resolved once per process; see `resolve_config_path` for precedence.
+    pub fn config_path() -> PathBuf {
+        RESOLVED_PATH
+            .get_or_init(|| {
+                let exe_dir = std::env::current_exe()
+                    .ok()
+                    .and_then(|exe| exe.parent().map(Path::to_path_buf));
+                resolve_config_path(
+                    PATH_OVERRIDE.get().map(PathBuf::as_path),
+                    |name| std::env::var_os(name).map(PathBuf::from),
+                    exe_dir.as_deref(),
+                    |path| path.is_file(),
+                )
+            })
+            .clone()
+    }
+
+    /// Where an old `config.ini` would be, next to the config file.
+    pub fn legacy_path() -> PathBuf {
+        Self::config_path().with_file_name(LEGACY_CONFIG_FILE)
+    }
+
+    /// The toggle hotkey followed by the user bindings, so that a binding for
+    /// the same sequence wins.
+    pub fn keymap(&self) -> Keymap {
+        let mut bindings = vec![Binding {
+            sequence: KeySequence(vec![self.hotkey.clone()]),
+            action: Action::Toggle,
+        }];
+        if let Some(hotkey) = &self.dimmer.hotkey {
+            bindings.push(Binding {
+                sequence: KeySequence(vec![hotkey.clone()]),
+                action: Action::Dimmer,
+            });
+        }
+        bindings.extend(self.bindings.iter().cloned());
+        Keymap::new(bindings)
+    }
+
+    /// Overlay settings at startup, before any profile is picked.
+    pub fn overlay(&self) -> OverlaySettings {
+        OverlaySettings {
+            color: self.color,
+            opacity: self.opacity,
+            monitors: self.monitors.clone(),
+            window: self.window,
+            ..OverlaySettings::default()
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn sequence(&self, name: &str) -> Option<&Sequence> {
+        self.sequences.iter().find(|s| s.name == name)
+    }
+
+    /// How many presets the keymap needs: one past the highest `preset N`.
+    pub fn presets_in_use(&self) -> usize {
+        self.bindings
+            .iter()
+            .filter_map(|b| match b.action {
+                Action::Preset(index) => Some(index + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Moves `color` to the front of the recent colors. Returns false if it
+    /// was already there.
+    pub fn remember_color(&mut self, color: u32) -> bool {
+        if self.recent.first() == Some(&color) {
+            return false;
+        }
+        self.recent.retain(|&c| c != color);
+        self.recent.insert(0, color);
+        self.recent.truncate(MAX_RECENT_COLORS);
+        true
+    }
+
+    /// Whether `other` registers different hotkeys or binds different
+    /// sequences, so the keymap has to be rebuilt.
+    pub fn keys_changed(&self, other: &Config) -> bool {
+        self.hotkey != other.hotkey
+            || self.bindings != other.bindings
+            || self.chord_timeout != other.chord_timeout
+            || self.dimmer.hotkey != other.dimmer.hotkey
     }
 
-    fn config_path() -> PathBuf {
-        Self::config_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(CONFIG_FILE)
+    /// Names of the settings that differ between `self` and `other`.
+    pub fn changes(&self, other: &Config) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.hotkey != other.hotkey {
+            changes.push("hotkey");
+        }
+        if self.bindings != other.bindings
+            || self.chord_timeout != other.chord_timeout
+            || self.dimmer.hotkey != other.dimmer.hotkey
+        {
+            changes.push("key bindings");
+        }
+        if self.patch_advance != other.patch_advance {
+            changes.push("patch advance");
+        }
+        if self.auto_hide != other.auto_hide
+            || self.failsafe != other.failsafe
+            || self.countdown != other.countdown
+        {
+            changes.push("hide timeouts");
+        }
+        if self.fade_in != other.fade_in || self.fade_out != other.fade_out {
+            changes.push("fades");
+        }
+        if self.opacity != other.opacity {
+            changes.push("opacity");
+        }
+        if self.osd != other.osd {
+            changes.push("OSD");
+        }
+        if self.keep_awake != other.keep_awake {
+            changes.push("keep awake");
+        }
+        if self.color != other.color {
+            changes.push("default color");
+        }
+        if self.presets != other.presets {
+            changes.push("presets");
+        }
+        if self.recent != other.recent {
+            changes.push("recent colors");
+        }
+        if self.monitors != other.monitors {
+            changes.push("monitors");
+        }
+        if self.window != other.window {
+            changes.push("patch window");
+        }
+        if self.profiles != other.profiles {
+            changes.push("profiles");
+        }
+        if self.sequences != other.sequences {
+            changes.push("sequences");
+        }
+        if self.schedule != other.schedule {
+            changes.push("schedule");
+        }
+        if self.idle != other.idle {
+            changes.push("idle overlay");
+        }
+        if self.dimmer != other.dimmer {
+            changes.push("dimmer");
+        }
+        if self.http != other.http {
+            changes.push("HTTP API");
+        }
+        if self.generator != other.generator {
+            changes.push("pattern generator");
+        }
+        changes
+    }
+
+    /// Converts an old `config.ini` to `config.toml`, keeping the original as
+    /// `config.ini.bak`.
+    fn migrate_legacy() -> Option<Self> {
+        Self::migrate(&Self::legacy_path(), &Self::config_path())
+    }
+
+    fn migrate(legacy: &Path, path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(legacy).ok()?;
+        let (config, _) = Self::parse_legacy(&content);
+        if config.save_to(path).is_ok() {
+            let _ = fs::rename(legacy, legacy.with_extension("ini.bak"));
+        }
+        Some(config)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        let doc = toml::parse(content).map_err(|err| ConfigError::at(err.pos, err.message))?;
+
+        let mut config = Config::default();
+        let mut preset_refs = Vec::new();
+        let mut profile_refs = Vec::new();
+
+        for (key, item) in &doc {
+            match key.name.as_str() {
+                "general" => {
+                    for (key, item) in table(key, item)? {
+                        match key.name.as_str() {
+                            "hotkey" => {
+                                let s = string(key, item)?;
+                                config.hotkey = HotkeyConfig::parse(s).ok_or_else(|| {
+                                    ConfigError::at(item.pos, format!("invalid hotkey `{}`", s))
+                                })?;
+                            }
+                            "chord_timeout" => {
+                                let ms = non_negative(key, item)?;
+                                config.chord_timeout = Duration::from_millis(ms);
+                            }
+                            "patch_advance" => {
+                                let ms = non_negative(key, item)?;
+                                config.patch_advance = Duration::from_millis(ms);
+                            }
+                            "auto_hide" => config.auto_hide = timeout(key, item)?,
+                            "failsafe" => config.failsafe = timeout(key, item)?,
+                            "countdown" => config.countdown = boolean(key, item)?,
+                            "fade_in" => {
+                                let ms = non_negative(key, item)?;
+                                config.fade_in = Duration::from_millis(ms);
+                            }
+                            "fade_out" => {
+                                let ms = non_negative(key, item)?;
+                                config.fade_out = Duration::from_millis(ms);
+                            }
+                            "opacity" => config.opacity = percent(key, item)?,
+                            "osd" => config.osd = boolean(key, item)?,
+                            "keep_awake" => {
+                                let s = string(key, item)?;
+                                config.keep_awake = KeepAwake::parse(s).ok_or_else(|| {
+                                    ConfigError::at(
+                                        item.pos,
+                                        format!(
+                                            "invalid keep_awake `{}`, expected off, overlay \
+                                             or sessions",
+                                            s
+                                        ),
+                                    )
+                                })?;
+                            }
+                            _ => return Err(unknown_key(key, "general")),
+                        }
+                    }
+                }
+                "colors" => {
+                    for (key, item) in table(key, item)? {
+                        match key.name.as_str() {
+                            "default" => config.color = color_value(key, item)?,
+                            "recent" => {
+                                config.recent = array(key, item)?
+                                    .iter()
+                                    .map(|entry| color_value(key, entry))
+                                    .collect::<Result<_, _>>()?;
+                                config.recent.truncate(MAX_RECENT_COLORS);
+                            }
+                            _ => return Err(unknown_key(key, "colors")),
+                        }
+                    }
+                }
+                "presets" => {
+                    config.presets = Vec::new();
+                    for entry in array(key, item)? {
+                        config.presets.push(parse_preset(key, entry)?);
+                    }
+                }
+                "monitors" => config.monitors = monitor_selection(key, item)?,
+                "window" => config.window = Some(patch_window(key, item)?),
+                "idle" => config.idle = parse_idle(key, item)?,
+                "dimmer" => config.dimmer = parse_dimmer(key, item)?,
+                "http" => config.http = parse_http(key, item)?,
+                "generator" => config.generator = parse_generator(key, item)?,
+                "profiles" => {
+                    for entry in array(key, item)? {
+                        let profile = parse_profile(key, entry)?;
+                        if config.profile(&profile.name).is_some() {
+                            return Err(ConfigError::at(
+                                entry.pos,
+                                format!("profile `{}` is defined twice", profile.name),
+                            ));
+                        }
+                        config.profiles.push(profile);
+                    }
+                }
+                "sequences" => {
+                    for entry in array(key, item)? {
+                        let sequence = parse_sequence(key, entry)?;
+                        if config.sequence(&sequence.name).is_some() {
+                            return Err(ConfigError::at(
+                                entry.pos,
+                                format!("sequence `{}` is defined twice", sequence.name),
+                            ));
+                        }
+                        config.sequences.push(sequence);
+                    }
+                }
+                "schedule" => {
+                    for entry in array(key, item)? {
+                        let rule = parse_rule(key, entry)?;
+                        if let Some(name) = &rule.profile {
+                            profile_refs.push((entry.pos, name.clone()));
+                        }
+                        config.schedule.push(rule);
+                    }
+                }
+                "keymap" => {
+                    for (key, item) in table(key, item)? {
+                        let sequence = KeySequence::parse(&key.name).ok_or_else(|| {
+                            ConfigError::at(key.pos, format!("invalid key sequence `{}`", key.name))
+                        })?;
+                        let s = string(key, item)?;
+                        let action = Action::parse(s).ok_or_else(|| {
+                            ConfigError::at(
+                                item.pos,
+                                format!(
+                                    "invalid action `{}`, expected toggle, show, hide, quit, \
+                                     color #RRGGBB, preset N, profile NAME, dimmer, \
+                                     dimmer + or dimmer -",
+                                    s
+                                ),
+                            )
+                        })?;
+                        match &action {
+                            Action::Preset(index) => preset_refs.push((item.pos, *index)),
+                            Action::Profile(name) => profile_refs.push((item.pos, name.clone())),
+                            _ => {}
+                        }
+                        config.bindings.push(Binding { sequence, action });
+                    }
+                }
+                _ => {
+                    return Err(ConfigError::at(
+                        key.pos,
+                        format!("unknown section `{}`", key.name),
+                    ))
+                }
+            }
+        }
+
+        // Presets may be declared after the keymap, so check references last
+        for (pos, index) in preset_refs {
+            if index >= config.presets.len() {
+                return Err(ConfigError::at(
+                    pos,
+                    format!(
+                        "preset {} does not exist ({} presets defined)",
+                        index + 1,
+                        config.presets.len()
+                    ),
+                ));
+            }
+        }
+
+        for (pos, name) in profile_refs {
+            if config.profile(&name).is_none() {
+                return Err(ConfigError::at(
+                    pos,
+                    format!("profile `{}` does not exist", name),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+
+        out += "[general]\n";
+        out += &format!("hotkey = {}\n", toml::quote(&self.hotkey.display()));
+        out += &format!("chord_timeout = {}\n", self.chord_timeout.as_millis());
+        out += &format!("patch_advance = {}\n", self.patch_advance.as_millis());
+        let timeout = |t: Option<Duration>| t.map_or("off".to_string(), cli::format_duration);
+        out += &format!("auto_hide = {}\n", toml::quote(&timeout(self.auto_hide)));
+        out += &format!("failsafe = {}\n", toml::quote(&timeout(self.failsafe)));
+        out += &format!("countdown = {}\n", self.countdown);
+        out += &format!("osd = {}\n", self.osd);
+        out += &format!("keep_awake = {}\n", toml::quote(self.keep_awake.name()));
+        out += &format!("fade_in = {}\n", self.fade_in.as_millis());
+        out += &format!("fade_out = {}\n", self.fade_out.as_millis());
+        out += &format!("opacity = {}\n", self.opacity);
+
+        out += "\n[colors]\n";
+        out += &format!("default = {}\n", toml::quote(&color::to_hex(self.color)));
+        let recent: Vec<String> = self
+            .recent
+            .iter()
+            .map(|&c| toml::quote(&color::to_hex(c)))
+            .collect();
+        out += &format!("recent = [{}]\n", recent.join(", "));
+
+        out += "\n[monitors]\n";
+        out += &format!("include = {}\n", number_list(&self.monitors.include));
+        out += &format!("exclude = {}\n", number_list(&self.monitors.exclude));
+
+        if let Some(window) = &self.window {
+            out += "\n[window]\n";
+            out += &format!("size = {}\n", toml::quote(&window.size.display()));
+            out += &format!("x = {}\n", window.x);
+            out += &format!("y = {}\n", window.y);
+            out += &format!(
+                "background = {}\n",
+                toml::quote(&color::to_hex(window.background))
+            );
+        }
+
+        out += "\n[keymap]\n";
+        for binding in &self.bindings {
+            out += &format!(
+                "{} = {}\n",
+                toml::key(&binding.sequence.display()),
+                toml::quote(&binding.action.display())
+            );
+        }
+
+        for preset in &self.presets {
+            out += "\n[[presets]]\n";
+            out += &format!("name = {}\n", toml::quote(&preset.name));
+            out += &format!("color = {}\n", toml::quote(&color::to_hex(preset.color)));
+        }
+
+        for sequence in &self.sequences {
+            out += "\n[[sequences]]\n";
+            out += &format!("name = {}\n", toml::quote(&sequence.name));
+            out += &format!("loop = {}\n", sequence.looping);
+            out += "steps = [\n";
+            for step in &sequence.steps {
+                out += &format!(
+                    "  {{ color = {}, pattern = {}, duration = {} }},\n",
+                    toml::quote(&color::to_hex(step.color)),
+                    toml::quote(step.pattern.name()),
+                    toml::quote(&cli::format_duration(step.duration))
+                );
+            }
+            out += "]\n";
+        }
+
+        for rule in &self.schedule {
+            out += "\n[[schedule]]\n";
+            let days: Vec<String> = schedule::format_days(rule.days)
+                .into_iter()
+                .map(toml::quote)
+                .collect();
+            out += &format!("days = [{}]\n", days.join(", "));
+            out += &format!(
+                "start = {}\n",
+                toml::quote(&schedule::format_time(rule.start))
+            );
+            out += &format!("end = {}\n", toml::quote(&schedule::format_time(rule.end)));
+            if let Some(color) = rule.color {
+                out += &format!("color = {}\n", toml::quote(&color::to_hex(color)));
+            }
+            if let Some(profile) = &rule.profile {
+                out += &format!("profile = {}\n", toml::quote(profile));
+            }
+            if !rule.monitors.is_empty() {
+                out += &format!("monitors = {}\n", number_list(&rule.monitors));
+            }
+        }
+
+        out += "\n[idle]\n";
+        out += &format!("after = {}\n", toml::quote(&timeout(self.idle.after)));
+        out += &format!("color = {}\n", toml::quote(&color::to_hex(self.idle.color)));
+
+        out += "\n[dimmer]\n";
+        if let Some(hotkey) = &self.dimmer.hotkey {
+            out += &format!("hotkey = {}\n", toml::quote(&hotkey.display()));
+        }
+        out += &format!(
+            "color = {}\n",
+            toml::quote(&color::to_hex(self.dimmer.color))
+        );
+        out += &format!("level = {}\n", self.dimmer.level);
+        out += &format!("step = {}\n", self.dimmer.step);
+        out += &format!("monitors = {}\n", number_list(&self.dimmer.monitors));
+
+        out += "\n[http]\n";
+        out += &format!("enabled = {}\n", self.http.enabled);
+        out += &format!("port = {}\n", self.http.port);
+        out += &format!("token = {}\n", toml::quote(&self.http.token));
+
+        out += "\n[generator]\n";
+        out += &format!("enabled = {}\n", self.generator.enabled);
+        out += &format!("address = {}\n", toml::quote(&self.generator.address));
+
+        for profile in &self.profiles {
+            let settings = &profile.settings;
+            out += "\n[[profiles]]\n";
+            out += &format!("name = {}\n", toml::quote(&profile.name));
+            out += &format!("color = {}\n", toml::quote(&color::to_hex(settings.color)));
+            out += &format!("pattern = {}\n", toml::quote(settings.pattern.name()));
+            out += &format!(
+                "monitors = {{ include = {}, exclude = {} }}\n",
+                number_list(&settings.monitors.include),
+                number_list(&settings.monitors.exclude)
+            );
+            out += &format!("dismiss_on_click = {}\n", settings.dismissal.on_click);
+            out += &format!("dismiss_on_escape = {}\n", settings.dismissal.on_escape);
+            out += &format!("brightness = {}\n", settings.brightness);
+            out += &format!("opacity = {}\n", settings.opacity);
+            if let Some(window) = &settings.window {
+                out += &format!(
+                    "window = {{ size = {}, x = {}, y = {}, background = {} }}\n",
+                    toml::quote(&window.size.display()),
+                    window.x,
+                    window.y,
+                    toml::quote(&color::to_hex(window.background))
+                );
+            }
+        }
+
+        out
     }
 
-    fn parse(content: &str) -> Self {
+    /// Reads the old `config.ini` format. Lines it can't use are skipped and
+    /// returned as errors, so a check can list what migrating would drop.
+    fn parse_legacy(content: &str) -> (Self, Vec<ConfigError>) {
         let mut config = Config::default();
-        for line in content.lines() {
+        let mut skipped = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let pos = Pos {
+                line: index + 1,
+                column: line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+            };
             let line = line.trim();
             if line.is_empty() || line.starts_with('#') {
                 continue;
             }
-            if let Some((key, value)) = line.split_once('=') {
-                let key = key.trim();
-                let value = value.trim();
-                if key == "hotkey" {
-                    if let Some(hk) = Self::parse_hotkey(value) {
-                        config.hotkey = hk;
-                    }
+            let Some((key, value)) = line.split_once('=') else {
+                skipped.push(ConfigError::at(pos, "expected `key = value`"));
+                continue;
+            };
+            let key = key.trim();
+            let value = value.trim();
+            let parsed = match key {
+                "hotkey" => HotkeyConfig::parse(value).map(|hk| config.hotkey = hk),
+                "chord_timeout" => value
+                    .parse::<u64>()
+                    .ok()
+                    .map(|ms| config.chord_timeout = Duration::from_millis(ms)),
+                "bind" => Self::parse_binding(value).map(|b| config.bindings.push(b)),
+                _ => {
+                    skipped.push(ConfigError::at(pos, format!("unknown key `{}`", key)));
+                    continue;
                 }
+            };
+            if parsed.is_none() {
+                skipped.push(ConfigError::at(
+                    pos,
+                    format!("invalid `{}` value `{}`", key, value),
+                ));
             }
         }
-        config
+        (config, skipped)
     }
 
-    fn parse_hotkey(s: &str) -> Option<HotkeyConfig> {
-        let parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
-        if parts.is_empty() {
-            return None;
+    /// `Ctrl+Shift+B, W = color #FFFFFF`
+    fn parse_binding(s: &str) -> Option<Binding> {
+        let (sequence, action) = s.split_once('=')?;
+        Some(Binding {
+            sequence: KeySequence::parse(sequence)?,
+            action: Action::parse(action)?,
+        })
+    }
+}
+
+/// Picks the config file, highest precedence first:
+///
+/// 1. `--config <path>` (a directory means `<path>/config.toml`)
+/// 2. the `BLANQR_CONFIG` environment variable, same rules as the flag
+/// 3. portable mode: `config.toml` or a legacy `config.ini` next to the executable
+/// 4. `%APPDATA%\Blanqr\config.toml` on Windows;
+///    `$XDG_CONFIG_HOME/blanqr/config.toml`, falling back to
+///    `$HOME/.config/blanqr/config.toml`, elsewhere
+/// 5. `config.toml` in the current directory
+///
+/// The environment and file system are passed in so the rules can be checked
+/// without touching either.
+pub fn resolve_config_path(
+    flag: Option<&Path>,
+    env: impl Fn(&str) -> Option<PathBuf>,
+    exe_dir: Option<&Path>,
+    is_file: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    let explicit = |path: PathBuf| {
+        if path.extension().is_none() && !is_file(&path) {
+            path.join(CONFIG_FILE)
+        } else {
+            path
         }
+    };
 
-        let mut modifiers = 0u32;
-        let mut key = 0u32;
+    if let Some(path) = flag {
+        return explicit(path.to_path_buf());
+    }
+    if let Some(path) = env(CONFIG_ENV).filter(|p| !p.as_os_str().is_empty()) {
+        return explicit(path);
+    }
 
-        for part in &parts {
-            let upper = part.to_uppercase();
-            match upper.as_str() {
-                "CTRL" | "CONTROL" => modifiers |= 0x0002,
-                "ALT" => modifiers |= 0x0001,
-                "SHIFT" => modifiers |= 0x0004,
-                "WIN" | "WINDOWS" => modifiers |= 0x0008,
-                _ => {
-                    if upper.len() == 1 {
-                        let c = upper.chars().next().unwrap();
-                        if c.is_ascii_alphanumeric() {
-                            key = c as u32;
-                        }
-                    } else if let Some(suffix) = upper.strip_prefix('F') {
-                        if let Ok(n) = suffix.parse::<u32>() {
-                            if (1..=12).contains(&n) {
-                                key = 0x6F + n; // VK_F1 = 0x70
-                            }
-                        }
-                    }
+    if let Some(dir) = exe_dir {
+        let portable = dir.join(CONFIG_FILE);
+        if is_file(&portable) || is_file(&dir.join(LEGACY_CONFIG_FILE)) {
+            return portable;
+        }
+    }
+
+    let user_dir = if cfg!(windows) {
+        env("APPDATA").map(|p| p.join(APP_NAME))
+    } else {
+        env("XDG_CONFIG_HOME")
+            .filter(|p| p.is_absolute())
+            .or_else(|| env("HOME").map(|home| home.join(".config")))
+            .map(|p| p.join(XDG_APP_NAME))
+    };
+
+    user_dir
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(CONFIG_FILE)
+}
+
+fn parse_preset(section: &Key, item: &Item) -> Result<Preset, ConfigError> {
+    let mut name = None;
+    let mut color = None;
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "name" => name = Some(string(key, item)?.to_string()),
+            "color" => color = Some(color_value(key, item)?),
+            _ => return Err(unknown_key(key, "presets")),
+        }
+    }
+    match (name, color) {
+        (Some(name), Some(color)) => Ok(Preset { name, color }),
+        (None, _) => Err(ConfigError::at(item.pos, "preset is missing `name`")),
+        (_, None) => Err(ConfigError::at(item.pos, "preset is missing `color`")),
+    }
+}
+
+fn parse_profile(section: &Key, item: &Item) -> Result<Profile, ConfigError> {
+    let mut name = None;
+    let mut settings = OverlaySettings::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "name" => name = Some(string(key, item)?.to_string()),
+            "color" => settings.color = color_value(key, item)?,
+            "pattern" => settings.pattern = pattern_value(key, item)?,
+            "monitors" => settings.monitors = monitor_selection(key, item)?,
+            "dismiss_on_click" => settings.dismissal.on_click = boolean(key, item)?,
+            "dismiss_on_escape" => settings.dismissal.on_escape = boolean(key, item)?,
+            "brightness" => settings.brightness = percent(key, item)?,
+            "opacity" => settings.opacity = percent(key, item)?,
+            "window" => settings.window = Some(patch_window(key, item)?),
+            _ => return Err(unknown_key(key, "profiles")),
+        }
+    }
+    match name {
+        Some(name) => Ok(Profile { name, settings }),
+        None => Err(ConfigError::at(item.pos, "profile is missing `name`")),
+    }
+}
+
+fn parse_sequence(section: &Key, item: &Item) -> Result<Sequence, ConfigError> {
+    let mut name = None;
+    let mut steps = None;
+    let mut looping = true;
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "name" => name = Some(string(key, item)?.to_string()),
+            "loop" => looping = boolean(key, item)?,
+            "steps" => {
+                let entries = array(key, item)?;
+                if entries.is_empty() {
+                    return Err(ConfigError::at(item.pos, "`steps` must not be empty"));
                 }
+                steps = Some(
+                    entries
+                        .iter()
+                        .map(|entry| parse_step(key, entry))
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
             }
+            _ => return Err(unknown_key(key, "sequences")),
         }
+    }
+    match (name, steps) {
+        (Some(name), Some(steps)) => Ok(Sequence {
+            name,
+            steps,
+            looping,
+        }),
+        (None, _) => Err(ConfigError::at(item.pos, "sequence is missing `name`")),
+        (_, None) => Err(ConfigError::at(item.pos, "sequence is missing `steps`")),
+    }
+}
 
-        if key != 0 {
-            Some(HotkeyConfig { modifiers, key })
-        } else {
-            None
+fn parse_step(section: &Key, item: &Item) -> Result<Step, ConfigError> {
+    let mut color = None;
+    let mut pattern = Pattern::Solid;
+    let mut duration = None;
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "color" => color = Some(color_value(key, item)?),
+            "pattern" => pattern = pattern_value(key, item)?,
+            "duration" => {
+                let s = string(key, item)?;
+                duration = Some(cli::parse_duration(s).ok_or_else(|| {
+                    ConfigError::at(
+                        item.pos,
+                        format!(
+                            "invalid duration `{}`, expected e.g. \"30s\", \"5m\", \"1h\" or \
+                             \"500ms\"",
+                            s
+                        ),
+                    )
+                })?);
+            }
+            _ => return Err(unknown_key(key, "sequences.steps")),
+        }
+    }
+    match (color, duration) {
+        (Some(color), Some(duration)) => Ok(Step {
+            color,
+            pattern,
+            duration,
+        }),
+        (None, _) => Err(ConfigError::at(item.pos, "step is missing `color`")),
+        (_, None) => Err(ConfigError::at(item.pos, "step is missing `duration`")),
+    }
+}
+
+fn parse_rule(section: &Key, item: &Item) -> Result<Rule, ConfigError> {
+    let mut days = None;
+    let mut start = None;
+    let mut end = None;
+    let mut color = None;
+    let mut profile = None;
+    let mut monitors = Vec::new();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "days" => {
+                let mut bits = 0;
+                for entry in array(key, item)? {
+                    let s = string(key, entry)?;
+                    bits |= schedule::parse_days(s).ok_or_else(|| {
+                        ConfigError::at(
+                            entry.pos,
+                            format!(
+                                "invalid day `{}`, expected a day name like \"mon\", \
+                                 \"weekdays\", \"weekends\" or \"daily\"",
+                                s
+                            ),
+                        )
+                    })?;
+                }
+                days = Some(bits);
+            }
+            "start" => start = Some(time_value(key, item)?),
+            "end" => end = Some(time_value(key, item)?),
+            "color" => color = Some(color_value(key, item)?),
+            "profile" => profile = Some(string(key, item)?.to_string()),
+            "monitors" => monitors = monitor_numbers(key, item)?,
+            _ => return Err(unknown_key(key, "schedule")),
+        }
+    }
+    if color.is_some() && profile.is_some() {
+        return Err(ConfigError::at(
+            item.pos,
+            "a schedule rule takes either `color` or `profile`, not both",
+        ));
+    }
+    match (start, end) {
+        (Some(start), Some(end)) => Ok(Rule {
+            days: days.unwrap_or(Rule::EVERY_DAY),
+            start,
+            end,
+            color,
+            profile,
+            monitors,
+        }),
+        (None, _) => Err(ConfigError::at(
+            item.pos,
+            "schedule rule is missing `start`",
+        )),
+        (_, None) => Err(ConfigError::at(item.pos, "schedule rule is missing `end`")),
+    }
+}
+
+fn parse_idle(section: &Key, item: &Item) -> Result<IdleSettings, ConfigError> {
+    let mut idle = IdleSettings::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "after" => idle.after = timeout(key, item)?,
+            "color" => idle.color = color_value(key, item)?,
+            _ => return Err(unknown_key(key, "idle")),
+        }
+    }
+    Ok(idle)
+}
+
+fn parse_dimmer(section: &Key, item: &Item) -> Result<DimmerSettings, ConfigError> {
+    let mut dimmer = DimmerSettings::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "hotkey" => {
+                let s = string(key, item)?;
+                dimmer.hotkey =
+                    Some(HotkeyConfig::parse(s).ok_or_else(|| {
+                        ConfigError::at(item.pos, format!("invalid hotkey `{}`", s))
+                    })?);
+            }
+            "color" => dimmer.color = color_value(key, item)?,
+            "level" => dimmer.level = percent(key, item)?,
+            "step" => dimmer.step = percent(key, item)?,
+            "monitors" => dimmer.monitors = monitor_numbers(key, item)?,
+            _ => return Err(unknown_key(key, "dimmer")),
+        }
+    }
+    Ok(dimmer)
+}
+
+fn parse_http(section: &Key, item: &Item) -> Result<HttpSettings, ConfigError> {
+    let mut http = HttpSettings::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "enabled" => http.enabled = boolean(key, item)?,
+            "port" => http.port = port(key, item)?,
+            "token" => http.token = string(key, item)?.to_string(),
+            _ => return Err(unknown_key(key, "http")),
+        }
+    }
+    if http.enabled && http.token.is_empty() {
+        return Err(ConfigError::at(
+            item.pos,
+            "the HTTP API needs a `token` when `enabled = true`",
+        ));
+    }
+    Ok(http)
+}
+
+fn parse_generator(section: &Key, item: &Item) -> Result<GeneratorSettings, ConfigError> {
+    let mut generator = GeneratorSettings::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "enabled" => generator.enabled = boolean(key, item)?,
+            "address" => generator.address = address(key, item)?,
+            _ => return Err(unknown_key(key, "generator")),
+        }
+    }
+    Ok(generator)
+}
+
+fn monitor_selection(section: &Key, item: &Item) -> Result<MonitorSelection, ConfigError> {
+    let mut selection = MonitorSelection::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "include" => selection.include = monitor_numbers(key, item)?,
+            "exclude" => selection.exclude = monitor_numbers(key, item)?,
+            _ => return Err(unknown_key(key, "monitors")),
+        }
+    }
+    Ok(selection)
+}
+
+fn patch_window(section: &Key, item: &Item) -> Result<PatchWindow, ConfigError> {
+    let mut window = PatchWindow::default();
+    for (key, item) in table(section, item)? {
+        match key.name.as_str() {
+            "size" => {
+                let s = string(key, item)?;
+                window.size = PatchSize::parse(s).ok_or_else(|| {
+                    ConfigError::at(
+                        item.pos,
+                        format!(
+                            "invalid window size `{}`, expected a percentage of the \
+                             screen like \"10%\" or pixels like \"400x300\"",
+                            s
+                        ),
+                    )
+                })?;
+            }
+            "x" => window.x = position(key, item)?,
+            "y" => window.y = position(key, item)?,
+            "background" => window.background = color_value(key, item)?,
+            _ => return Err(unknown_key(key, "window")),
+        }
+    }
+    Ok(window)
+}
+
+fn unknown_key(key: &Key, section: &str) -> ConfigError {
+    ConfigError::at(
+        key.pos,
+        format!("unknown key `{}` in [{}]", key.name, section),
+    )
+}
+
+fn wrong_type(key: &Key, item: &Item, expected: &str) -> ConfigError {
+    ConfigError::at(
+        item.pos,
+        format!(
+            "`{}` must be {}, found {}",
+            key.name,
+            expected,
+            item.value.type_name()
+        ),
+    )
+}
+
+fn table<'a>(key: &Key, item: &'a Item) -> Result<&'a Table, ConfigError> {
+    match &item.value {
+        Value::Table(table) => Ok(table),
+        _ => Err(wrong_type(key, item, "a table")),
+    }
+}
+
+fn array<'a>(key: &Key, item: &'a Item) -> Result<&'a [Item], ConfigError> {
+    match &item.value {
+        Value::Array(items) => Ok(items),
+        _ => Err(wrong_type(key, item, "an array")),
+    }
+}
+
+fn string<'a>(key: &Key, item: &'a Item) -> Result<&'a str, ConfigError> {
+    match &item.value {
+        Value::String(s) => Ok(s),
+        _ => Err(wrong_type(key, item, "a string")),
+    }
+}
+
+fn boolean(key: &Key, item: &Item) -> Result<bool, ConfigError> {
+    match item.value {
+        Value::Boolean(b) => Ok(b),
+        _ => Err(wrong_type(key, item, "a boolean")),
+    }
+}
+
+fn percent(key: &Key, item: &Item) -> Result<u8, ConfigError> {
+    match item.value {
+        Value::Integer(n) if (1..=100).contains(&n) => Ok(n as u8),
+        Value::Integer(n) => Err(ConfigError::at(
+            item.pos,
+            format!("`{}` must be between 1 and 100, found {}", key.name, n),
+        )),
+        _ => Err(wrong_type(key, item, "an integer")),
+    }
+}
+
+/// Placement within the free space, 0-100.
+fn position(key: &Key, item: &Item) -> Result<u8, ConfigError> {
+    match item.value {
+        Value::Integer(n) if (0..=100).contains(&n) => Ok(n as u8),
+        Value::Integer(n) => Err(ConfigError::at(
+            item.pos,
+            format!("`{}` must be between 0 and 100, found {}", key.name, n),
+        )),
+        _ => Err(wrong_type(key, item, "an integer")),
+    }
+}
+
+/// A duration string like `"30m"`, or `"off"`.
+fn timeout(key: &Key, item: &Item) -> Result<Option<Duration>, ConfigError> {
+    let s = string(key, item)?;
+    if s.trim().eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    cli::parse_duration(s).map(Some).ok_or_else(|| {
+        ConfigError::at(
+            item.pos,
+            format!(
+                "invalid `{}` value `{}`, expected a duration like \"30m\" or \"off\"",
+                key.name, s
+            ),
+        )
+    })
+}
+
+/// Local time of day, `"HH:MM"`.
+fn time_value(key: &Key, item: &Item) -> Result<u16, ConfigError> {
+    let s = string(key, item)?;
+    schedule::parse_time(s).ok_or_else(|| {
+        ConfigError::at(
+            item.pos,
+            format!(
+                "invalid time `{}` for `{}`, expected \"HH:MM\"",
+                s, key.name
+            ),
+        )
+    })
+}
+
+fn pattern_value(key: &Key, item: &Item) -> Result<Pattern, ConfigError> {
+    let s = string(key, item)?;
+    Pattern::parse(s).ok_or_else(|| {
+        ConfigError::at(
+            item.pos,
+            format!(
+                "invalid pattern `{}`, expected one of {}",
+                s,
+                Pattern::names()
+            ),
+        )
+    })
+}
+
+/// `host:port`; the host is resolved only when connecting.
+fn address(key: &Key, item: &Item) -> Result<String, ConfigError> {
+    let s = string(key, item)?;
+    match s.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok_and(|p| p > 0) => {
+            Ok(s.to_string())
+        }
+        _ => Err(ConfigError::at(
+            item.pos,
+            format!("invalid address `{}`, expected \"host:port\"", s),
+        )),
+    }
+}
+
+fn port(key: &Key, item: &Item) -> Result<u16, ConfigError> {
+    match item.value {
+        Value::Integer(n) if (1..=65535).contains(&n) => Ok(n as u16),
+        Value::Integer(n) => Err(ConfigError::at(
+            item.pos,
+            format!("`{}` must be between 1 and 65535, found {}", key.name, n),
+        )),
+        _ => Err(wrong_type(key, item, "an integer")),
+    }
+}
+
+fn non_negative(key: &Key, item: &Item) -> Result<u64, ConfigError> {
+    match item.value {
+        Value::Integer(n) if n >= 0 => Ok(n as u64),
+        Value::Integer(n) => Err(ConfigError::at(
+            item.pos,
+            format!("`{}` must not be negative, found {}", key.name, n),
+        )),
+        _ => Err(wrong_type(key, item, "an integer")),
+    }
+}
+
+fn color_value(key: &Key, item: &Item) -> Result<u32, ConfigError> {
+    let s = string(key, item)?;
+    color::parse_hex(s).ok_or_else(|| {
+        ConfigError::at(
+            item.pos,
+            format!(
+                "invalid color `{}` for `{}`, expected \"#RRGGBB\"",
+                s, key.name
+            ),
+        )
+    })
+}
+
+fn monitor_numbers(key: &Key, item: &Item) -> Result<Vec<u32>, ConfigError> {
+    array(key, item)?
+        .iter()
+        .map(|entry| match entry.value {
+            Value::Integer(n) if n >= 1 && n <= u32::MAX as i64 => Ok(n as u32),
+            _ => Err(ConfigError::at(
+                entry.pos,
+                format!("`{}` must list monitor numbers starting at 1", key.name),
+            )),
+        })
+        .collect()
+}
+
+fn number_list(numbers: &[u32]) -> String {
+    let items: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    format!("[{}]", items.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::{ChordResult, ChordState};
+    use std::time::Instant;
+
+    struct Case {
+        name: &'static str,
+        flag: Option<&'static str>,
+        env: &'static [(&'static str, &'static str)],
+        files: &'static [&'static str],
+        expected: &'static str,
+    }
+
+    const EXE_DIR: &str = "/opt/blanqr";
+
+    fn resolve(case: &Case) -> PathBuf {
+        resolve_config_path(
+            case.flag.map(Path::new),
+            |name| {
+                case.env
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| PathBuf::from(value))
+            },
+            Some(Path::new(EXE_DIR)),
+            |path| case.files.iter().any(|f| Path::new(f) == path),
+        )
+    }
+
+    #[test]
+    fn config_path_precedence() {
+        // Every level is present in the environment of the one above it, so
+        // each case shows that level winning over everything below
+        #[cfg(windows)]
+        const USER: (&str, &str) = ("APPDATA", "/users/me/appdata");
+        #[cfg(windows)]
+        const USER_CONFIG: &str = "/users/me/appdata/Blanqr/config.toml";
+        #[cfg(not(windows))]
+        const USER: (&str, &str) = ("XDG_CONFIG_HOME", "/home/me/.xdg");
+        #[cfg(not(windows))]
+        const USER_CONFIG: &str = "/home/me/.xdg/blanqr/config.toml";
+
+        let cases = [
+            Case {
+                name: "flag file",
+                flag: Some("/etc/flag.toml"),
+                env: &[("BLANQR_CONFIG", "/etc/env.toml"), USER],
+                files: &["/opt/blanqr/config.toml"],
+                expected: "/etc/flag.toml",
+            },
+            Case {
+                name: "flag directory",
+                flag: Some("/etc/flagdir"),
+                env: &[("BLANQR_CONFIG", "/etc/env.toml")],
+                files: &[],
+                expected: "/etc/flagdir/config.toml",
+            },
+            Case {
+                name: "flag naming an existing file without extension",
+                flag: Some("/etc/blanqrrc"),
+                env: &[],
+                files: &["/etc/blanqrrc"],
+                expected: "/etc/blanqrrc",
+            },
+            Case {
+                name: "environment variable",
+                flag: None,
+                env: &[("BLANQR_CONFIG", "/etc/env.toml"), USER],
+                files: &["/opt/blanqr/config.toml"],
+                expected: "/etc/env.toml",
+            },
+            Case {
+                name: "environment variable directory",
+                flag: None,
+                env: &[("BLANQR_CONFIG", "/etc/envdir")],
+                files: &[],
+                expected: "/etc/envdir/config.toml",
+            },
+            Case {
+                name: "empty environment variable is ignored",
+                flag: None,
+                env: &[("BLANQR_CONFIG", ""), USER],
+                files: &[],
+                expected: USER_CONFIG,
+            },
+            Case {
+                name: "portable config",
+                flag: None,
+                env: &[USER],
+                files: &["/opt/blanqr/config.toml"],
+                expected: "/opt/blanqr/config.toml",
+            },
+            Case {
+                name: "portable legacy config",
+                flag: None,
+                env: &[USER],
+                files: &["/opt/blanqr/config.ini"],
+                expected: "/opt/blanqr/config.toml",
+            },
+            Case {
+                name: "user config directory",
+                flag: None,
+                env: &[USER, ("HOME", "/home/me")],
+                files: &[],
+                expected: USER_CONFIG,
+            },
+            Case {
+                name: "current directory",
+                flag: None,
+                env: &[],
+                files: &[],
+                expected: "./config.toml",
+            },
+        ];
+
+        for case in &cases {
+            assert_eq!(resolve(case), PathBuf::from(case.expected), "{}", case.name);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn config_path_falls_back_to_home() {
+        let relative_xdg = Case {
+            name: "relative XDG_CONFIG_HOME is ignored",
+            flag: None,
+            env: &[("XDG_CONFIG_HOME", "relative"), ("HOME", "/home/me")],
+            files: &[],
+            expected: "/home/me/.config/blanqr/config.toml",
+        };
+        assert_eq!(resolve(&relative_xdg), PathBuf::from(relative_xdg.expected));
+    }
+
+    #[test]
+    fn config_path_without_exe_dir() {
+        let path = resolve_config_path(None, |_| None, None, |_| true);
+        assert_eq!(path, PathBuf::from("./config.toml"));
+    }
+
+    fn parse_error(content: &str) -> String {
+        match Config::parse(content) {
+            Ok(_) => panic!("parsed {:?}", content),
+            Err(err) => err.to_string(),
         }
     }
+
+    #[test]
+    fn parse_errors_carry_line_and_column() {
+        let cases = [
+            (
+                "[general]\nhotkey =\n",
+                "2:9: expected a value, found end of line",
+            ),
+            (
+                "[general]\n[general]\n",
+                "2:1: table `general` is defined twice",
+            ),
+            ("\n[genral]\n", "2:2: unknown section `genral`"),
+            (
+                "[general]\nosd = true\nhotkye = \"Ctrl+B\"\n",
+                "3:1: unknown key `hotkye` in [general]",
+            ),
+            (
+                "[dimmer]\nlevle = 40\n",
+                "2:1: unknown key `levle` in [dimmer]",
+            ),
+            (
+                "general = 1\n",
+                "1:11: `general` must be a table, found integer",
+            ),
+            (
+                "[general]\nosd = \"yes\"\n",
+                "2:7: `osd` must be a boolean, found string",
+            ),
+            (
+                "[general]\nchord_timeout = \"1s\"\n",
+                "2:17: `chord_timeout` must be an integer, found string",
+            ),
+            (
+                "[colors]\ndefault = 255\n",
+                "2:11: `default` must be a string, found integer",
+            ),
+            (
+                "presets = \"red\"\n",
+                "1:11: `presets` must be an array, found string",
+            ),
+            (
+                "[general]\nopacity = 0\n",
+                "2:11: `opacity` must be between 1 and 100, found 0",
+            ),
+            (
+                "[general]\nhotkey = \"Ctrl+Shfit+B\"\n",
+                "2:10: invalid hotkey `Ctrl+Shfit+B`",
+            ),
+            (
+                "[[presets]]\nname = \"Red\"\n",
+                "1:1: preset is missing `color`",
+            ),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(parse_error(content), expected, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn error_names_the_file() {
+        let err = Config::parse("[general]\nosd = 1\n")
+            .err()
+            .unwrap()
+            .in_file(Path::new("blanqr/config.toml"));
+        assert_eq!(
+            err.to_string(),
+            "blanqr/config.toml:2:7: `osd` must be a boolean, found integer"
+        );
+    }
+
+    const CUSTOM: &str = r##"
+[general]
+hotkey = "Ctrl+Alt+K"
+chord_timeout = 800
+patch_advance = 2000
+auto_hide = "30m"
+failsafe = "2h"
+countdown = true
+osd = true
+keep_awake = "sessions"
+fade_in = 250
+fade_out = 500
+opacity = 80
+
+[colors]
+default = "#102030"
+recent = ["#FFFFFF", "#FF0000"]
+
+[monitors]
+include = [2, 3]
+exclude = []
+
+[window]
+size = "400x300"
+x = 10
+y = 90
+background = "#808080"
+
+[keymap]
+"Ctrl+Alt+K, W" = "color #FFFFFF"
+"Ctrl+Alt+K, 1" = "preset 1"
+
+[[presets]]
+name = "Paper \"white\""
+color = "#F4F1EA"
+
+[[sequences]]
+name = "warm up"
+loop = false
+steps = [
+  { color = "#FFFFFF", pattern = "grid", duration = "30s" },
+  { color = "#000000", duration = "500ms" },
+]
+
+[[schedule]]
+days = ["weekdays"]
+start = "22:00"
+end = "06:30"
+color = "#200000"
+monitors = [1]
+
+[idle]
+after = "10m"
+color = "#000010"
+
+[dimmer]
+hotkey = "Ctrl+Alt+D"
+color = "#301000"
+level = 40
+step = 5
+monitors = [2]
+
+[http]
+enabled = true
+port = 9000
+token = "secret"
+
+[generator]
+enabled = true
+address = "calibration.local:20002"
+"##;
+
+    #[test]
+    fn to_toml_round_trips_a_custom_config() {
+        let config = Config::parse(CUSTOM).unwrap();
+        let default = Config::default();
+        // Every setting but the profiles differs from the defaults
+        assert_eq!(config.changes(&default).len(), 19);
+        assert_eq!(config.hotkey.display(), "Ctrl+Alt+K");
+        assert_eq!(config.failsafe, Some(Duration::from_secs(7200)));
+        assert_eq!(config.keep_awake, KeepAwake::Sessions);
+        assert_eq!(config.presets[0].name, "Paper \"white\"");
+        assert_eq!(config.sequences[0].steps[1].pattern, Pattern::Solid);
+        assert_eq!(config.schedule[0].start, 22 * 60);
+        assert_eq!(config.dimmer.level, 40);
+
+        let toml = config.to_toml();
+        let reparsed = Config::parse(&toml).unwrap();
+        assert_eq!(reparsed.to_toml(), toml);
+        assert!(reparsed == config);
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let config = Config::default();
+        assert!(Config::parse(&config.to_toml()).unwrap() == config);
+        assert!(Config::parse("").unwrap() == config);
+    }
+
+    #[test]
+    fn changes_name_the_edited_sections() {
+        let base = Config::parse(CUSTOM).unwrap();
+        let edit = |f: &dyn Fn(&mut Config)| {
+            let mut config = base.clone();
+            f(&mut config);
+            base.changes(&config)
+        };
+
+        assert_eq!(
+            edit(&|c| c.hotkey = HotkeyConfig::parse("Ctrl+Alt+J").unwrap()),
+            ["hotkey"]
+        );
+        assert_eq!(edit(&|c| c.bindings.clear()), ["key bindings"]);
+        assert_eq!(
+            edit(&|c| c.chord_timeout = Duration::from_secs(2)),
+            ["key bindings"]
+        );
+        assert_eq!(
+            edit(&|c| c.dimmer.hotkey = None),
+            ["key bindings", "dimmer"]
+        );
+        assert_eq!(
+            edit(&|c| {
+                c.color = 0xFFFFFF;
+                c.opacity = 50;
+                c.monitors = MonitorSelection::default();
+                c.window = None;
+            }),
+            ["opacity", "default color", "monitors", "patch window"]
+        );
+        assert_eq!(edit(&|c| c.failsafe = None), ["hide timeouts"]);
+        assert_eq!(edit(&|c| c.http.port = 9001), ["HTTP API"]);
+    }
+
+    #[test]
+    fn reloading_the_same_file_changes_nothing() {
+        let config = Config::parse(CUSTOM).unwrap();
+        let reloaded = Config::parse(CUSTOM).unwrap();
+        assert!(config.changes(&reloaded).is_empty());
+        let rewritten = Config::parse(&config.to_toml()).unwrap();
+        assert!(config.changes(&rewritten).is_empty());
+    }
+
+    const PROFILE: &str = r##"
+[[profiles]]
+name = "Soft proof"
+color = "#F4F1EA"
+pattern = "checkerboard"
+monitors = { include = [2], exclude = [1] }
+dismiss_on_click = false
+dismiss_on_escape = true
+brightness = 40
+opacity = 90
+window = { size = "10%", x = 50, y = 25, background = "#202020" }
+"##;
+
+    #[test]
+    fn parses_a_profile() {
+        let config = Config::parse(PROFILE).unwrap();
+        let profile = config.profile("Soft proof").unwrap();
+        assert_eq!(
+            profile.settings,
+            OverlaySettings {
+                color: 0xEAF1F4, // #F4F1EA as BGR
+                pattern: Pattern::Checkerboard,
+                monitors: MonitorSelection {
+                    include: vec![2],
+                    exclude: vec![1],
+                },
+                dismissal: Dismissal {
+                    on_click: false,
+                    on_escape: true,
+                },
+                brightness: 40,
+                opacity: 90,
+                window: Some(PatchWindow {
+                    size: PatchSize::Area(10),
+                    x: 50,
+                    y: 25,
+                    background: 0x202020,
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_profiles() {
+        let cases = [
+            (
+                "[[profiles]]\nname = \"a\"\nbrigthness = 40\n",
+                "3:1: unknown key `brigthness` in [profiles]",
+            ),
+            (
+                "[[profiles]]\ncolor = \"#FFFFFF\"\n",
+                "1:1: profile is missing `name`",
+            ),
+            (
+                "[[profiles]]\nname = \"a\"\n[[profiles]]\nname = \"a\"\n",
+                "3:1: profile `a` is defined twice",
+            ),
+            (
+                "[keymap]\n\"Ctrl+Shift+B, P\" = \"profile Night\"\n",
+                "2:21: profile `Night` does not exist",
+            ),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(parse_error(content), expected, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn profiles_survive_to_toml() {
+        let mut config = Config::parse(PROFILE).unwrap();
+        config.profiles.push(Profile {
+            name: "Plain".to_string(),
+            settings: OverlaySettings::default(),
+        });
+        let reparsed = Config::parse(&config.to_toml()).unwrap();
+        assert!(reparsed.profiles == config.profiles);
+        assert_eq!(reparsed.profiles[1].settings.window, None);
+    }
+
+    fn hotkey_of(s: &str) -> HotkeyConfig {
+        HotkeyConfig::parse(s).unwrap()
+    }
+
+    #[test]
+    fn dimmer_hotkey_is_in_the_keymap() {
+        let config = Config::parse("[dimmer]\nhotkey = \"Ctrl+Alt+D\"\n").unwrap();
+        let hotkey = hotkey_of("Ctrl+Alt+D");
+        let keymap = config.keymap();
+        assert!(keymap.leaders().contains(&hotkey));
+        let mut chord = ChordState::new(config.chord_timeout);
+        assert_eq!(
+            chord.feed(&keymap, hotkey.clone(), Instant::now()),
+            ChordResult::Matched(Action::Dimmer)
+        );
+        assert!(!Config::default().keymap().leaders().contains(&hotkey));
+    }
+
+    #[test]
+    fn dimmer_hotkey_edits_rebuild_the_keymap() {
+        let base = Config::default();
+        let mut edited = base.clone();
+        edited.dimmer.hotkey = Some(hotkey_of("Ctrl+Alt+D"));
+        assert!(base.keys_changed(&edited));
+
+        let mut moved = edited.clone();
+        moved.dimmer.hotkey = Some(hotkey_of("Ctrl+Alt+F"));
+        assert!(edited.keys_changed(&moved));
+
+        let mut dimmer_only = base.clone();
+        dimmer_only.dimmer.level = 30;
+        assert!(!base.keys_changed(&dimmer_only));
+    }
+
+    #[test]
+    fn parses_dimmer_settings() {
+        let config =
+            Config::parse("[dimmer]\ncolor = \"#301000\"\nlevel = 40\nstep = 5\nmonitors = [2]\n")
+                .unwrap();
+        assert_eq!(
+            config.dimmer,
+            DimmerSettings {
+                hotkey: None,
+                color: 0x001030,
+                level: 40,
+                step: 5,
+                monitors: vec![2],
+            }
+        );
+        assert_eq!(
+            parse_error("[dimmer]\nlevel = 0\n"),
+            "2:9: `level` must be between 1 and 100, found 0"
+        );
+        assert_eq!(
+            parse_error("[dimmer]\nhotkey = \"Ctrl+\"\n"),
+            "2:10: invalid hotkey `Ctrl+`"
+        );
+    }
+
+    #[test]
+    fn dimmer_steps_stay_in_range() {
+        let dimmer = DimmerSettings::default();
+        assert_eq!(dimmer.step_level(50, 1), 60);
+        assert_eq!(dimmer.step_level(50, -2), 30);
+        assert_eq!(dimmer.step_level(90, 1), MAX_DIMMER_LEVEL);
+        assert_eq!(dimmer.step_level(10, -1), MIN_DIMMER_LEVEL);
+        assert_eq!(dimmer.step_level(100, 0), MAX_DIMMER_LEVEL);
+    }
+
+    #[test]
+    fn legacy_config_skips_what_it_cannot_read() {
+        let (config, skipped) = Config::parse_legacy(
+            "# blanqr settings\n\
+             hotkey = Ctrl+Alt+K\n\
+             chord_timeout = soon\n\
+             bind = Ctrl+Alt+K, W = color #FFFFFF\n\
+             bind = Ctrl+Alt+K, X = explode\n\
+             color = red\n  \
+             half a line\n",
+        );
+        assert_eq!(config.hotkey.display(), "Ctrl+Alt+K");
+        assert_eq!(config.chord_timeout, DEFAULT_CHORD_TIMEOUT);
+        assert_eq!(config.bindings.len(), 1);
+        assert_eq!(config.bindings[0].action, Action::Color(0xFFFFFF));
+        assert_eq!(config.color, Config::default().color);
+        let skipped: Vec<String> = skipped.iter().map(ConfigError::to_string).collect();
+        assert_eq!(
+            skipped,
+            [
+                "3:1: invalid `chord_timeout` value `soon`",
+                "5:1: invalid `bind` value `Ctrl+Alt+K, X = explode`",
+                "6:1: unknown key `color`",
+                "7:3: expected `key = value`",
+            ]
+        );
+    }
+
+    #[test]
+    fn migrates_legacy_config_to_toml() {
+        let dir = std::env::temp_dir().join(format!("blanqr-migrate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join(LEGACY_CONFIG_FILE);
+        let path = dir.join(CONFIG_FILE);
+        fs::write(
+            &legacy,
+            "hotkey = Ctrl+Alt+K\r\n\
+             chord_timeout = 900\r\n\
+             bind = Ctrl+Alt+K, W = color #FFFFFF\r\n",
+        )
+        .unwrap();
+
+        let config = Config::migrate(&legacy, &path).unwrap();
+        assert_eq!(config.hotkey.display(), "Ctrl+Alt+K");
+        assert_eq!(config.chord_timeout, Duration::from_millis(900));
+        assert_eq!(config.bindings.len(), 1);
+
+        let written = Config::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(written == config);
+        assert!(!legacy.exists());
+        assert!(dir.join("config.ini.bak").is_file());
+        assert!(Config::migrate(&legacy, &path).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,296 @@
+//! Control server on `\\.\pipe\blanqr`. Connections are read on background
+//! threads; decoded requests are queued for the UI thread, which answers and
+//! publishes events through `ControlServer`.
+//!
+//! Pipe handles are overlapped so the UI thread can write a response or event
+//! while the connection's thread is blocked in a read.
+//!
+//! Windows only; there is no Unix socket counterpart.
+
+use crate::protocol::{self, Event, EventKind, Request};
+use std::mem::zeroed;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_IO_PENDING, ERROR_PIPE_CONNECTED, HANDLE,
+    INVALID_HANDLE_VALUE, WAIT_OBJECT_0,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    ReadFile, WriteFile, FILE_FLAG_OVERLAPPED, PIPE_ACCESS_DUPLEX,
+};
+use windows_sys::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+    PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+use windows_sys::Win32::System::Threading::{
+    CreateEventW, GetCurrentThreadId, WaitForSingleObject, INFINITE,
+};
+use windows_sys::Win32::System::IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED};
+use windows_sys::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_APP};
+
+pub const PIPE_NAME: &str = r"\\.\pipe\blanqr";
+
+/// Posted to the UI thread when requests are waiting in `ControlServer::take`.
+pub const WM_CONTROL: u32 = WM_APP + 2;
+
+const BUFFER_SIZE: u32 = 64 * 1024;
+/// A client that stops reading is dropped rather than stalling the UI thread.
+const WRITE_TIMEOUT_MS: u32 = 1000;
+/// Longest accepted request line.
+const MAX_LINE: usize = 64 * 1024;
+
+/// Owned handle that may cross threads; Win32 handles are thread-agnostic.
+struct Handle(HANDLE);
+
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+pub struct Client {
+    pipe: Handle,
+    /// Keeps responses and events from interleaving.
+    writing: Mutex<()>,
+    subscriptions: Mutex<Vec<EventKind>>,
+    closed: AtomicBool,
+}
+
+impl Client {
+    /// Writes one line. Failures close the connection.
+    pub fn send(&self, line: &str) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut data = line.as_bytes().to_vec();
+        data.push(b'\n');
+        let _writing = lock(&self.writing);
+        let written = overlapped_io(&self.pipe, &mut data, false, WRITE_TIMEOUT_MS);
+        if written != Some(data.len()) {
+            self.close();
+        }
+    }
+
+    pub fn subscribe(&self, events: &[EventKind]) {
+        let mut subscriptions = lock(&self.subscriptions);
+        for &kind in events {
+            if !subscriptions.contains(&kind) {
+                subscriptions.push(kind);
+            }
+        }
+    }
+
+    pub fn unsubscribe(&self, events: &[EventKind]) {
+        let mut subscriptions = lock(&self.subscriptions);
+        subscriptions.retain(|kind| !events.contains(kind));
+    }
+
+    fn is_subscribed(&self, kind: EventKind) -> bool {
+        let subscriptions = lock(&self.subscriptions);
+        subscriptions.contains(&kind)
+    }
+
+    fn close(&self) {
+        if !self.closed.swap(true, Ordering::Relaxed) {
+            unsafe {
+                // Unblocks the reader thread, which then cleans up
+                CancelIoEx(self.pipe.0, null_mut());
+                DisconnectNamedPipe(self.pipe.0);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    clients: Mutex<Vec<Arc<Client>>>,
+    queue: Mutex<Vec<(Arc<Client>, Request)>>,
+}
+
+pub struct ControlServer {
+    shared: Arc<Shared>,
+}
+
+impl ControlServer {
+    /// Starts accepting connections. `WM_CONTROL` is posted to the calling
+    /// thread whenever requests arrive.
+    pub fn start() -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread_id = unsafe { GetCurrentThreadId() };
+
+        let listener = Arc::clone(&shared);
+        std::thread::spawn(move || accept_loop(listener, thread_id));
+
+        ControlServer { shared }
+    }
+
+    /// Requests received since the last call, in arrival order.
+    pub fn take(&self) -> Vec<(Arc<Client>, Request)> {
+        std::mem::take(&mut *lock(&self.shared.queue))
+    }
+
+    /// Sends `event` to every connection subscribed to it.
+    pub fn publish(&self, event: Event) {
+        let clients: Vec<Arc<Client>> = lock(&self.shared.clients).clone();
+        let subscribed: Vec<_> = clients
+            .iter()
+            .filter(|c| c.is_subscribed(event.kind()))
+            .collect();
+        if subscribed.is_empty() {
+            return;
+        }
+
+        let line = protocol::encode_event(event);
+        for client in subscribed {
+            client.send(&line);
+        }
+    }
+}
+
+/// A panic elsewhere must not take the control server down with it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn accept_loop(shared: Arc<Shared>, thread_id: u32) {
+    let name: Vec<u16> = PIPE_NAME.encode_utf16().chain(std::iter::once(0)).collect();
+    loop {
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                null_mut(),
+            )
+        };
+        if pipe == INVALID_HANDLE_VALUE {
+            return;
+        }
+        let pipe = Handle(pipe);
+        if !connect(&pipe) {
+            continue;
+        }
+
+        let client = Arc::new(Client {
+            pipe,
+            writing: Mutex::new(()),
+            subscriptions: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+        });
+        lock(&shared.clients).push(Arc::clone(&client));
+
+        let shared = Arc::clone(&shared);
+        std::thread::spawn(move || read_loop(shared, client, thread_id));
+    }
+}
+
+/// Waits for a client to connect to `pipe`.
+fn connect(pipe: &Handle) -> bool {
+    unsafe {
+        let event = Handle(CreateEventW(null_mut(), 1, 0, null_mut()));
+        let mut overlapped: OVERLAPPED = zeroed();
+        overlapped.hEvent = event.0;
+        if ConnectNamedPipe(pipe.0, &mut overlapped) != 0 {
+            return true;
+        }
+        match GetLastError() {
+            ERROR_PIPE_CONNECTED => true,
+            ERROR_IO_PENDING => {
+                let mut transferred = 0;
+                GetOverlappedResult(pipe.0, &overlapped, &mut transferred, 1) != 0
+            }
+            _ => false,
+        }
+    }
+}
+
+fn read_loop(shared: Arc<Shared>, client: Arc<Client>, thread_id: u32) {
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buf = vec![0u8; BUFFER_SIZE as usize];
+
+    while let Some(n) = overlapped_io(&client.pipe, &mut buf, true, INFINITE) {
+        if n == 0 || client.closed.load(Ordering::Relaxed) {
+            break;
+        }
+        pending.extend_from_slice(&buf[..n]);
+
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match protocol::parse_request(line) {
+                Ok(request) => {
+                    lock(&shared.queue).push((Arc::clone(&client), request));
+                    unsafe {
+                        PostThreadMessageW(thread_id, WM_CONTROL, 0, 0);
+                    }
+                }
+                Err(err) => client.send(&protocol::encode_response(
+                    err.id.as_ref(),
+                    Err(err.message),
+                )),
+            }
+        }
+
+        if pending.len() > MAX_LINE {
+            client.send(&protocol::encode_response(
+                None,
+                Err("request line too long".to_string()),
+            ));
+            break;
+        }
+    }
+
+    client.close();
+    lock(&shared.clients).retain(|c| !Arc::ptr_eq(c, &client));
+}
+
+/// One overlapped read or write on `pipe`, waiting up to `timeout_ms`.
+/// Returns the bytes transferred, or `None` on error or timeout.
+fn overlapped_io(pipe: &Handle, buf: &mut [u8], read: bool, timeout_ms: u32) -> Option<usize> {
+    unsafe {
+        let event = Handle(CreateEventW(null_mut(), 1, 0, null_mut()));
+        if event.0.is_null() {
+            return None;
+        }
+        let mut overlapped: OVERLAPPED = zeroed();
+        overlapped.hEvent = event.0;
+
+        let len = buf.len() as u32;
+        let started = if read {
+            ReadFile(pipe.0, buf.as_mut_ptr(), len, null_mut(), &mut overlapped)
+        } else {
+            WriteFile(pipe.0, buf.as_ptr(), len, null_mut(), &mut overlapped)
+        };
+        if started == 0 && GetLastError() != ERROR_IO_PENDING {
+            return None;
+        }
+
+        if WaitForSingleObject(event.0, timeout_ms) != WAIT_OBJECT_0 {
+            CancelIoEx(pipe.0, &overlapped);
+            // The OVERLAPPED must outlive the cancelled operation
+            let mut transferred = 0;
+            GetOverlappedResult(pipe.0, &overlapped, &mut transferred, 1);
+            return None;
+        }
+
+        let mut transferred = 0;
+        if GetOverlappedResult(pipe.0, &overlapped, &mut transferred, 0) == 0 {
+            return None;
+        }
+        Some(transferred as usize)
+    }
+}
@@ -0,0 +1,130 @@
+//! Timing for overlay fade transitions. Pure: callers pass the current time
+//! on each frame and apply `value` as window opacity.
+
+use std::time::{Duration, Instant};
+
+/// A transition from one opacity (0.0-1.0) to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fade {
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    pub fn new(from: f64, to: f64, duration: Duration, now: Instant) -> Self {
+        Fade {
+            from,
+            to,
+            start: now,
+            duration,
+        }
+    }
+
+    /// Opacity at `now`, eased; `to` once the fade is over.
+    pub fn value(&self, now: Instant) -> f64 {
+        self.from + (self.to - self.from) * ease_in_out(self.progress(now))
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        self.progress(now) >= 1.0
+    }
+
+    /// Linear progress, 0.0-1.0.
+    fn progress(&self, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        let elapsed = now.saturating_duration_since(self.start);
+        (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+    }
+}
+
+/// Smoothstep: starts and ends gently, so neither end of the fade pops.
+pub fn ease_in_out(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Window alpha for an opacity in 0.0-1.0.
+pub fn alpha(opacity: f64) -> u8 {
+    (opacity.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn starts_and_ends_at_the_endpoints() {
+        let start = Instant::now();
+        let fade = Fade::new(0.2, 0.8, SECOND, start);
+        assert_eq!(fade.value(start), 0.2);
+        assert!(!fade.is_done(start));
+        assert_eq!(fade.value(start + SECOND), 0.8);
+        assert!(fade.is_done(start + SECOND));
+        assert_eq!(fade.value(start + 10 * SECOND), 0.8);
+    }
+
+    #[test]
+    fn runs_either_way() {
+        let start = Instant::now();
+        let fade_out = Fade::new(1.0, 0.0, 2 * SECOND, start);
+        assert_eq!(fade_out.value(start + SECOND), 0.5);
+        let fade_in = Fade::new(0.0, 1.0, 2 * SECOND, start);
+        assert_eq!(fade_in.value(start + SECOND), 0.5);
+    }
+
+    #[test]
+    fn eases_at_both_ends() {
+        let start = Instant::now();
+        let fade = Fade::new(0.0, 1.0, SECOND, start);
+        // Slower than linear near the ends, faster in the middle
+        assert!(fade.value(start + SECOND / 10) < 0.1);
+        assert!(fade.value(start + SECOND * 9 / 10) > 0.9);
+        let mut last = 0.0;
+        for tenth in 1..=10 {
+            let value = fade.value(start + SECOND * tenth / 10);
+            assert!(value > last);
+            last = value;
+        }
+    }
+
+    #[test]
+    fn zero_duration_is_done_at_once() {
+        let start = Instant::now();
+        let fade = Fade::new(0.0, 0.6, Duration::ZERO, start);
+        assert!(fade.is_done(start));
+        assert_eq!(fade.value(start), 0.6);
+    }
+
+    #[test]
+    fn times_before_the_start_count_as_the_start() {
+        let start = Instant::now() + SECOND;
+        let fade = Fade::new(1.0, 0.0, SECOND, start);
+        assert_eq!(fade.value(start - SECOND / 2), 1.0);
+    }
+
+    #[test]
+    fn smoothstep() {
+        assert_eq!(ease_in_out(0.0), 0.0);
+        assert_eq!(ease_in_out(0.25), 0.15625);
+        assert_eq!(ease_in_out(0.5), 0.5);
+        assert_eq!(ease_in_out(0.75), 0.84375);
+        assert_eq!(ease_in_out(1.0), 1.0);
+        assert_eq!(ease_in_out(-1.0), 0.0);
+        assert_eq!(ease_in_out(2.0), 1.0);
+    }
+
+    #[test]
+    fn converts_to_alpha() {
+        assert_eq!(alpha(0.0), 0);
+        assert_eq!(alpha(0.5), 128);
+        assert_eq!(alpha(1.0), 255);
+        assert_eq!(alpha(1.5), 255);
+        assert_eq!(alpha(-0.5), 0);
+    }
+}
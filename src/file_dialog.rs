@@ -0,0 +1,94 @@
+use std::mem::zeroed;
+use std::path::PathBuf;
+use windows_sys::Win32::UI::Controls::Dialogs::{
+    GetOpenFileNameW, GetSaveFileNameW, OFN_EXPLORER, OFN_FILEMUSTEXIST, OFN_NOCHANGEDIR,
+    OFN_OVERWRITEPROMPT, OFN_PATHMUSTEXIST, OPENFILENAMEW,
+};
+
+/// Longest path the dialog can return, in UTF-16 units.
+const MAX_PATH_LEN: usize = 32 * 1024;
+
+/// Asks for an existing file. `filters` pairs a description with
+/// `;`-separated patterns, e.g. `("CSV files", "*.csv")`. Returns `None` if
+/// cancelled.
+pub fn show_open_dialog(title: &str, filters: &[(&str, &str)]) -> Option<PathBuf> {
+    let filter = filter_list(filters);
+    let title = wide_str(title);
+    let mut path = vec![0u16; MAX_PATH_LEN];
+
+    unsafe {
+        let mut ofn: OPENFILENAMEW = zeroed();
+        ofn.lStructSize = std::mem::size_of::<OPENFILENAMEW>() as u32;
+        ofn.lpstrFilter = filter.as_ptr();
+        ofn.nFilterIndex = 1;
+        ofn.lpstrFile = path.as_mut_ptr();
+        ofn.nMaxFile = path.len() as u32;
+        ofn.lpstrTitle = title.as_ptr();
+        // Keep the working directory, relative config paths depend on it
+        ofn.Flags = OFN_EXPLORER | OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR;
+
+        if GetOpenFileNameW(&mut ofn) == 0 {
+            return None;
+        }
+    }
+
+    let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&path[..len])))
+}
+
+/// Asks for a file to write, starting with `file_name`, and confirms before
+/// replacing one. A name typed without an extension gets the selected
+/// filter's. Returns `None` if cancelled.
+pub fn show_save_dialog(title: &str, filters: &[(&str, &str)], file_name: &str) -> Option<PathBuf> {
+    let filter = filter_list(filters);
+    let title = wide_str(title);
+    // Any non-empty default makes the dialog use the filter's extension
+    let default_ext = filters
+        .first()
+        .and_then(|(_, patterns)| patterns.split(';').next())
+        .and_then(|pattern| pattern.rsplit_once('.'))
+        .map(|(_, ext)| wide_str(ext));
+    let mut path = vec![0u16; MAX_PATH_LEN];
+    for (slot, c) in path[..MAX_PATH_LEN - 1]
+        .iter_mut()
+        .zip(file_name.encode_utf16())
+    {
+        *slot = c;
+    }
+
+    unsafe {
+        let mut ofn: OPENFILENAMEW = zeroed();
+        ofn.lStructSize = std::mem::size_of::<OPENFILENAMEW>() as u32;
+        ofn.lpstrFilter = filter.as_ptr();
+        ofn.nFilterIndex = 1;
+        ofn.lpstrFile = path.as_mut_ptr();
+        ofn.nMaxFile = path.len() as u32;
+        ofn.lpstrTitle = title.as_ptr();
+        if let Some(ext) = &default_ext {
+            ofn.lpstrDefExt = ext.as_ptr();
+        }
+        ofn.Flags = OFN_EXPLORER | OFN_OVERWRITEPROMPT | OFN_PATHMUSTEXIST | OFN_NOCHANGEDIR;
+
+        if GetSaveFileNameW(&mut ofn) == 0 {
+            return None;
+        }
+    }
+
+    let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&path[..len])))
+}
+
+/// Pairs of nul-terminated strings, ending with an extra nul.
+fn filter_list(filters: &[(&str, &str)]) -> Vec<u16> {
+    let mut filter: Vec<u16> = Vec::new();
+    for (description, patterns) in filters {
+        filter.extend(description.encode_utf16().chain(std::iter::once(0)));
+        filter.extend(patterns.encode_utf16().chain(std::iter::once(0)));
+    }
+    filter.push(0);
+    filter
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
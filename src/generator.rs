@@ -0,0 +1,230 @@
+//! Connection to calibration software acting as a Resolve pattern generator
+//! server. Runs on a background thread that keeps reconnecting, so the
+//! session can be started before or after the calibration software.
+
+use crate::patch::Patch;
+use crate::resolve;
+use std::io::Read;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+use windows_sys::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_APP};
+
+/// Posted to the UI thread when events are waiting in `Generator::take`.
+pub const WM_GENERATOR: u32 = WM_APP + 4;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+/// How often a waiting thread checks whether it was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeneratorEvent {
+    Connected,
+    Disconnected(String),
+    Patch(Patch),
+}
+
+#[derive(Default)]
+struct Shared {
+    events: Mutex<Vec<GeneratorEvent>>,
+    stopped: AtomicBool,
+    /// The live connection, so `drop` can interrupt a blocking read.
+    stream: Mutex<Option<TcpStream>>,
+}
+
+pub struct Generator {
+    shared: Arc<Shared>,
+}
+
+impl Generator {
+    /// Starts connecting to `address` (`host:port`). `WM_GENERATOR` is posted
+    /// to the calling thread whenever there are events.
+    pub fn start(address: &str) -> Self {
+        let thread_id = unsafe { GetCurrentThreadId() };
+        Self::connect(address, move || unsafe {
+            PostThreadMessageW(thread_id, WM_GENERATOR, 0, 0);
+        })
+    }
+
+    /// Like `start`, calling `notify` instead of posting `WM_GENERATOR`.
+    fn connect(address: &str, notify: impl Fn() + Send + 'static) -> Self {
+        let shared = Arc::new(Shared::default());
+        let running = Arc::clone(&shared);
+        let address = address.to_string();
+        std::thread::spawn(move || run(&running, &address, &notify));
+        Generator { shared }
+    }
+
+    /// Events since the last call, in order.
+    pub fn take(&self) -> Vec<GeneratorEvent> {
+        std::mem::take(&mut *lock(&self.shared.events))
+    }
+}
+
+impl Drop for Generator {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        if let Some(stream) = lock(&self.shared.stream).as_ref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        // Not joined: the thread may be in a connect that can't be cut short.
+        // It sees the flag and exits on its own, and anything it posts until
+        // then lands in a queue nobody reads.
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn post(shared: &Shared, event: GeneratorEvent, notify: &dyn Fn()) {
+    let mut events = lock(&shared.events);
+    // Only the newest patch matters if the UI thread falls behind
+    if let (GeneratorEvent::Patch(_), Some(GeneratorEvent::Patch(_))) = (&event, events.last()) {
+        events.pop();
+    }
+    events.push(event);
+    drop(events);
+    notify();
+}
+
+fn run(shared: &Shared, address: &str, notify: &dyn Fn()) {
+    while !shared.stopped.load(Ordering::Relaxed) {
+        if let Some(stream) = connect(address) {
+            if let Ok(clone) = stream.try_clone() {
+                *lock(&shared.stream) = Some(clone);
+            }
+            // Re-check: `drop` may have run before the clone was stored
+            if shared.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+
+            post(shared, GeneratorEvent::Connected, notify);
+            let reason = receive(shared, stream, notify);
+            lock(&shared.stream).take();
+            if shared.stopped.load(Ordering::Relaxed) {
+                break;
+            }
+            post(shared, GeneratorEvent::Disconnected(reason), notify);
+        }
+
+        let mut waited = Duration::ZERO;
+        while waited < RETRY_INTERVAL && !shared.stopped.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL_INTERVAL);
+            waited += POLL_INTERVAL;
+        }
+    }
+}
+
+fn connect(address: &str) -> Option<TcpStream> {
+    let addrs = address.to_socket_addrs().ok()?;
+    addrs
+        .into_iter()
+        .find_map(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).ok())
+}
+
+/// Reads frames until the connection ends. Returns why it ended.
+fn receive(shared: &Shared, mut stream: TcpStream, notify: &dyn Fn()) -> String {
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match stream.read(&mut buf) {
+            Ok(0) => return "connection closed".to_string(),
+            Ok(n) => n,
+            Err(err) => return err.to_string(),
+        };
+        pending.extend_from_slice(&buf[..n]);
+
+        loop {
+            match resolve::take_frame(&mut pending) {
+                Ok(Some(xml)) => match resolve::parse_calibration(&xml) {
+                    Ok(patch) => post(shared, GeneratorEvent::Patch(patch), notify),
+                    // Skip documents we don't understand, keep the session
+                    Err(_) => continue,
+                },
+                Ok(None) => break,
+                Err(err) => return err,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::Window;
+    use std::io::Write;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Instant;
+
+    /// Longer than one retry, so a reconnect has time to happen.
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn frame(xml: &str) -> Vec<u8> {
+        let mut bytes = (xml.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(xml.as_bytes());
+        bytes
+    }
+
+    /// Waits for the next batch of events.
+    fn next(generator: &Generator, notified: &Receiver<()>) -> Vec<GeneratorEvent> {
+        loop {
+            notified.recv_timeout(WAIT).expect("no event in time");
+            let events = generator.take();
+            if !events.is_empty() {
+                return events;
+            }
+        }
+    }
+
+    #[test]
+    fn receives_patches_and_reconnects() {
+        // Stand-in for the calibration software
+        let server = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let (notify_tx, notified) = mpsc::channel();
+        let generator = Generator::connect(&address, move || {
+            let _ = notify_tx.send(());
+        });
+
+        let (mut session, _) = server.accept().unwrap();
+        assert_eq!(next(&generator, &notified), [GeneratorEvent::Connected]);
+
+        // One frame split across writes, plus a document to skip
+        let patch = frame(
+            r#"<calibration><color red="255" green="0" blue="0"/><background red="0" green="0" blue="0"/></calibration>"#,
+        );
+        session.write_all(&patch[..7]).unwrap();
+        session.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        session.write_all(&patch[7..]).unwrap();
+        session.write_all(&frame("<calibration/>")).unwrap();
+        assert_eq!(
+            next(&generator, &notified),
+            [GeneratorEvent::Patch(Patch {
+                foreground: 0x0000FF,
+                background: 0,
+                window: Window::FULL,
+            })]
+        );
+
+        drop(session);
+        assert!(matches!(
+            next(&generator, &notified)[..],
+            [GeneratorEvent::Disconnected(_)]
+        ));
+
+        let (session, _) = server.accept().unwrap();
+        assert_eq!(next(&generator, &notified), [GeneratorEvent::Connected]);
+
+        // Stopping doesn't wait for the thread, even mid-session
+        let stopping = Instant::now();
+        drop(generator);
+        assert!(stopping.elapsed() < Duration::from_millis(100));
+        drop(session);
+    }
+}
@@ -0,0 +1,145 @@
+//! GIMP palettes (`.gpl`): a `GIMP Palette` header, optional `Name:` and
+//! `Columns:` lines, then one `R G B name` line per color.
+
+use crate::color;
+use crate::config::Preset;
+
+const HEADER: &str = "GIMP Palette";
+
+/// Reads the colors of a palette. Colors without a name are named by their
+/// hex value.
+pub fn parse(text: &str) -> Result<Vec<Preset>, String> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, first)) if first.trim_start_matches('\u{feff}').trim() == HEADER => {}
+        _ => return Err(format!("line 1: expected `{}`", HEADER)),
+    }
+
+    let mut presets = Vec::new();
+    for (i, line) in lines {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        let mut rest = line;
+        let mut channels = [0u8; 3];
+        for channel in &mut channels {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            *channel = rest[..end].parse().map_err(|_| {
+                format!("line {}: expected red, green and blue from 0 to 255", i + 1)
+            })?;
+            rest = rest[end..].trim_start();
+        }
+
+        let color = color::rgb_to_colorref(channels[0], channels[1], channels[2]);
+        let name = if rest.is_empty() {
+            color::to_hex(color)
+        } else {
+            rest.to_string()
+        };
+        presets.push(Preset { name, color });
+    }
+    Ok(presets)
+}
+
+/// Writes `presets` as a palette called `name`. Line breaks in names become
+/// spaces, since each name must stay on its color's line.
+pub fn write(name: &str, presets: &[Preset]) -> String {
+    let mut out = format!("{}\nName: {}\n#\n", HEADER, one_line(name));
+    for preset in presets {
+        let (r, g, b) = color::colorref_to_rgb(preset.color);
+        out += &format!("{:3} {:3} {:3}\t{}\n", r, g, b, one_line(&preset.name));
+    }
+    out
+}
+
+fn one_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str, r: u8, g: u8, b: u8) -> Preset {
+        Preset {
+            name: name.to_string(),
+            color: color::rgb_to_colorref(r, g, b),
+        }
+    }
+
+    #[test]
+    fn skips_comments_names_and_columns() {
+        let presets = parse(include_str!("../testdata/palette.gpl")).unwrap();
+        assert_eq!(
+            presets,
+            [
+                preset("Black", 0, 0, 0),
+                preset("Paper White", 255, 255, 255),
+                preset("#808080", 128, 128, 128),
+                preset("Sky  blue", 51, 153, 255),
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_the_header() {
+        assert_eq!(
+            parse("Name: x\n0 0 0 Black\n"),
+            Err("line 1: expected `GIMP Palette`".to_string())
+        );
+        assert_eq!(
+            parse(""),
+            Err("line 1: expected `GIMP Palette`".to_string())
+        );
+        assert_eq!(parse("GIMP Palette\r\n"), Ok(vec![]));
+    }
+
+    #[test]
+    fn rejects_bad_channels() {
+        for line in ["0 0", "0 0 256 Too bright", "0 -1 0", "red 0 0"] {
+            assert_eq!(
+                parse(&format!("GIMP Palette\n#\n{}\n", line)),
+                Err("line 3: expected red, green and blue from 0 to 255".to_string()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let presets = vec![
+            preset("Black", 0, 0, 0),
+            preset("Warm white 5000K", 255, 228, 206),
+            preset("#102030", 16, 32, 48),
+        ];
+        let text = write("Studio", &presets);
+        assert!(text.starts_with("GIMP Palette\nName: Studio\n#\n"));
+        assert!(text.contains("255 228 206\tWarm white 5000K\n"));
+        assert_eq!(parse(&text), Ok(presets));
+    }
+
+    #[test]
+    fn keeps_names_on_one_line() {
+        let presets = vec![
+            preset("Two\nlines", 1, 2, 3),
+            preset("Three\r\nmore", 4, 5, 6),
+        ];
+        let text = write("Multi\nline", &presets);
+        assert_eq!(text.lines().count(), 5);
+        assert!(text.contains("Name: Multi line\n"));
+        assert_eq!(
+            parse(&text),
+            Ok(vec![
+                preset("Two lines", 1, 2, 3),
+                preset("Three  more", 4, 5, 6)
+            ])
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! When a visible overlay hides by itself: at the end of `--duration`, after
+//! auto-hide runs out without input, or at the failsafe limit. Pure: callers
+//! pass the current time and act on what comes due.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Deadlines {
+    /// End of `--duration`. Its own timer hides the overlay; this is only
+    /// for the countdown.
+    pub duration: Option<Instant>,
+    /// Hide once reached unless input or a running session pushes it back.
+    pub auto_hide: Option<Instant>,
+    /// Hide once reached, whatever is running.
+    pub failsafe: Option<Instant>,
+}
+
+/// What a check of the deadlines found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Due {
+    Nothing,
+    AutoHide,
+    Failsafe,
+}
+
+impl Deadlines {
+    /// Starts the auto-hide and failsafe clocks for a newly shown overlay.
+    pub fn start(&mut self, now: Instant, auto_hide: Option<Duration>, failsafe: Option<Duration>) {
+        self.auto_hide = auto_hide.map(|d| now + d);
+        self.failsafe = failsafe.map(|d| now + d);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.duration.is_none() && self.auto_hide.is_none() && self.failsafe.is_none()
+    }
+
+    /// Pushes back a running auto-hide deadline after input.
+    pub fn touch(&mut self, now: Instant, auto_hide: Option<Duration>) {
+        if let (Some(delay), Some(_)) = (auto_hide, self.auto_hide) {
+            self.auto_hide = Some(now + delay);
+        }
+    }
+
+    /// The failsafe wins over everything. Sessions keep the overlay up on
+    /// purpose, so an expired auto-hide is pushed back while one runs.
+    pub fn check(&mut self, now: Instant, auto_hide: Option<Duration>, session: bool) -> Due {
+        if self.failsafe.is_some_and(|d| now >= d) {
+            return Due::Failsafe;
+        }
+        if self.auto_hide.is_some_and(|d| now >= d) {
+            if !session {
+                return Due::AutoHide;
+            }
+            self.touch(now, auto_hide);
+        }
+        Due::Nothing
+    }
+
+    /// The earliest time the overlay will hide by itself.
+    pub fn next(&self, session: bool) -> Option<Instant> {
+        let auto_hide = self.auto_hide.filter(|_| !session);
+        [self.duration, auto_hide, self.failsafe]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+/// `4:05`, or `1:02:03` from an hour up. Rounds up so it never shows `0:00`
+/// while time is left.
+pub fn format_countdown(left: Duration) -> String {
+    let secs = left.as_millis().div_ceil(1000) as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn started(now: Instant) -> Deadlines {
+        let mut deadlines = Deadlines::default();
+        deadlines.start(now, Some(5 * MINUTE), Some(60 * MINUTE));
+        deadlines
+    }
+
+    #[test]
+    fn formats_countdowns() {
+        let cases = [
+            (Duration::ZERO, "0:00"),
+            (Duration::from_millis(1), "0:01"),
+            (Duration::from_millis(999), "0:01"),
+            (Duration::from_secs(1), "0:01"),
+            (Duration::from_secs(59), "0:59"),
+            (Duration::from_millis(59_001), "1:00"),
+            (Duration::from_secs(60), "1:00"),
+            (Duration::from_secs(61), "1:01"),
+            (Duration::from_secs(4 * 60 + 5), "4:05"),
+            (Duration::from_secs(59 * 60 + 59), "59:59"),
+            (Duration::from_secs(3600), "1:00:00"),
+            (Duration::from_secs(3600 + 2 * 60 + 3), "1:02:03"),
+            (Duration::from_secs(25 * 3600), "25:00:00"),
+        ];
+        for (left, expected) in cases {
+            assert_eq!(format_countdown(left), expected, "{:?}", left);
+        }
+    }
+
+    #[test]
+    fn start_keeps_the_duration_deadline() {
+        let now = Instant::now();
+        let mut deadlines = Deadlines {
+            duration: Some(now + MINUTE),
+            ..Deadlines::default()
+        };
+        deadlines.start(now, None, Some(2 * MINUTE));
+        assert_eq!(
+            deadlines,
+            Deadlines {
+                duration: Some(now + MINUTE),
+                auto_hide: None,
+                failsafe: Some(now + 2 * MINUTE),
+            }
+        );
+        assert!(!deadlines.is_empty());
+        assert!(Deadlines::default().is_empty());
+    }
+
+    #[test]
+    fn auto_hide_comes_due_without_input() {
+        let now = Instant::now();
+        let mut deadlines = started(now);
+        let auto_hide = Some(5 * MINUTE);
+        assert_eq!(
+            deadlines.check(now + MINUTE, auto_hide, false),
+            Due::Nothing
+        );
+        assert_eq!(
+            deadlines.check(now + 5 * MINUTE, auto_hide, false),
+            Due::AutoHide
+        );
+    }
+
+    #[test]
+    fn input_pushes_auto_hide_back() {
+        let now = Instant::now();
+        let mut deadlines = started(now);
+        deadlines.touch(now + 4 * MINUTE, Some(5 * MINUTE));
+        assert_eq!(deadlines.auto_hide, Some(now + 9 * MINUTE));
+        assert_eq!(
+            deadlines.check(now + 5 * MINUTE, Some(5 * MINUTE), false),
+            Due::Nothing
+        );
+
+        // Nothing to push back when auto-hide is off
+        let mut off = Deadlines::default();
+        off.touch(now, Some(5 * MINUTE));
+        assert!(off.is_empty());
+    }
+
+    #[test]
+    fn sessions_hold_off_auto_hide_but_not_the_failsafe() {
+        let now = Instant::now();
+        let mut deadlines = started(now);
+        let auto_hide = Some(5 * MINUTE);
+        assert_eq!(deadlines.next(true), Some(now + 60 * MINUTE));
+
+        let late = now + 6 * MINUTE;
+        assert_eq!(deadlines.check(late, auto_hide, true), Due::Nothing);
+        assert_eq!(deadlines.auto_hide, Some(late + 5 * MINUTE));
+
+        assert_eq!(
+            deadlines.check(now + 60 * MINUTE, auto_hide, true),
+            Due::Failsafe
+        );
+    }
+
+    #[test]
+    fn failsafe_wins_when_both_are_due() {
+        let now = Instant::now();
+        let mut deadlines = Deadlines::default();
+        deadlines.start(now, Some(MINUTE), Some(MINUTE));
+        assert_eq!(
+            deadlines.check(now + MINUTE, Some(MINUTE), false),
+            Due::Failsafe
+        );
+    }
+
+    #[test]
+    fn next_is_the_earliest_deadline() {
+        let now = Instant::now();
+        let mut deadlines = started(now);
+        assert_eq!(deadlines.next(false), Some(now + 5 * MINUTE));
+        deadlines.duration = Some(now + MINUTE);
+        assert_eq!(deadlines.next(false), Some(now + MINUTE));
+        assert_eq!(Deadlines::default().next(false), None);
+    }
+}
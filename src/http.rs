@@ -0,0 +1,510 @@
+//! HTTP/1.1 request parsing and routing for the loopback REST API. Pure over
+//! `BufRead`/`Write` so it can be driven without sockets; `http_server` owns
+//! the listener.
+//!
+//! ```text
+//! GET  /state                      -> {"visible":false,"color":"#000000",...}
+//! GET  /monitors                   -> [{"number":1,"name":"\\\\.\\DISPLAY1",...}]
+//! POST /show, /hide, /toggle       -> {"ok":true}
+//! POST /color   {"hex":"#ffffff"}  -> {"ok":true}
+//! POST /pattern {"pattern":"grid"} -> {"ok":true}
+//! ```
+
+use crate::color;
+use crate::json::{self, Value};
+use crate::pattern::Pattern;
+use crate::protocol::{self, Command, Reply};
+use std::io::{self, BufRead, Read, Write};
+
+/// Longest accepted request line or header line.
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Header lookup; names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    /// Sent as `Allow` with 405 responses, and as the allowed methods of a
+    /// CORS preflight.
+    pub allow: Option<&'static str>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &Value) -> Self {
+        Response {
+            status,
+            body: value.to_string(),
+            allow: None,
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &Value::object([("error", Value::from(message))]))
+    }
+
+    /// Answers a browser's CORS preflight for a path that accepts `allow`.
+    pub fn preflight(allow: &'static str) -> Self {
+        Response {
+            status: 204,
+            body: String::new(),
+            allow: Some(allow),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        // Any origin may call: the bearer token is the protection, and
+        // browsers never send it on their own
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n",
+            self.status,
+            reason(self.status)
+        );
+        if self.status == 204 {
+            head += &format!(
+                "Access-Control-Allow-Methods: {}, OPTIONS\r\n\
+                 Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+                 Access-Control-Max-Age: 600\r\n",
+                self.allow.unwrap_or_default()
+            );
+        } else {
+            head += &format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                self.body.len()
+            );
+        }
+        if self.status == 401 {
+            head += "WWW-Authenticate: Bearer\r\n";
+        }
+        if let Some(allow) = self.allow {
+            head += &format!("Allow: {}, OPTIONS\r\n", allow);
+        }
+        head += "\r\n";
+        out.write_all(head.as_bytes())?;
+        out.write_all(self.body.as_bytes())?;
+        out.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Reads one request. Malformed input comes back as the error response to
+/// send; I/O errors (including timeouts) as `None`.
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, Option<Response>> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Some(Response::error(400, "malformed request line")));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Some(Response::error(400, "unsupported HTTP version")));
+    }
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(Some(Response::error(431, "too many headers")));
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Some(Response::error(400, "malformed header")));
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path,
+        headers,
+        body: Vec::new(),
+    };
+
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(Some(Response::error(
+            400,
+            "chunked bodies are not supported",
+        )));
+    }
+    let length = match request.header("Content-Length") {
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| Some(Response::error(400, "invalid Content-Length")))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Some(Response::error(413, "request body too large")));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).map_err(|_| None)?;
+
+    Ok(request)
+}
+
+/// One CRLF- (or LF-) terminated line, without the terminator.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Option<Response>> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .map_err(|_| None)?;
+    if read == 0 {
+        return Err(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(Some(Response::error(431, "line too long")));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| Some(Response::error(400, "request is not UTF-8")))
+}
+
+/// Checks the token and maps the request onto a control command. Preflights
+/// are answered without a token, since browsers send them without one.
+pub fn route(request: &Request, token: &str) -> Result<Command, Response> {
+    let endpoint = endpoint(&request.path);
+    if request.method == "OPTIONS" {
+        return Err(match endpoint {
+            Some((_, allow)) => Response::preflight(allow),
+            None => Response::error(404, "not found"),
+        });
+    }
+
+    let presented = request
+        .header("Authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    if !presented.is_some_and(|t| tokens_match(t, token)) {
+        return Err(Response::error(401, "missing or invalid token"));
+    }
+
+    let Some((command, allow)) = endpoint else {
+        return Err(Response::error(404, "not found"));
+    };
+    if request.method != allow {
+        let mut response = Response::error(405, "method not allowed");
+        response.allow = Some(allow);
+        return Err(response);
+    }
+    if let Some(command) = command {
+        return Ok(command);
+    }
+
+    let body = std::str::from_utf8(&request.body)
+        .ok()
+        .and_then(|s| json::parse(s).ok())
+        .ok_or_else(|| Response::error(400, "body must be a JSON object"))?;
+    let field = |name: &str| {
+        body.get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| Response::error(400, &format!("body needs a string `{}`", name)))
+    };
+
+    if request.path == "/color" {
+        let hex = field("hex")?;
+        color::parse_hex(hex)
+            .map(Command::SetColor)
+            .ok_or_else(|| Response::error(400, &format!("invalid color `{}`", hex)))
+    } else {
+        let name = field("pattern")?;
+        Pattern::parse(name)
+            .map(Command::SetPattern)
+            .ok_or_else(|| {
+                Response::error(
+                    400,
+                    &format!(
+                        "invalid pattern `{}`, expected one of {}",
+                        name,
+                        Pattern::names()
+                    ),
+                )
+            })
+    }
+}
+
+/// The command behind `path`, if it needs no body, and its method.
+fn endpoint(path: &str) -> Option<(Option<Command>, &'static str)> {
+    Some(match path {
+        "/state" => (Some(Command::GetState), "GET"),
+        "/monitors" => (Some(Command::ListMonitors), "GET"),
+        "/show" => (Some(Command::Show), "POST"),
+        "/hide" => (Some(Command::Hide), "POST"),
+        "/toggle" => (Some(Command::Toggle), "POST"),
+        "/color" | "/pattern" => (None, "POST"),
+        _ => return None,
+    })
+}
+
+/// Compares without stopping at the first difference, so response timing
+/// doesn't reveal how much of a guessed token was right.
+fn tokens_match(a: &str, b: &str) -> bool {
+    if a.len() != b.len() || b.is_empty() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+pub fn reply_response(reply: Reply) -> Response {
+    match reply {
+        Reply::Done => Response::json(200, &Value::object([("ok", Value::Bool(true))])),
+        Reply::State(state) => Response::json(200, &protocol::state_value(&state)),
+        Reply::Monitors(monitors) => Response::json(
+            200,
+            &Value::Array(monitors.iter().map(protocol::monitor_value).collect()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    const TOKEN: &str = "s3cret";
+
+    /// Hands out one byte per read, like a slow client.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(slot)) => {
+                    *slot = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn read(raw: &str) -> Result<Request, Option<Response>> {
+        read_request(&mut raw.as_bytes())
+    }
+
+    fn status(raw: &str) -> Option<u16> {
+        read(raw).err().flatten().map(|response| response.status)
+    }
+
+    fn request(method: &str, path: &str, auth: Option<&str>, body: &str) -> Request {
+        let mut headers = Vec::new();
+        if let Some(auth) = auth {
+            headers.push(("authorization".to_string(), auth.to_string()));
+        }
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn authorized(method: &str, path: &str, body: &str) -> Result<Command, Response> {
+        route(&request(method, path, Some("Bearer s3cret"), body), TOKEN)
+    }
+
+    fn route_status(result: Result<Command, Response>) -> u16 {
+        result.unwrap_err().status
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let raw = "POST /color?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 17\r\n\
+                   \r\n{\"hex\":\"#ffffff\"}";
+        let request = read(raw).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/color");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.body, b"{\"hex\":\"#ffffff\"}");
+    }
+
+    #[test]
+    fn reads_bare_newlines() {
+        let request = read("GET /state HTTP/1.0\nAuthorization: Bearer x\n\n").unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer x"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn reads_in_pieces() {
+        let raw = b"POST /show HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}";
+        let mut reader = BufReader::with_capacity(3, Trickle(raw));
+        let request = read_request(&mut reader).unwrap();
+        assert_eq!(request.path, "/show");
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn truncated_requests_are_dropped() {
+        assert_eq!(read(""), Err(None));
+        assert_eq!(read("GET /state HTTP/1.1\r\nHost: x\r\n"), Err(None));
+        assert_eq!(
+            read("POST /color HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}"),
+            Err(None)
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_requests() {
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status(&long_line), Some(431));
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(status(&long_header), Some(431));
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: 1\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert_eq!(status(&many_headers), Some(431));
+        let big_body = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert_eq!(status(&big_body), Some(413));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert_eq!(status("GET /state\r\n\r\n"), Some(400));
+        assert_eq!(status("GET /state HTTP/2\r\n\r\n"), Some(400));
+        assert_eq!(status("GET / HTTP/1.1\r\nno colon\r\n\r\n"), Some(400));
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            Some(400)
+        );
+    }
+
+    #[test]
+    fn requires_the_token() {
+        let no_token = request("GET", "/state", None, "");
+        assert_eq!(route_status(route(&no_token, TOKEN)), 401);
+        for auth in ["Bearer wrong!", "Bearer s3cre", "Basic s3cret", "s3cret"] {
+            let bad = request("GET", "/state", Some(auth), "");
+            assert_eq!(route_status(route(&bad, TOKEN)), 401, "{}", auth);
+        }
+        let unknown = request("GET", "/nope", Some("Bearer x"), "");
+        assert_eq!(route_status(route(&unknown, TOKEN)), 401);
+        // An empty configured token never matches
+        let empty = request("GET", "/state", Some("Bearer "), "");
+        assert_eq!(route_status(route(&empty, "")), 401);
+    }
+
+    #[test]
+    fn routes_commands() {
+        assert_eq!(authorized("GET", "/state", ""), Ok(Command::GetState));
+        assert_eq!(
+            authorized("GET", "/monitors", ""),
+            Ok(Command::ListMonitors)
+        );
+        assert_eq!(authorized("POST", "/toggle", ""), Ok(Command::Toggle));
+        assert_eq!(
+            authorized("POST", "/color", r##"{"hex":"#0000ff"}"##),
+            Ok(Command::SetColor(0xFF0000))
+        );
+        assert_eq!(
+            authorized("POST", "/pattern", r#"{"pattern":"grid"}"#),
+            Ok(Command::SetPattern(Pattern::Grid))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_routes_and_bodies() {
+        assert_eq!(route_status(authorized("GET", "/nope", "")), 404);
+        let wrong_method = authorized("GET", "/show", "").unwrap_err();
+        assert_eq!(wrong_method.status, 405);
+        assert_eq!(wrong_method.allow, Some("POST"));
+        assert_eq!(route_status(authorized("POST", "/color", "hex")), 400);
+        assert_eq!(route_status(authorized("POST", "/color", "{}")), 400);
+        assert_eq!(
+            route_status(authorized("POST", "/color", r#"{"hex":"red"}"#)),
+            400
+        );
+        assert_eq!(
+            route_status(authorized("POST", "/pattern", r#"{"pattern":1}"#)),
+            400
+        );
+    }
+
+    #[test]
+    fn answers_preflights_without_a_token() {
+        let preflight = route(&request("OPTIONS", "/color", None, ""), TOKEN);
+        assert_eq!(preflight, Err(Response::preflight("POST")));
+        let unknown = route(&request("OPTIONS", "/nope", None, ""), TOKEN);
+        assert_eq!(route_status(unknown), 404);
+
+        let mut out = Vec::new();
+        Response::preflight("POST").write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(out.contains("Access-Control-Allow-Origin: *\r\n"));
+        assert!(out.contains("Access-Control-Allow-Methods: POST, OPTIONS\r\n"));
+        assert!(out.contains("Access-Control-Allow-Headers: Authorization, Content-Type\r\n"));
+        assert!(!out.contains("Content-Length"));
+        assert!(out.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn writes_responses() {
+        let mut out = Vec::new();
+        Response::error(401, "missing or invalid token")
+            .write_to(&mut out)
+            .unwrap();
+        let body = r#"{"error":"missing or invalid token"}"#;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "HTTP/1.1 401 Unauthorized\r\nAccess-Control-Allow-Origin: *\r\n\
+                 Connection: close\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nWWW-Authenticate: Bearer\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Loopback listener for the HTTP API. Each connection gets a thread; routed
+//! commands are queued for the UI thread, which sends back the reply.
+
+use crate::http::{self, Response};
+use crate::protocol::{Command, Reply};
+use std::io::{self, BufReader};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+use windows_sys::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_APP};
+
+/// Posted to the UI thread when commands are waiting in `HttpServer::take`.
+pub const WM_HTTP: u32 = WM_APP + 3;
+
+/// Per-connection socket timeout, and how long to wait for the UI thread.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the accept loop checks whether it was stopped, which bounds how
+/// long dropping the server blocks.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Notify = Arc<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Vec<(Command, Sender<Reply>)>>,
+    stopped: AtomicBool,
+}
+
+pub struct HttpServer {
+    shared: Arc<Shared>,
+    accepting: Option<JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Listens on `127.0.0.1:port`. `WM_HTTP` is posted to the calling thread
+    /// whenever commands arrive.
+    pub fn start(port: u16, token: &str) -> io::Result<Self> {
+        let thread_id = unsafe { GetCurrentThreadId() };
+        Self::listen(port, token, move || unsafe {
+            PostThreadMessageW(thread_id, WM_HTTP, 0, 0);
+        })
+    }
+
+    /// Like `start`, calling `notify` instead of posting `WM_HTTP`.
+    fn listen(
+        port: u16,
+        token: &str,
+        notify: impl Fn() + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        // Polled, so `drop` never waits on a blocking accept
+        listener.set_nonblocking(true)?;
+        let shared = Arc::new(Shared::default());
+        let notify: Notify = Arc::new(notify);
+
+        let listening = Arc::clone(&shared);
+        let token = token.to_string();
+        let accepting = std::thread::spawn(move || {
+            while !listening.stopped.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(POLL_INTERVAL);
+                        continue;
+                    }
+                    Err(_) => continue,
+                };
+                let shared = Arc::clone(&listening);
+                let token = token.clone();
+                let notify = Arc::clone(&notify);
+                std::thread::spawn(move || serve(stream, &shared, &token, &notify));
+            }
+        });
+
+        Ok(HttpServer {
+            shared,
+            accepting: Some(accepting),
+        })
+    }
+
+    /// Commands received since the last call, with where to send each reply.
+    pub fn take(&self) -> Vec<(Command, Sender<Reply>)> {
+        std::mem::take(&mut *lock(&self.shared.queue))
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Relaxed);
+        // Wait until the accept loop has released the port, so a restart can
+        // bind it again; it notices the flag within one poll
+        if let Some(accepting) = self.accepting.take() {
+            let _ = accepting.join();
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn serve(stream: TcpStream, shared: &Shared, token: &str, notify: &Notify) {
+    // Accepted sockets may inherit non-blocking mode from the listener
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+
+    let mut reader = BufReader::new(&stream);
+    let response = match http::read_request(&mut reader) {
+        Ok(request) => match http::route(&request, token) {
+            Ok(command) => dispatch(command, shared, notify),
+            Err(response) => response,
+        },
+        Err(Some(response)) => response,
+        Err(None) => return,
+    };
+    let _ = response.write_to(&stream);
+}
+
+/// Hands `command` to the UI thread and waits for its reply.
+fn dispatch(command: Command, shared: &Shared, notify: &Notify) -> Response {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    lock(&shared.queue).push((command, reply_tx));
+    notify();
+
+    match reply_rx.recv_timeout(TIMEOUT) {
+        Ok(reply) => http::reply_response(reply),
+        Err(_) => Response::error(503, "blanqr is busy"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::time::Instant;
+
+    fn free_port() -> u16 {
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .port()
+    }
+
+    fn send(port: u16, raw: &str) -> String {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_over_loopback() {
+        let port = free_port();
+        let (notify_tx, notify_rx) = mpsc::channel();
+        let server = HttpServer::listen(port, "s3cret", move || {
+            let _ = notify_tx.send(());
+        })
+        .unwrap();
+
+        let client = std::thread::spawn(move || {
+            let denied = send(port, "GET /state HTTP/1.1\r\n\r\n");
+            let shown = send(
+                port,
+                "POST /show HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n",
+            );
+            (denied, shown)
+        });
+
+        // Play the UI thread: answer the one command that gets through
+        notify_rx.recv_timeout(TIMEOUT).unwrap();
+        let mut commands = server.take();
+        assert_eq!(commands.len(), 1);
+        let (command, reply) = commands.pop().unwrap();
+        assert_eq!(command, Command::Show);
+        reply.send(Reply::Done).unwrap();
+
+        let (denied, shown) = client.join().unwrap();
+        assert!(denied.starts_with("HTTP/1.1 401 "), "{}", denied);
+        assert!(shown.starts_with("HTTP/1.1 200 OK\r\n"), "{}", shown);
+        assert!(shown.ends_with("\r\n\r\n{\"ok\":true}"), "{}", shown);
+
+        let stopping = Instant::now();
+        drop(server);
+        assert!(stopping.elapsed() < Duration::from_secs(1));
+        // The port is free again for a restart
+        TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+//! The tray icon, drawn at runtime: a swatch of the current color with a
+//! badge for what blanqr is doing. Pure; `tray` turns the pixels into an
+//! `HICON`.
+
+use crate::color;
+
+/// Marks the bottom-right corner of the swatch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Badge {
+    None,
+    /// The overlay is up.
+    Visible,
+    /// A sequence is playing.
+    Playing,
+}
+
+const TRANSPARENT: u32 = 0x00000000;
+const OUTLINE: u32 = 0xFFFFFFFF;
+const VISIBLE_FILL: u32 = 0xFF2ECC40;
+const PLAYING_FILL: u32 = 0xFFFF851B;
+
+/// Pixels of a `size` x `size` icon, top row first, as `0xAARRGGBB`.
+/// `color` is a `COLORREF`.
+pub fn render(size: u32, color: u32, badge: Badge) -> Vec<u32> {
+    let s = size as f64;
+    // One pixel at 16x16, scaled up with the icon
+    let line = (s / 16.0).max(1.0);
+    let (r, g, b) = color::colorref_to_rgb(color);
+    let fill = 0xFF000000 | (r as u32) << 16 | (g as u32) << 8 | b as u32;
+    // A border that stands out from the swatch, so black shows on a dark
+    // taskbar and white on a light one
+    let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+    let border = if luma > 128.0 { 0xFF404040 } else { 0xFFC0C0C0 };
+
+    let center = s * 0.75;
+    let radius = s * 0.25 - line;
+
+    let mut pixels = Vec::with_capacity((size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let mut pixel = if px < line || py < line || px > s - line || py > s - line {
+                TRANSPARENT
+            } else if px < 2.0 * line
+                || py < 2.0 * line
+                || px > s - 2.0 * line
+                || py > s - 2.0 * line
+            {
+                border
+            } else {
+                fill
+            };
+
+            if badge != Badge::None {
+                let distance = ((px - center).powi(2) + (py - center).powi(2)).sqrt();
+                if distance <= radius {
+                    pixel = match badge {
+                        Badge::Playing if in_play_triangle(px - center, py - center, radius) => {
+                            OUTLINE
+                        }
+                        Badge::Playing => PLAYING_FILL,
+                        _ => VISIBLE_FILL,
+                    };
+                } else if distance <= radius + line {
+                    pixel = OUTLINE;
+                }
+            }
+            pixels.push(pixel);
+        }
+    }
+    pixels
+}
+
+/// Whether an offset from the badge center falls in a right-pointing
+/// triangle sized to the badge.
+fn in_play_triangle(dx: f64, dy: f64, radius: f64) -> bool {
+    let half = radius * 0.5;
+    let (left, right) = (-half * 0.8, half * 1.2);
+    if dx < left || dx > right {
+        return false;
+    }
+    // Height shrinks linearly to the tip
+    let reach = half * (right - dx) / (right - left);
+    dy.abs() <= reach
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORANGE: u32 = 0x000080FF;
+
+    fn pixel(pixels: &[u32], size: u32, x: u32, y: u32) -> u32 {
+        pixels[(y * size + x) as usize]
+    }
+
+    #[test]
+    fn fills_a_square_buffer() {
+        for size in [16, 20, 32, 48] {
+            assert_eq!(
+                render(size, ORANGE, Badge::None).len(),
+                (size * size) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn paints_the_swatch_color() {
+        let pixels = render(16, ORANGE, Badge::None);
+        assert_eq!(pixel(&pixels, 16, 4, 4), 0xFFFF8000);
+        assert_eq!(pixel(&pixels, 16, 12, 12), 0xFFFF8000);
+        // The border contrasts with the swatch
+        assert_eq!(pixel(&pixels, 16, 1, 8), 0xFF404040);
+        let dark = render(16, 0, Badge::None);
+        assert_eq!(pixel(&dark, 16, 4, 4), 0xFF000000);
+        assert_eq!(pixel(&dark, 16, 1, 8), 0xFFC0C0C0);
+    }
+
+    #[test]
+    fn leaves_the_corners_transparent() {
+        for badge in [Badge::None, Badge::Visible, Badge::Playing] {
+            for size in [16, 32] {
+                let pixels = render(size, ORANGE, badge);
+                let last = size - 1;
+                for (x, y) in [(0, 0), (last, 0), (0, last), (last, last)] {
+                    assert_eq!(
+                        pixel(&pixels, size, x, y),
+                        TRANSPARENT,
+                        "{:?} {}",
+                        badge,
+                        size
+                    );
+                }
+            }
+        }
+        // The edge is one pixel at 16x16 and scales with the icon
+        let pixels = render(32, ORANGE, Badge::None);
+        assert_eq!(pixel(&pixels, 32, 1, 16), TRANSPARENT);
+        assert_eq!(pixel(&pixels, 32, 2, 16), 0xFF404040);
+    }
+
+    #[test]
+    fn draws_each_badge() {
+        let none = render(16, ORANGE, Badge::None);
+        let visible = render(16, ORANGE, Badge::Visible);
+        let playing = render(16, ORANGE, Badge::Playing);
+
+        // Inside the badge, below its center
+        assert_eq!(pixel(&visible, 16, 12, 14), VISIBLE_FILL);
+        assert_eq!(pixel(&playing, 16, 12, 14), PLAYING_FILL);
+        // The play triangle at the center
+        assert_eq!(pixel(&visible, 16, 12, 12), VISIBLE_FILL);
+        assert_eq!(pixel(&playing, 16, 12, 12), OUTLINE);
+        // The ring around the badge
+        assert_eq!(pixel(&visible, 16, 12, 15), OUTLINE);
+        assert_eq!(pixel(&none, 16, 12, 15), TRANSPARENT);
+        // The rest of the swatch is untouched
+        assert_eq!(none[..16 * 6], visible[..16 * 6]);
+        assert_eq!(none[..16 * 6], playing[..16 * 6]);
+    }
+}
@@ -0,0 +1,93 @@
+//! How long the user has been away from the keyboard and mouse, for showing
+//! the overlay like a screensaver. `App` only sees the `IdleSource` trait, so
+//! another input source can stand in for the Windows one. There is no X11
+//! (XScreenSaver) source yet.
+
+use std::mem::size_of;
+use std::time::Duration;
+use windows_sys::Win32::System::SystemInformation::GetTickCount;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+pub trait IdleSource {
+    /// Time since the last keyboard or mouse input in the session.
+    fn idle_time(&self) -> Duration;
+}
+
+/// What a poll of the idle time asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleStep {
+    Wait,
+    /// Idle for `after`, and no overlay is up.
+    Show,
+    /// Input while the idle overlay is up.
+    Hide,
+}
+
+/// Decides one poll. `showing` is whether the idle overlay is up; `visible`
+/// whether any overlay is, since one shown some other way is left alone.
+pub fn idle_step(idle: Duration, after: Duration, showing: bool, visible: bool) -> IdleStep {
+    if showing {
+        if idle < after {
+            IdleStep::Hide
+        } else {
+            IdleStep::Wait
+        }
+    } else if !visible && idle >= after {
+        IdleStep::Show
+    } else {
+        IdleStep::Wait
+    }
+}
+
+/// System-wide last input time from `GetLastInputInfo`.
+pub struct LastInput;
+
+impl IdleSource for LastInput {
+    fn idle_time(&self) -> Duration {
+        let mut info = LASTINPUTINFO {
+            cbSize: size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return Duration::ZERO;
+        }
+        // Both tick counts wrap after 49.7 days
+        let now = unsafe { GetTickCount() };
+        Duration::from_millis(now.wrapping_sub(info.dwTime) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFTER: Duration = Duration::from_secs(300);
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn waits_below_the_threshold() {
+        assert_eq!(idle_step(secs(0), AFTER, false, false), IdleStep::Wait);
+        assert_eq!(idle_step(secs(299), AFTER, false, false), IdleStep::Wait);
+    }
+
+    #[test]
+    fn shows_once_the_threshold_is_crossed() {
+        assert_eq!(idle_step(secs(300), AFTER, false, false), IdleStep::Show);
+        assert_eq!(idle_step(secs(301), AFTER, false, false), IdleStep::Show);
+        // An overlay shown another way is left alone
+        assert_eq!(idle_step(secs(301), AFTER, false, true), IdleStep::Wait);
+    }
+
+    #[test]
+    fn input_hides_the_idle_overlay() {
+        assert_eq!(idle_step(secs(900), AFTER, true, true), IdleStep::Wait);
+        assert_eq!(idle_step(secs(0), AFTER, true, true), IdleStep::Hide);
+        // After hiding, the next show waits for a full idle period again
+        assert_eq!(idle_step(secs(1), AFTER, false, false), IdleStep::Wait);
+        assert_eq!(idle_step(secs(299), AFTER, false, false), IdleStep::Wait);
+        assert_eq!(idle_step(secs(300), AFTER, false, false), IdleStep::Show);
+    }
+}
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::mem::zeroed;
+use std::ptr::null_mut;
+use std::rc::Rc;
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::Graphics::Gdi::{GetStockObject, WHITE_BRUSH};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::SetFocus;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW,
+    GetWindowLongPtrW, GetWindowTextLengthW, GetWindowTextW, IsDialogMessageW, PostQuitMessage,
+    RegisterClassW, SetWindowLongPtrW, ShowWindow, TranslateMessage, CREATESTRUCTW, CW_USEDEFAULT,
+    GWLP_USERDATA, MSG, SW_SHOW, WM_CLOSE, WM_COMMAND, WM_CREATE, WM_DESTROY, WNDCLASSW, WS_BORDER,
+    WS_CAPTION, WS_CHILD, WS_EX_DLGMODALFRAME, WS_OVERLAPPED, WS_SYSMENU, WS_TABSTOP, WS_VISIBLE,
+};
+
+// Window styles for controls
+const BS_DEFPUSHBUTTON: u32 = 0x00000001;
+const ES_AUTOHSCROLL: u32 = 0x0080;
+
+const ID_OK: u16 = 1;
+const ID_CANCEL: u16 = 2;
+
+static CLASS_NAME: &[u16] = &[
+    'B' as u16, 'l' as u16, 'a' as u16, 'n' as u16, 'q' as u16, 'r' as u16, 'I' as u16, 'n' as u16,
+    'p' as u16, 'u' as u16, 't' as u16, 0,
+];
+
+struct DialogState {
+    initial: String,
+    text: Option<String>,
+    hwnd_edit: HWND,
+}
+
+/// Asks for a single line of text. Returns `None` if cancelled or empty.
+pub fn show_input_dialog(title: &str, initial: &str) -> Option<String> {
+    unsafe {
+        let hinstance = GetModuleHandleW(null_mut());
+        if hinstance.is_null() {
+            return None;
+        }
+
+        let wc = WNDCLASSW {
+            style: 0,
+            lpfnWndProc: Some(dialog_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: hinstance,
+            hIcon: null_mut(),
+            hCursor: null_mut(),
+            hbrBackground: GetStockObject(WHITE_BRUSH) as _,
+            lpszMenuName: null_mut(),
+            lpszClassName: CLASS_NAME.as_ptr(),
+        };
+
+        RegisterClassW(&wc);
+
+        let state = Rc::new(RefCell::new(DialogState {
+            initial: initial.to_string(),
+            text: None,
+            hwnd_edit: null_mut(),
+        }));
+
+        let title = wide_str(title);
+        let hwnd = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            CLASS_NAME.as_ptr(),
+            title.as_ptr(),
+            WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            280,
+            140,
+            null_mut(),
+            null_mut(),
+            hinstance,
+            Rc::into_raw(state.clone()) as *mut _,
+        );
+
+        if hwnd.is_null() {
+            return None;
+        }
+
+        ShowWindow(hwnd, SW_SHOW);
+        SetFocus(state.borrow().hwnd_edit);
+
+        // Message loop; IsDialogMessageW makes Enter, Escape and Tab work
+        let mut msg: MSG = zeroed();
+        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+            if IsDialogMessageW(hwnd, &msg) == 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+
+        let text = state.borrow_mut().text.take();
+        text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+    }
+}
+
+fn wide_str(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+unsafe fn window_text(hwnd: HWND) -> String {
+    let len = GetWindowTextLengthW(hwnd);
+    let mut buf = vec![0u16; len as usize + 1];
+    let copied = GetWindowTextW(hwnd, buf.as_mut_ptr(), buf.len() as i32);
+    String::from_utf16_lossy(&buf[..copied as usize])
+}
+
+unsafe extern "system" fn dialog_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_CREATE => {
+            let cs = &*(lparam as *const CREATESTRUCTW);
+            let state = cs.lpCreateParams as *mut RefCell<DialogState>;
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, state as isize);
+
+            let hinstance = GetModuleHandleW(null_mut());
+            let state_ref = &*state;
+
+            let edit_class = wide_str("EDIT");
+            let initial_text = wide_str(&state_ref.borrow().initial);
+            let hwnd_edit = CreateWindowExW(
+                0,
+                edit_class.as_ptr(),
+                initial_text.as_ptr(),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WS_BORDER | ES_AUTOHSCROLL,
+                20,
+                15,
+                220,
+                26,
+                hwnd,
+                null_mut(),
+                hinstance,
+                null_mut(),
+            );
+            state_ref.borrow_mut().hwnd_edit = hwnd_edit;
+
+            let button_class = wide_str("BUTTON");
+            let ok_text = wide_str("OK");
+            CreateWindowExW(
+                0,
+                button_class.as_ptr(),
+                ok_text.as_ptr(),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | BS_DEFPUSHBUTTON,
+                20,
+                60,
+                80,
+                30,
+                hwnd,
+                ID_OK as isize as _,
+                hinstance,
+                null_mut(),
+            );
+
+            let cancel_text = wide_str("Cancel");
+            CreateWindowExW(
+                0,
+                button_class.as_ptr(),
+                cancel_text.as_ptr(),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP,
+                160,
+                60,
+                80,
+                30,
+                hwnd,
+                ID_CANCEL as isize as _,
+                hinstance,
+                null_mut(),
+            );
+
+            0
+        }
+        WM_COMMAND => {
+            let id = (wparam & 0xFFFF) as u16;
+            match id {
+                ID_OK => {
+                    let state_ptr =
+                        GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut RefCell<DialogState>;
+                    if !state_ptr.is_null() {
+                        let mut state = (*state_ptr).borrow_mut();
+                        state.text = Some(window_text(state.hwnd_edit));
+                    }
+                    DestroyWindow(hwnd);
+                }
+                ID_CANCEL => {
+                    DestroyWindow(hwnd);
+                }
+                _ => {}
+            }
+            0
+        }
+        WM_CLOSE => {
+            DestroyWindow(hwnd);
+            0
+        }
+        WM_DESTROY => {
+            PostQuitMessage(0);
+            0
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
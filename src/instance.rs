@@ -0,0 +1,181 @@
+//! Single-instance enforcement. The first instance holds a named mutex and
+//! listens on a hidden message-only window; later launches hand their
+//! command-line actions to it over `WM_COPYDATA` and exit.
+//!
+//! Windows only; there is no Linux counterpart (lock file and socket).
+
+use crate::keymap::Action;
+use std::ptr::null_mut;
+use std::thread;
+use std::time::Duration;
+use windows_sys::Win32::Foundation::{
+    GetLastError, ERROR_ALREADY_EXISTS, HANDLE, HWND, LPARAM, LRESULT, WPARAM,
+};
+use windows_sys::Win32::System::DataExchange::COPYDATASTRUCT;
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::System::Threading::CreateMutexW;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    AllowSetForegroundWindow, CreateWindowExW, DefWindowProcW, FindWindowExW,
+    GetWindowThreadProcessId, RegisterClassW, SendMessageTimeoutW, HWND_MESSAGE, SMTO_ABORTIFHUNG,
+    WM_COPYDATA, WNDCLASSW,
+};
+
+/// `Local\` scopes the mutex to the login session, like the tray icon.
+const MUTEX_NAME: &str = "Local\\BlanqrSingleInstance";
+
+static CLASS_NAME: &[u16] = &[
+    'B' as u16, 'l' as u16, 'a' as u16, 'n' as u16, 'q' as u16, 'r' as u16, 'I' as u16, 'n' as u16,
+    's' as u16, 't' as u16, 'a' as u16, 'n' as u16, 'c' as u16, 'e' as u16, 0,
+];
+
+/// Tags our `WM_COPYDATA` payloads ("BLQR").
+const COPYDATA_ID: usize = 0x424C_5152;
+const SEND_TIMEOUT_MS: u32 = 5000;
+/// How long to wait for a just-started instance to create its window.
+const FIND_RETRIES: u32 = 20;
+const FIND_INTERVAL: Duration = Duration::from_millis(100);
+
+static mut COMMAND_CALLBACK: Option<Box<dyn Fn(Vec<Action>)>> = None;
+
+/// Held for the lifetime of the process; the mutex is released on exit.
+pub struct InstanceLock {
+    _mutex: HANDLE,
+}
+
+/// Returns `None` if another instance already holds the lock.
+pub fn acquire() -> Option<InstanceLock> {
+    let name: Vec<u16> = MUTEX_NAME
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    unsafe {
+        let mutex = CreateMutexW(null_mut(), 0, name.as_ptr());
+        if !mutex.is_null() && GetLastError() == ERROR_ALREADY_EXISTS {
+            return None;
+        }
+        // If creating the mutex failed outright, run anyway rather than
+        // refusing to start.
+        Some(InstanceLock { _mutex: mutex })
+    }
+}
+
+/// Sends `actions` to the running instance. An empty list just asks it to
+/// announce itself. Returns false if it could not be reached.
+pub fn forward(actions: &[Action]) -> bool {
+    let text = encode(actions);
+
+    unsafe {
+        let mut hwnd = null_mut();
+        for _ in 0..FIND_RETRIES {
+            hwnd = FindWindowExW(HWND_MESSAGE, null_mut(), CLASS_NAME.as_ptr(), null_mut());
+            if !hwnd.is_null() {
+                break;
+            }
+            thread::sleep(FIND_INTERVAL);
+        }
+        if hwnd.is_null() {
+            return false;
+        }
+
+        // Let the overlay take the foreground so it receives Escape
+        let mut pid = 0;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        AllowSetForegroundWindow(pid);
+
+        let data = COPYDATASTRUCT {
+            dwData: COPYDATA_ID,
+            cbData: text.len() as u32,
+            lpData: text.as_ptr() as *mut _,
+        };
+        let mut result = 0;
+        SendMessageTimeoutW(
+            hwnd,
+            WM_COPYDATA,
+            0,
+            &data as *const COPYDATASTRUCT as LPARAM,
+            SMTO_ABORTIFHUNG,
+            SEND_TIMEOUT_MS,
+            &mut result,
+        ) != 0
+            && result != 0
+    }
+}
+
+/// Creates the window later instances forward to. `callback` runs on the
+/// calling thread for each received command.
+pub fn listen<F: Fn(Vec<Action>) + 'static>(callback: F) {
+    unsafe {
+        COMMAND_CALLBACK = Some(Box::new(callback));
+
+        let hinstance = GetModuleHandleW(null_mut());
+        let wc = WNDCLASSW {
+            style: 0,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: hinstance,
+            hIcon: null_mut(),
+            hCursor: null_mut(),
+            hbrBackground: null_mut(),
+            lpszMenuName: null_mut(),
+            lpszClassName: CLASS_NAME.as_ptr(),
+        };
+        RegisterClassW(&wc);
+
+        CreateWindowExW(
+            0,
+            CLASS_NAME.as_ptr(),
+            CLASS_NAME.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            null_mut(),
+            hinstance,
+            null_mut(),
+        );
+    }
+}
+
+/// One action per line, in the same syntax as `[keymap]` bindings.
+fn encode(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .map(|a| a.display())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn decode(text: &str) -> Vec<Action> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(Action::parse)
+        .collect()
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_COPYDATA {
+        let data = &*(lparam as *const COPYDATASTRUCT);
+        if data.dwData != COPYDATA_ID {
+            return 0;
+        }
+        let bytes = if data.cbData == 0 {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(data.lpData as *const u8, data.cbData as usize)
+        };
+        let actions = decode(&String::from_utf8_lossy(bytes));
+        if let Some(ref cb) = COMMAND_CALLBACK {
+            cb(actions);
+        }
+        return 1;
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
@@ -0,0 +1,445 @@
+//! Leader-key bindings: sequences of chords such as `Ctrl+Shift+B, W`.
+//!
+//! The first chord of every sequence is registered as a global hotkey. Pressing
+//! it arms a short timeout window during which the next chord selects the
+//! action. Everything in here is pure; `App` owns the Win32 side.
+
+use crate::color;
+use crate::config::HotkeyConfig;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Toggle,
+    Show,
+    Hide,
+    Color(u32),
+    /// Zero-based index into the preset list (written 1-based in config).
+    Preset(usize),
+    Profile(String),
+    /// Turns the click-through dimmer on or off.
+    Dimmer,
+    /// Makes the dimmer darker (positive) or lighter by this many steps.
+    DimmerStep(i8),
+    Quit,
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (name, arg) = match s.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (s, ""),
+        };
+        match (name.to_lowercase().as_str(), arg) {
+            ("toggle", "") => Some(Action::Toggle),
+            ("show", "") => Some(Action::Show),
+            ("hide", "") => Some(Action::Hide),
+            ("quit", "") => Some(Action::Quit),
+            ("color", hex) => color::parse_hex(hex).map(Action::Color),
+            ("preset", n) => match n.parse::<usize>() {
+                Ok(n) if n >= 1 => Some(Action::Preset(n - 1)),
+                _ => None,
+            },
+            ("profile", name) if !name.is_empty() => Some(Action::Profile(name.to_string())),
+            ("dimmer", "") => Some(Action::Dimmer),
+            ("dimmer", "+") => Some(Action::DimmerStep(1)),
+            ("dimmer", "-") => Some(Action::DimmerStep(-1)),
+            _ => None,
+        }
+    }
+
+    pub fn display(&self) -> String {
+        match self {
+            Action::Toggle => "toggle".to_string(),
+            Action::Show => "show".to_string(),
+            Action::Hide => "hide".to_string(),
+            Action::Color(c) => format!("color {}", color::to_hex(*c)),
+            Action::Preset(i) => format!("preset {}", i + 1),
+            Action::Profile(name) => format!("profile {}", name),
+            Action::Dimmer => "dimmer".to_string(),
+            Action::DimmerStep(steps) if *steps < 0 => "dimmer -".to_string(),
+            Action::DimmerStep(_) => "dimmer +".to_string(),
+            Action::Quit => "quit".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySequence(pub Vec<HotkeyConfig>);
+
+impl KeySequence {
+    /// Parses comma-separated chords, e.g. `Ctrl+Shift+B, W`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let chords = s
+            .split(',')
+            .map(HotkeyConfig::parse)
+            .collect::<Option<Vec<_>>>()?;
+        if chords.is_empty() {
+            None
+        } else {
+            Some(KeySequence(chords))
+        }
+    }
+
+    pub fn display(&self) -> String {
+        self.0
+            .iter()
+            .map(|c| c.display())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub sequence: KeySequence,
+    pub action: Action,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// The same sequence is bound more than once; the last binding wins.
+    Duplicate(KeySequence),
+    /// A bound sequence is also the prefix of a longer one. The shorter
+    /// binding fires only once the chord timeout expires.
+    Prefix {
+        prefix: KeySequence,
+        sequence: KeySequence,
+    },
+}
+
+impl Conflict {
+    /// Duplicates silently shadow a binding; prefixes work, just delayed.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Conflict::Duplicate(_))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Conflict::Duplicate(seq) => format!("{} is bound more than once", seq.display()),
+            Conflict::Prefix { prefix, sequence } => format!(
+                "{} waits for the chord timeout because {} starts with it",
+                prefix.display(),
+                sequence.display()
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    pub fn new(bindings: Vec<Binding>) -> Self {
+        Keymap { bindings }
+    }
+
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for (i, a) in self.bindings.iter().enumerate() {
+            for b in &self.bindings[i + 1..] {
+                let (sa, sb) = (&a.sequence.0, &b.sequence.0);
+                if sa == sb {
+                    conflicts.push(Conflict::Duplicate(a.sequence.clone()));
+                } else if sb.starts_with(sa) {
+                    conflicts.push(Conflict::Prefix {
+                        prefix: a.sequence.clone(),
+                        sequence: b.sequence.clone(),
+                    });
+                } else if sa.starts_with(sb) {
+                    conflicts.push(Conflict::Prefix {
+                        prefix: b.sequence.clone(),
+                        sequence: a.sequence.clone(),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Distinct first chords, i.e. the chords to register as global hotkeys.
+    pub fn leaders(&self) -> Vec<HotkeyConfig> {
+        let mut leaders: Vec<HotkeyConfig> = Vec::new();
+        for binding in &self.bindings {
+            let first = &binding.sequence.0[0];
+            if !leaders.contains(first) {
+                leaders.push(first.clone());
+            }
+        }
+        leaders
+    }
+
+    /// The action bound to exactly `keys` (last binding wins).
+    fn exact(&self, keys: &[HotkeyConfig]) -> Option<&Action> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|b| b.sequence.0 == keys)
+            .map(|b| &b.action)
+    }
+
+    /// Chords that may follow `keys` in some longer binding.
+    fn continuations(&self, keys: &[HotkeyConfig]) -> Vec<HotkeyConfig> {
+        let mut next: Vec<HotkeyConfig> = Vec::new();
+        for binding in &self.bindings {
+            let seq = &binding.sequence.0;
+            if seq.len() > keys.len() && seq.starts_with(keys) && !next.contains(&seq[keys.len()]) {
+                next.push(seq[keys.len()].clone());
+            }
+        }
+        next
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChordResult {
+    /// A prefix was entered; listen for these chords until the deadline.
+    Pending(Vec<HotkeyConfig>),
+    Matched(Action),
+    /// The chord did not continue any binding; the sequence was dropped.
+    Cancelled,
+}
+
+pub struct ChordState {
+    keys: Vec<HotkeyConfig>,
+    deadline: Option<Instant>,
+    timeout: Duration,
+}
+
+impl ChordState {
+    pub fn new(timeout: Duration) -> Self {
+        ChordState {
+            keys: Vec::new(),
+            deadline: None,
+            timeout,
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn reset(&mut self) {
+        self.keys.clear();
+        self.deadline = None;
+    }
+
+    pub fn feed(&mut self, keymap: &Keymap, chord: HotkeyConfig, now: Instant) -> ChordResult {
+        // Callers are expected to call `expire` at the deadline; a chord that
+        // arrives late simply starts a new sequence.
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            self.reset();
+        }
+
+        self.keys.push(chord);
+        let next = keymap.continuations(&self.keys);
+        if next.is_empty() {
+            let action = keymap.exact(&self.keys).cloned();
+            self.reset();
+            return match action {
+                Some(action) => ChordResult::Matched(action),
+                None => ChordResult::Cancelled,
+            };
+        }
+
+        self.deadline = Some(now + self.timeout);
+        ChordResult::Pending(next)
+    }
+
+    /// Ends a pending sequence whose deadline has passed. Returns the action
+    /// bound to the entered prefix, if any.
+    pub fn expire(&mut self, keymap: &Keymap, now: Instant) -> Option<Action> {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                let action = keymap.exact(&self.keys).cloned();
+                self.reset();
+                action
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> HotkeyConfig {
+        HotkeyConfig::parse(s).unwrap()
+    }
+
+    fn binding(seq: &str, action: &str) -> Binding {
+        Binding {
+            sequence: KeySequence::parse(seq).unwrap(),
+            action: Action::parse(action).unwrap(),
+        }
+    }
+
+    fn keymap() -> Keymap {
+        Keymap::new(vec![
+            binding("Ctrl+Shift+B", "toggle"),
+            binding("Ctrl+Shift+B, W", "color #FFFFFF"),
+            binding("Ctrl+Shift+B, K, 1", "preset 1"),
+            binding("Ctrl+Alt+H", "hide"),
+        ])
+    }
+
+    #[test]
+    fn parses_sequences() {
+        let seq = KeySequence::parse("Ctrl+Shift+B, W").unwrap();
+        assert_eq!(seq.0, vec![chord("Ctrl+Shift+B"), chord("W")]);
+        assert_eq!(seq.display(), "Ctrl+Shift+B, W");
+        assert_eq!(
+            KeySequence::parse(" ctrl + alt + F5 ").unwrap().display(),
+            "Ctrl+Alt+F5"
+        );
+        assert_eq!(KeySequence::parse("Ctrl+Shift+B, "), None);
+        assert_eq!(KeySequence::parse("Ctrl+Shfit+B"), None);
+        assert_eq!(KeySequence::parse(""), None);
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!(Action::parse("preset 3"), Some(Action::Preset(2)));
+        assert_eq!(Action::parse("preset 0"), None);
+        assert_eq!(
+            Action::parse("color #FF0000"),
+            Some(Action::Color(0x0000FF))
+        );
+        assert_eq!(Action::parse("dimmer -"), Some(Action::DimmerStep(-1)));
+        assert_eq!(Action::parse("profile"), None);
+        assert_eq!(Action::parse("toggle now"), None);
+        for s in [
+            "toggle",
+            "color #12AB34",
+            "preset 2",
+            "profile night",
+            "dimmer +",
+            "quit",
+        ] {
+            assert_eq!(Action::parse(s).unwrap().display(), s);
+        }
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let keymap = Keymap::new(vec![
+            binding("Ctrl+Shift+B, W", "show"),
+            binding("Ctrl+Shift+B, W", "hide"),
+            binding("Ctrl+Shift+B, K", "toggle"),
+            binding("Ctrl+Shift+B, K, 1", "preset 1"),
+            binding("Ctrl+Alt+H", "hide"),
+        ]);
+        let conflicts = keymap.conflicts();
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::Duplicate(KeySequence::parse("Ctrl+Shift+B, W").unwrap()),
+                Conflict::Prefix {
+                    prefix: KeySequence::parse("Ctrl+Shift+B, K").unwrap(),
+                    sequence: KeySequence::parse("Ctrl+Shift+B, K, 1").unwrap(),
+                },
+            ]
+        );
+        assert!(conflicts[0].is_error());
+        assert!(!conflicts[1].is_error());
+        assert!(Keymap::new(vec![binding("Ctrl+Alt+H", "hide")])
+            .conflicts()
+            .is_empty());
+    }
+
+    #[test]
+    fn lists_distinct_leaders() {
+        assert_eq!(
+            keymap().leaders(),
+            vec![chord("Ctrl+Shift+B"), chord("Ctrl+Alt+H")]
+        );
+    }
+
+    #[test]
+    fn completes_chords() {
+        let keymap = keymap();
+        let mut state = ChordState::new(Duration::from_secs(1));
+        let t = Instant::now();
+
+        assert_eq!(
+            state.feed(&keymap, chord("Ctrl+Shift+B"), t),
+            ChordResult::Pending(vec![chord("W"), chord("K")])
+        );
+        assert_eq!(state.deadline(), Some(t + Duration::from_secs(1)));
+        assert_eq!(
+            state.feed(&keymap, chord("K"), t + Duration::from_millis(500)),
+            ChordResult::Pending(vec![chord("1")])
+        );
+        assert_eq!(
+            state.feed(&keymap, chord("1"), t + Duration::from_millis(900)),
+            ChordResult::Matched(Action::Preset(0))
+        );
+        assert_eq!(state.deadline(), None);
+
+        // Single-chord bindings match straight away
+        assert_eq!(
+            state.feed(&keymap, chord("Ctrl+Alt+H"), t),
+            ChordResult::Matched(Action::Hide)
+        );
+    }
+
+    #[test]
+    fn timeout_fires_the_prefix_binding() {
+        let keymap = keymap();
+        let mut state = ChordState::new(Duration::from_secs(1));
+        let t = Instant::now();
+
+        state.feed(&keymap, chord("Ctrl+Shift+B"), t);
+        assert_eq!(state.expire(&keymap, t + Duration::from_millis(999)), None);
+        assert_eq!(
+            state.expire(&keymap, t + Duration::from_secs(1)),
+            Some(Action::Toggle)
+        );
+        assert_eq!(state.deadline(), None);
+
+        // A prefix with no binding of its own expires to nothing
+        state.feed(&keymap, chord("Ctrl+Shift+B"), t);
+        state.feed(&keymap, chord("K"), t);
+        assert_eq!(state.expire(&keymap, t + Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn late_chord_starts_a_new_sequence() {
+        let keymap = keymap();
+        let mut state = ChordState::new(Duration::from_secs(1));
+        let t = Instant::now();
+
+        state.feed(&keymap, chord("Ctrl+Shift+B"), t);
+        // W after the deadline isn't a follower of anything on its own
+        assert_eq!(
+            state.feed(&keymap, chord("W"), t + Duration::from_secs(2)),
+            ChordResult::Cancelled
+        );
+    }
+
+    #[test]
+    fn unknown_follower_resets() {
+        let keymap = keymap();
+        let mut state = ChordState::new(Duration::from_secs(1));
+        let t = Instant::now();
+
+        state.feed(&keymap, chord("Ctrl+Shift+B"), t);
+        assert_eq!(state.feed(&keymap, chord("Q"), t), ChordResult::Cancelled);
+        assert_eq!(state.deadline(), None);
+        // The next chord is a fresh start, not a continuation of B, Q
+        assert_eq!(
+            state.feed(&keymap, chord("Ctrl+Shift+B"), t),
+            ChordResult::Pending(vec![chord("W"), chord("K")])
+        );
+        assert_eq!(
+            state.feed(&keymap, chord("W"), t),
+            ChordResult::Matched(Action::Color(0xFFFFFF))
+        );
+    }
+}
@@ -1,22 +1,246 @@
 #![windows_subsystem = "windows"]
 
 mod app;
+mod ase;
+mod cgats;
+mod cli;
+mod clipboard;
+mod color;
 mod color_picker;
 mod color_window;
 mod config;
+mod control;
+mod fade;
+mod file_dialog;
+mod generator;
+mod gpl;
+mod guard;
 mod hotkey_dialog;
+mod http;
+mod http_server;
+mod icon;
+mod idle;
+mod input_dialog;
+mod instance;
+mod json;
+mod keymap;
 mod monitor;
+mod palette;
+mod patch;
+mod patch_set;
+mod pattern;
+mod player;
+mod power;
+mod protocol;
+mod resolve;
+mod schedule;
 mod startup;
+mod toml;
 mod tray;
+mod watcher;
 
 use app::App;
 use config::Config;
+use idle::LastInput;
+use monitor::enumerate_monitors;
+use patch_set::PatchSet;
+use std::path::Path;
+use std::ptr::null_mut;
+use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONWARNING, MB_OK};
 
 fn main() {
-    // Enable startup on first run
-    startup::ensure_startup_enabled();
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            report(&format!("blanqr: {}\n\n{}", err, cli::USAGE));
+            std::process::exit(2);
+        }
+    };
 
-    let config = Config::load();
-    let app = App::new(config);
-    app.run();
+    if options.help {
+        report(cli::USAGE);
+        return;
+    }
+    if let Some(path) = &options.config {
+        Config::set_path_override(path.clone());
+    }
+    if options.check_config {
+        std::process::exit(check_config());
+    }
+
+    let _lock = match instance::acquire() {
+        Some(lock) => lock,
+        None => std::process::exit(forward_to_running(&options)),
+    };
+    if options.hide || options.quit {
+        // Nothing is running, so there is nothing to hide or quit
+        return;
+    }
+
+    // Enable startup on first run, but not from one-shot scripted runs
+    if !options.no_tray {
+        startup::ensure_startup_enabled();
+    }
+
+    let (config, fallback) = match Config::load() {
+        Ok(config) => (config, false),
+        Err(err) => {
+            show_warning(&format!(
+                "{}\n\nUsing default settings. Changes are not saved until the \
+                 file is fixed.",
+                err
+            ));
+            (Config::default(), true)
+        }
+    };
+
+    if let Some(name) = &options.profile {
+        if config.profile(name).is_none() {
+            report(&format!("blanqr: profile `{}` does not exist", name));
+            std::process::exit(2);
+        }
+    }
+
+    if let Some(name) = &options.sequence {
+        if config.sequence(name).is_none() {
+            report(&format!("blanqr: sequence `{}` does not exist", name));
+            std::process::exit(2);
+        }
+    }
+
+    if !options.monitors.is_empty() {
+        let connected: Vec<u32> = enumerate_monitors().iter().map(|m| m.number).collect();
+        if let Err(err) = cli::check_monitors(&options, &connected) {
+            report(&format!("blanqr: {}", err));
+            std::process::exit(2);
+        }
+    }
+
+    let patches = options.patches.as_deref().map(|path| {
+        PatchSet::load(path).unwrap_or_else(|err| {
+            report(&format!("blanqr: {}", err));
+            std::process::exit(1);
+        })
+    });
+
+    let app = App::new(config, fallback, Box::new(LastInput));
+    app.run(options, patches);
+}
+
+/// Hands the command line to the instance that is already running. Returns
+/// the process exit code.
+fn forward_to_running(options: &cli::Options) -> i32 {
+    let actions = match cli::forwarded_actions(options) {
+        Ok(actions) => actions,
+        Err(err) => {
+            report(&format!("blanqr: {}", err));
+            return 2;
+        }
+    };
+    if instance::forward(&actions) {
+        0
+    } else {
+        report("blanqr: another instance is running but did not respond");
+        1
+    }
+}
+
+/// Validates the config file and reports on the console of the parent
+/// process. Returns the process exit code.
+fn check_config() -> i32 {
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+
+    // Read only: a check must not migrate or rewrite the config
+    let path = Config::config_path();
+    match Config::read() {
+        Ok(None) => check_legacy_config(),
+        Ok(Some(config)) => {
+            let code = check_keymap(&path, &config);
+            if code == 0 {
+                println!("{}: OK", path.display());
+            }
+            code
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Checks the `config.ini` of older versions when there is no `config.toml`
+/// yet. Lines that would be dropped when it is converted count as errors.
+fn check_legacy_config() -> i32 {
+    let path = Config::legacy_path();
+    match Config::read_legacy() {
+        Ok(None) => {
+            println!(
+                "{}: not found, defaults apply",
+                Config::config_path().display()
+            );
+            0
+        }
+        Ok(Some((config, skipped))) => {
+            for err in &skipped {
+                eprintln!("{}", err);
+            }
+            let mut code = check_keymap(&path, &config);
+            if !skipped.is_empty() {
+                code = 1;
+            }
+            if code == 0 {
+                println!(
+                    "{}: OK, converted to {} on next start",
+                    path.display(),
+                    Config::config_path().display()
+                );
+            }
+            code
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+/// Reports key binding conflicts in `config`. Returns 1 if any is an error.
+fn check_keymap(path: &Path, config: &Config) -> i32 {
+    let mut code = 0;
+    for conflict in config.keymap().conflicts() {
+        let level = if conflict.is_error() {
+            code = 1;
+            "error"
+        } else {
+            "note"
+        };
+        eprintln!("{}: {}: {}", path.display(), level, conflict.describe());
+    }
+    code
+}
+
+/// Prints to the console blanqr was started from, or shows a message box
+/// when there is none (e.g. started from a shortcut).
+fn report(text: &str) {
+    if unsafe { AttachConsole(ATTACH_PARENT_PROCESS) } != 0 {
+        eprintln!("{}", text.trim_end());
+    } else {
+        show_warning(text);
+    }
+}
+
+fn show_warning(text: &str) {
+    let title: Vec<u16> = "Blanqr".encode_utf16().chain(std::iter::once(0)).collect();
+    let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        MessageBoxW(
+            null_mut(),
+            text.as_ptr(),
+            title.as_ptr(),
+            MB_OK | MB_ICONWARNING,
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::protocol;
 use std::mem::zeroed;
 use std::ptr::null_mut;
 use windows_sys::Win32::Foundation::{BOOL, LPARAM, RECT, TRUE};
@@ -12,6 +13,37 @@ pub struct MonitorInfo {
     pub rect: Rect,
     pub is_primary: bool,
     pub name: String,
+    /// 1-based number as shown in Windows display settings (`\\.\DISPLAY2` is 2).
+    pub number: u32,
+}
+
+/// Which monitors the overlay covers, by monitor number. An empty `include`
+/// means every monitor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonitorSelection {
+    pub include: Vec<u32>,
+    pub exclude: Vec<u32>,
+}
+
+impl MonitorSelection {
+    pub fn selects(&self, monitor: &MonitorInfo) -> bool {
+        (self.include.is_empty() || self.include.contains(&monitor.number))
+            && !self.exclude.contains(&monitor.number)
+    }
+}
+
+impl From<&MonitorInfo> for protocol::Monitor {
+    fn from(m: &MonitorInfo) -> Self {
+        protocol::Monitor {
+            number: m.number,
+            name: m.name.clone(),
+            primary: m.is_primary,
+            left: m.rect.left,
+            top: m.rect.top,
+            width: m.rect.width(),
+            height: m.rect.height(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -81,11 +113,17 @@ unsafe extern "system" fn enum_monitor_callback(
 
         let is_primary = (monitor_info.monitorInfo.dwFlags & 1) != 0;
 
+        let number = name
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .parse()
+            .unwrap_or(monitors.len() as u32 + 1);
+
         monitors.push(MonitorInfo {
             hmonitor,
             rect: monitor_info.monitorInfo.rcMonitor.into(),
             is_primary,
             name,
+            number,
         });
     }
 
@@ -0,0 +1,338 @@
+//! Preset palettes shared with other tools: GIMP `.gpl`, Adobe `.ase`, and
+//! JSON of the form
+//! `{ "name": "...", "colors": [{ "name": "Black", "color": "#000000" }] }`.
+
+use crate::ase;
+use crate::color;
+use crate::config::Preset;
+use crate::gpl;
+use crate::json::{self, Value};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Gpl,
+    Ase,
+    Json,
+}
+
+impl Format {
+    /// Picks the format from the file extension.
+    fn from_path(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gpl" => Some(Format::Gpl),
+            "ase" => Some(Format::Ase),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the presets in `path`.
+pub fn load(path: &Path) -> Result<Vec<Preset>, String> {
+    let fail = |err: String| format!("{}: {}", path.display(), err);
+    let format = Format::from_path(path)
+        .ok_or_else(|| fail("expected a .gpl, .ase or .json file".to_string()))?;
+    let data = fs::read(path).map_err(|err| fail(err.to_string()))?;
+    match format {
+        Format::Gpl => utf8(data).and_then(|text| gpl::parse(&text)),
+        Format::Ase => ase::parse(&data),
+        Format::Json => utf8(data).and_then(|text| parse_json(&text)),
+    }
+    .map_err(fail)
+}
+
+fn utf8(data: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(data).map_err(|_| "not UTF-8 text".to_string())
+}
+
+/// Writes `presets` to `path`, naming the palette after the file.
+pub fn save(path: &Path, presets: &[Preset]) -> Result<(), String> {
+    let fail = |err: String| format!("{}: {}", path.display(), err);
+    let format = Format::from_path(path)
+        .ok_or_else(|| fail("expected a .gpl, .ase or .json file".to_string()))?;
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let data = match format {
+        Format::Gpl => gpl::write(&name, presets).into_bytes(),
+        Format::Ase => ase::write(presets),
+        Format::Json => write_json(&name, presets).into_bytes(),
+    };
+    fs::write(path, data).map_err(|err| fail(err.to_string()))
+}
+
+/// What importing a palette did to the presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Merged {
+    pub added: usize,
+    /// New colors left out because the presets were full.
+    pub skipped: usize,
+}
+
+impl Merged {
+    /// The notice shown after an import into presets capped at `limit`.
+    pub fn message(&self, limit: usize) -> String {
+        let mut message = match (self.added, self.skipped) {
+            (0, 0) => "Every color was already a preset.".to_string(),
+            (0, _) => "Added no presets.".to_string(),
+            (1, _) => "Added 1 preset.".to_string(),
+            (n, _) => format!("Added {} presets.", n),
+        };
+        if self.skipped > 0 {
+            message += &format!(
+                " Skipped {} that would go over the limit of {} presets.",
+                self.skipped, limit
+            );
+        }
+        message
+    }
+}
+
+/// Appends the `imported` presets that aren't in `presets` yet, stopping at
+/// `limit` presets in all.
+pub fn merge(presets: &mut Vec<Preset>, imported: Vec<Preset>, limit: usize) -> Merged {
+    let before = presets.len();
+    let mut over = Vec::new();
+    for preset in imported {
+        if presets.contains(&preset) || over.contains(&preset) {
+            continue;
+        }
+        if presets.len() < limit {
+            presets.push(preset);
+        } else {
+            over.push(preset);
+        }
+    }
+    Merged {
+        added: presets.len() - before,
+        skipped: over.len(),
+    }
+}
+
+/// Accepts the palette object or just its `colors` array.
+fn parse_json(text: &str) -> Result<Vec<Preset>, String> {
+    let value = json::parse(text).map_err(|err| err.to_string())?;
+    let colors = match &value {
+        Value::Array(items) => items.as_slice(),
+        _ => value
+            .get("colors")
+            .and_then(Value::as_array)
+            .ok_or_else(|| "expected an object with a `colors` array".to_string())?,
+    };
+
+    colors
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let hex = entry
+                .get("color")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("color {} is missing `color`", i + 1))?;
+            let color = color::parse_hex(hex).ok_or_else(|| {
+                format!(
+                    "color {}: invalid color `{}`, expected \"#RRGGBB\"",
+                    i + 1,
+                    hex
+                )
+            })?;
+            let name = match entry.get("name") {
+                Some(name) => name
+                    .as_str()
+                    .ok_or_else(|| format!("color {}: `name` must be a string", i + 1))?
+                    .to_string(),
+                None => color::to_hex(color),
+            };
+            Ok(Preset { name, color })
+        })
+        .collect()
+}
+
+/// One color per line, so palettes diff well.
+fn write_json(name: &str, presets: &[Preset]) -> String {
+    let mut out = format!("{{\n  \"name\": {},\n  \"colors\": [\n", Value::from(name));
+    for (i, preset) in presets.iter().enumerate() {
+        let entry = Value::object([
+            ("name", preset.name.as_str().into()),
+            ("color", color::to_hex(preset.color).into()),
+        ]);
+        let comma = if i + 1 < presets.len() { "," } else { "" };
+        out += &format!("    {}{}\n", entry, comma);
+    }
+    out += "  ]\n}\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    fn preset(name: &str, r: u8, g: u8, b: u8) -> Preset {
+        Preset {
+            name: name.to_string(),
+            color: color::rgb_to_colorref(r, g, b),
+        }
+    }
+
+    fn presets() -> Vec<Preset> {
+        vec![
+            preset("Black", 0, 0, 0),
+            preset("Say \"cheese\"", 255, 228, 206),
+            preset("Sky", 51, 153, 255),
+        ]
+    }
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        assert_eq!(Format::from_path(Path::new("a.GPL")), Some(Format::Gpl));
+        assert_eq!(Format::from_path(Path::new("a.ase")), Some(Format::Ase));
+        assert_eq!(Format::from_path(Path::new("a.Json")), Some(Format::Json));
+        assert_eq!(Format::from_path(Path::new("a.aco")), None);
+        assert_eq!(Format::from_path(Path::new("palette")), None);
+    }
+
+    #[test]
+    fn loads_each_format() {
+        let gpl = load(&testdata("palette.gpl")).unwrap();
+        assert_eq!(gpl.len(), 4);
+        let ase = load(&testdata("swatches.ase")).unwrap();
+        assert_eq!(ase.len(), 5);
+        assert_eq!(
+            load(&testdata("palette.json")),
+            Ok(vec![
+                preset("Black", 0, 0, 0),
+                preset("Sky", 51, 153, 255),
+                preset("#808080", 128, 128, 128),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_the_file() {
+        let err = load(&testdata("patches.csv")).unwrap_err();
+        assert!(err.ends_with("patches.csv: expected a .gpl, .ase or .json file"));
+        let err = load(&testdata("missing.gpl")).unwrap_err();
+        assert!(err.contains("missing.gpl: "), "{}", err);
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let dir = std::env::temp_dir().join(format!("blanqr-palette-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["studio.gpl", "studio.ase", "studio.json"] {
+            let path = dir.join(file);
+            save(&path, &presets()).unwrap();
+            assert_eq!(load(&path), Ok(presets()), "{}", file);
+        }
+        let json = fs::read_to_string(dir.join("studio.json")).unwrap();
+        assert!(json.contains("\"name\": \"studio\""));
+        assert_eq!(json.lines().count(), 8);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_a_bare_json_array() {
+        assert_eq!(
+            parse_json(r##"[{"name":"Black","color":"#000000"}]"##),
+            Ok(vec![preset("Black", 0, 0, 0)])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        assert_eq!(
+            parse_json(r#"{"name":"x"}"#),
+            Err("expected an object with a `colors` array".to_string())
+        );
+        assert_eq!(
+            parse_json(r#"[{"name":"x"}]"#),
+            Err("color 1 is missing `color`".to_string())
+        );
+        assert_eq!(
+            parse_json(r##"[{"color":"#000000"},{"color":"black"}]"##),
+            Err("color 2: invalid color `black`, expected \"#RRGGBB\"".to_string())
+        );
+        assert_eq!(
+            parse_json(r##"[{"color":"#000000","name":1}]"##),
+            Err("color 1: `name` must be a string".to_string())
+        );
+        assert!(parse_json("[").is_err());
+    }
+
+    #[test]
+    fn merge_skips_duplicates() {
+        let mut presets = vec![preset("Black", 0, 0, 0)];
+        let imported = vec![
+            preset("Black", 0, 0, 0),
+            preset("White", 255, 255, 255),
+            preset("White", 255, 255, 255),
+            // Same color, different name: a new preset
+            preset("Paper", 0, 0, 0),
+        ];
+        let merged = merge(&mut presets, imported, 500);
+        assert_eq!(
+            merged,
+            Merged {
+                added: 2,
+                skipped: 0
+            }
+        );
+        assert_eq!(presets.len(), 3);
+        assert_eq!(merged.message(500), "Added 2 presets.");
+
+        let merged = merge(&mut presets, vec![preset("White", 255, 255, 255)], 500);
+        assert_eq!(merged.message(500), "Every color was already a preset.");
+    }
+
+    #[test]
+    fn merge_stops_at_the_limit() {
+        let mut presets = vec![preset("Black", 0, 0, 0)];
+        let imported: Vec<Preset> = (1..=5).map(|n| preset(&n.to_string(), n, n, n)).collect();
+        let merged = merge(&mut presets, imported.clone(), 3);
+        assert_eq!(
+            merged,
+            Merged {
+                added: 2,
+                skipped: 3
+            }
+        );
+        assert_eq!(presets.len(), 3);
+        assert_eq!(presets[2].name, "2");
+        assert_eq!(
+            merged.message(3),
+            "Added 2 presets. Skipped 3 that would go over the limit of 3 presets."
+        );
+
+        // Full: nothing new fits, but known colors aren't counted as skipped
+        let merged = merge(&mut presets, imported, 3);
+        assert_eq!(
+            merged,
+            Merged {
+                added: 0,
+                skipped: 3
+            }
+        );
+        assert_eq!(
+            merged.message(3),
+            "Added no presets. Skipped 3 that would go over the limit of 3 presets."
+        );
+        assert_eq!(
+            Merged {
+                added: 1,
+                skipped: 1
+            }
+            .message(500),
+            "Added 1 preset. Skipped 1 that would go over the limit of 500 presets."
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Measurement patches: a rectangle of one color on a background of another.
+//! `Patch` comes from a pattern generator; `PatchWindow` is the overlay's own
+//! window mode.
+
+use crate::monitor::Rect;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Patch {
+    pub foreground: u32,
+    pub background: u32,
+    pub window: Window,
+}
+
+/// Patch area as fractions of the monitor, origin top-left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Window {
+    pub const FULL: Window = Window {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// The patch in pixels within `area`, clamped to it. A non-empty window
+    /// is at least one pixel in each direction.
+    pub fn to_rect(self, area: &Rect) -> Rect {
+        let (w, h) = (area.width() as f64, area.height() as f64);
+        let x0 = self.x.clamp(0.0, 1.0);
+        let y0 = self.y.clamp(0.0, 1.0);
+        let x1 = (self.x + self.width).clamp(x0, 1.0);
+        let y1 = (self.y + self.height).clamp(y0, 1.0);
+
+        let mut left = area.left + (x0 * w).round() as i32;
+        let mut top = area.top + (y0 * h).round() as i32;
+        let mut right = area.left + (x1 * w).round() as i32;
+        let mut bottom = area.top + (y1 * h).round() as i32;
+        if right == left && x1 > x0 {
+            right = (left + 1).min(area.right);
+            left = right - 1;
+        }
+        if bottom == top && y1 > y0 {
+            bottom = (top + 1).min(area.bottom);
+            top = bottom - 1;
+        }
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+}
+
+/// A patch of the overlay color on a plain background, for window
+/// measurements on displays that dim as more of the screen is lit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchWindow {
+    pub size: PatchSize,
+    /// Share of the free space left of the patch, in percent; 50 centers it.
+    pub x: u8,
+    /// Share of the free space above the patch, in percent; 50 centers it.
+    pub y: u8,
+    pub background: u32,
+}
+
+impl Default for PatchWindow {
+    fn default() -> Self {
+        PatchWindow {
+            size: PatchSize::Area(10),
+            x: 50,
+            y: 50,
+            background: 0x00000000, // Black
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchSize {
+    /// Percent of the monitor's area, 1-100, keeping its aspect ratio.
+    Area(u8),
+    /// Width and height in pixels, shrunk to fit the monitor.
+    Pixels(u32, u32),
+}
+
+impl PatchSize {
+    /// Area presets offered in the tray menu.
+    pub const AREAS: &'static [u8] = &[1, 2, 5, 10, 18, 25, 50];
+
+    /// `10%` or `400x300`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            return match percent.trim().parse::<u8>() {
+                Ok(n) if (1..=100).contains(&n) => Some(PatchSize::Area(n)),
+                _ => None,
+            };
+        }
+        let (w, h) = s.split_once(['x', 'X'])?;
+        match (w.trim().parse::<u32>(), h.trim().parse::<u32>()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => Some(PatchSize::Pixels(w, h)),
+            _ => None,
+        }
+    }
+
+    pub fn display(self) -> String {
+        match self {
+            PatchSize::Area(percent) => format!("{}%", percent),
+            PatchSize::Pixels(w, h) => format!("{}x{}", w, h),
+        }
+    }
+}
+
+impl PatchWindow {
+    /// `X,Y` in percent, e.g. `50,50` for centered.
+    pub fn parse_position(s: &str) -> Option<(u8, u8)> {
+        let (x, y) = s.split_once(',')?;
+        let percent = |s: &str| s.trim().parse::<u8>().ok().filter(|&n| n <= 100);
+        Some((percent(x)?, percent(y)?))
+    }
+
+    /// The patch in pixels on a monitor covering `monitor`. Never larger than
+    /// the monitor, and at least one pixel in each direction unless the
+    /// monitor itself is empty.
+    pub fn rect(self, monitor: &Rect) -> Rect {
+        let (mw, mh) = (monitor.width().max(0), monitor.height().max(0));
+        let (w, h) = match self.size {
+            PatchSize::Area(percent) => {
+                // Scale both sides by the square root to keep the aspect ratio
+                let scale = (percent.clamp(1, 100) as f64 / 100.0).sqrt();
+                (
+                    (mw as f64 * scale).round() as i64,
+                    (mh as f64 * scale).round() as i64,
+                )
+            }
+            PatchSize::Pixels(w, h) => (w as i64, h as i64),
+        };
+        let w = w.clamp(mw.min(1) as i64, mw as i64) as i32;
+        let h = h.clamp(mh.min(1) as i64, mh as i64) as i32;
+
+        let left = monitor.left + (mw - w) * self.x.min(100) as i32 / 100;
+        let top = monitor.top + (mh - h) * self.y.min(100) as i32 / 100;
+        Rect {
+            left,
+            top,
+            right: left + w,
+            bottom: top + h,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: Rect = Rect {
+        left: 0,
+        top: 0,
+        right: 1000,
+        bottom: 1000,
+    };
+    /// A 1920x1080 monitor right of and above the primary.
+    const SECONDARY: Rect = Rect {
+        left: 1920,
+        top: -200,
+        right: 3840,
+        bottom: 880,
+    };
+
+    fn edges(rect: Rect) -> (i32, i32, i32, i32) {
+        (rect.left, rect.top, rect.right, rect.bottom)
+    }
+
+    fn window(x: f64, y: f64, width: f64, height: f64) -> Window {
+        Window {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn patch_window(size: PatchSize, x: u8, y: u8) -> PatchWindow {
+        PatchWindow {
+            size,
+            x,
+            y,
+            ..PatchWindow::default()
+        }
+    }
+
+    #[test]
+    fn window_covers_the_monitor() {
+        assert_eq!(
+            edges(Window::FULL.to_rect(&SECONDARY)),
+            (1920, -200, 3840, 880)
+        );
+        assert_eq!(
+            edges(window(0.45, 0.45, 0.1, 0.1).to_rect(&SECONDARY)),
+            (2784, 286, 2976, 394)
+        );
+    }
+
+    #[test]
+    fn window_rounds_to_pixels() {
+        assert_eq!(
+            edges(window(0.1234, 0.5, 0.0004, 0.2496).to_rect(&SQUARE)),
+            (123, 500, 124, 750)
+        );
+        // Less than a pixel still shows one
+        assert_eq!(
+            edges(window(0.5, 0.5, 0.0001, 0.0001).to_rect(&SQUARE)),
+            (500, 500, 501, 501)
+        );
+        // An empty window stays empty
+        assert_eq!(
+            edges(window(0.5, 0.5, 0.0, 0.0).to_rect(&SQUARE)),
+            (500, 500, 500, 500)
+        );
+    }
+
+    #[test]
+    fn window_is_clamped_to_the_monitor() {
+        assert_eq!(
+            edges(window(-0.5, 0.8, 1.0, 0.5).to_rect(&SQUARE)),
+            (0, 800, 500, 1000)
+        );
+        assert_eq!(
+            edges(window(1.5, -1.0, 0.5, 3.0).to_rect(&SECONDARY)),
+            (3840, -200, 3840, 880)
+        );
+        // The one-pixel minimum moves inward at the far edge
+        assert_eq!(
+            edges(window(0.99995, 0.99995, 0.001, 0.001).to_rect(&SQUARE)),
+            (999, 999, 1000, 1000)
+        );
+    }
+
+    #[test]
+    fn area_keeps_the_aspect_ratio() {
+        let monitor = Rect {
+            left: 0,
+            top: 0,
+            right: 1920,
+            bottom: 1080,
+        };
+        assert_eq!(
+            edges(patch_window(PatchSize::Area(25), 50, 50).rect(&monitor)),
+            (480, 270, 1440, 810)
+        );
+        // sqrt(10%) of each side: 607.2 x 341.5
+        assert_eq!(
+            edges(patch_window(PatchSize::Area(10), 50, 50).rect(&monitor)),
+            (656, 369, 1263, 711)
+        );
+        assert_eq!(
+            edges(patch_window(PatchSize::Area(100), 50, 50).rect(&monitor)),
+            (0, 0, 1920, 1080)
+        );
+    }
+
+    #[test]
+    fn position_shares_the_free_space() {
+        let pixels = PatchSize::Pixels(100, 100);
+        assert_eq!(
+            edges(patch_window(pixels, 0, 100).rect(&SECONDARY)),
+            (1920, 780, 2020, 880)
+        );
+        assert_eq!(
+            edges(patch_window(pixels, 100, 0).rect(&SECONDARY)),
+            (3740, -200, 3840, -100)
+        );
+        // Out-of-range positions stop at the edge
+        assert_eq!(
+            edges(patch_window(pixels, 255, 255).rect(&SQUARE)),
+            (900, 900, 1000, 1000)
+        );
+    }
+
+    #[test]
+    fn patch_window_fits_the_monitor() {
+        assert_eq!(
+            edges(patch_window(PatchSize::Pixels(4000, 300), 50, 50).rect(&SECONDARY)),
+            (1920, 190, 3840, 490)
+        );
+        let tiny = Rect {
+            left: 0,
+            top: 0,
+            right: 3,
+            bottom: 3,
+        };
+        assert_eq!(
+            edges(patch_window(PatchSize::Area(1), 0, 0).rect(&tiny)),
+            (0, 0, 1, 1)
+        );
+        let empty = Rect {
+            left: 10,
+            top: 10,
+            right: 10,
+            bottom: 10,
+        };
+        assert_eq!(
+            edges(patch_window(PatchSize::Area(50), 50, 50).rect(&empty)),
+            (10, 10, 10, 10)
+        );
+    }
+
+    #[test]
+    fn parses_sizes_and_positions() {
+        assert_eq!(PatchSize::parse(" 18% "), Some(PatchSize::Area(18)));
+        assert_eq!(
+            PatchSize::parse("400X300"),
+            Some(PatchSize::Pixels(400, 300))
+        );
+        for bad in ["0%", "101%", "400x0", "400", "x300", "big"] {
+            assert_eq!(PatchSize::parse(bad), None, "{}", bad);
+        }
+        assert_eq!(PatchSize::Pixels(400, 300).display(), "400x300");
+        assert_eq!(PatchWindow::parse_position("0, 100"), Some((0, 100)));
+        assert_eq!(PatchWindow::parse_position("50,101"), None);
+        assert_eq!(PatchWindow::parse_position("50"), None);
+    }
+}
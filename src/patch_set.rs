@@ -0,0 +1,270 @@
+//! A list of colors to step through on the overlay, loaded from a CGATS file
+//! (`.ti1`, `.ti2`, `.ti3`) or a CSV of RGB values.
+
+use crate::cgats;
+use crate::color;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchSet {
+    /// File name, for notifications.
+    pub name: String,
+    pub colors: Vec<u32>,
+    index: usize,
+}
+
+impl PatchSet {
+    /// Reads `path`, picking the format from its extension.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv") || ext.eq_ignore_ascii_case("txt"));
+        let colors = if is_csv {
+            parse_csv(&text)
+        } else {
+            parse_cgats(&text)
+        }
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(PatchSet {
+            name,
+            colors,
+            index: 0,
+        })
+    }
+
+    pub fn current(&self) -> u32 {
+        self.colors[self.index]
+    }
+
+    /// Position for display, e.g. `3 / 240`.
+    pub fn label(&self) -> String {
+        format!("{} / {}", self.index + 1, self.colors.len())
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.index + 1 == self.colors.len()
+    }
+
+    /// Moves by `delta` patches, stopping at either end. Returns whether the
+    /// current patch changed.
+    pub fn step(&mut self, delta: isize) -> bool {
+        let last = self.colors.len() as isize - 1;
+        let index = (self.index as isize).saturating_add(delta).clamp(0, last) as usize;
+        let moved = index != self.index;
+        self.index = index;
+        moved
+    }
+}
+
+/// RGB columns of the first table that has them. Argyll device values run
+/// from 0 to 100.
+fn parse_cgats(text: &str) -> Result<Vec<u32>, String> {
+    let tables = cgats::parse(text).map_err(|err| err.to_string())?;
+    let (table, columns) = tables
+        .iter()
+        .find_map(|t| {
+            let columns = [t.column("RGB_R")?, t.column("RGB_G")?, t.column("RGB_B")?];
+            Some((t, columns))
+        })
+        .ok_or_else(|| "no table with RGB_R, RGB_G and RGB_B fields".to_string())?;
+
+    let mut colors = Vec::with_capacity(table.rows.len());
+    for (i, row) in table.rows.iter().enumerate() {
+        let mut rgb = [0u8; 3];
+        for (channel, &column) in rgb.iter_mut().zip(&columns) {
+            let value = row[column]
+                .parse::<f64>()
+                .ok()
+                .filter(|v| (0.0..=100.0).contains(v))
+                .ok_or_else(|| {
+                    format!(
+                        "patch {}: `{}` is not a value from 0 to 100",
+                        i + 1,
+                        row[column]
+                    )
+                })?;
+            *channel = (value * 255.0 / 100.0).round() as u8;
+        }
+        colors.push(color::rgb_to_colorref(rgb[0], rgb[1], rgb[2]));
+    }
+    if colors.is_empty() {
+        return Err("the patch table is empty".to_string());
+    }
+    Ok(colors)
+}
+
+/// One patch per line: exactly `R,G,B` from 0 to 255 (commas, semicolons,
+/// tabs or spaces between them) or `#RRGGBB`. The first non-empty line is
+/// skipped when it holds column names.
+fn parse_csv(text: &str) -> Result<Vec<u32>, String> {
+    let mut colors = Vec::new();
+    let mut first = true;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let header = std::mem::replace(&mut first, false);
+        if let Some(color) = color::parse_hex(line) {
+            colors.push(color);
+            continue;
+        }
+
+        let fields: Vec<&str> = line
+            .split([',', ';', '\t', ' '])
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .collect();
+        let values: Option<Vec<u8>> = fields.iter().map(|f| f.parse().ok()).collect();
+        match values {
+            Some(v) if v.len() == 3 => colors.push(color::rgb_to_colorref(v[0], v[1], v[2])),
+            // Column names
+            _ if header && fields.iter().any(|f| f.parse::<f64>().is_err()) => {}
+            _ => {
+                return Err(format!(
+                    "line {}: expected R,G,B from 0 to 255 or #RRGGBB, found `{}`",
+                    i + 1,
+                    line
+                ))
+            }
+        }
+    }
+    if colors.is_empty() {
+        return Err("no colors found".to_string());
+    }
+    Ok(colors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> u32 {
+        color::rgb_to_colorref(r, g, b)
+    }
+
+    #[test]
+    fn loads_a_ti1() {
+        let patches = PatchSet::load(&testdata("patches.ti1")).unwrap();
+        assert_eq!(patches.name, "patches.ti1");
+        assert_eq!(
+            patches.colors,
+            [
+                rgb(255, 255, 255),
+                rgb(0, 0, 0),
+                rgb(255, 0, 0),
+                rgb(128, 64, 191)
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_a_ti3() {
+        let patches = PatchSet::load(&testdata("measured.ti3")).unwrap();
+        assert_eq!(
+            patches.colors,
+            [rgb(255, 255, 255), rgb(0, 255, 0), rgb(51, 51, 51)]
+        );
+    }
+
+    #[test]
+    fn loads_a_csv() {
+        let patches = PatchSet::load(&testdata("patches.csv")).unwrap();
+        assert_eq!(
+            patches.colors,
+            [
+                rgb(255, 255, 255),
+                rgb(0, 0, 0),
+                rgb(128, 64, 32),
+                rgb(255, 0, 0),
+                rgb(10, 20, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_file() {
+        let err = PatchSet::load(&testdata("missing.ti1")).unwrap_err();
+        assert!(err.contains("missing.ti1"), "{}", err);
+    }
+
+    #[test]
+    fn csv_rows_have_exactly_three_values() {
+        for line in ["1,2", "1,2,3,4", "1,2,x", "256,0,0", "-1,0,0", "1.5,2,3"] {
+            let text = format!("1,2,3\n{}\n", line);
+            assert_eq!(
+                parse_csv(&text),
+                Err(format!(
+                    "line 2: expected R,G,B from 0 to 255 or #RRGGBB, found `{}`",
+                    line
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn csv_header_is_the_first_non_empty_line() {
+        assert_eq!(
+            parse_csv("\n  \nred;green;blue\n1;2;3\n"),
+            Ok(vec![rgb(1, 2, 3)])
+        );
+        assert!(parse_csv("1,2,3\nR,G,B\n").is_err());
+        // A numeric first line is data, even with a stray column
+        assert!(parse_csv("1,2,3,4\n5,6,7\n").is_err());
+        assert_eq!(parse_csv("R,G,B\n"), Err("no colors found".to_string()));
+        assert_eq!(parse_csv(""), Err("no colors found".to_string()));
+    }
+
+    #[test]
+    fn cgats_needs_rgb_from_0_to_100() {
+        let table = |row: &str| {
+            format!(
+                "CTI1\nBEGIN_DATA_FORMAT\nRGB_R RGB_G RGB_B\nEND_DATA_FORMAT\n\
+                 BEGIN_DATA\n{}\nEND_DATA\n",
+                row
+            )
+        };
+        assert_eq!(parse_cgats(&table("0 50 100")), Ok(vec![rgb(0, 128, 255)]));
+        assert_eq!(
+            parse_cgats(&table("0 100.5 0")),
+            Err("patch 1: `100.5` is not a value from 0 to 100".to_string())
+        );
+        assert_eq!(
+            parse_cgats("CTI3\nBEGIN_DATA_FORMAT\nXYZ_X XYZ_Y XYZ_Z\nEND_DATA_FORMAT\n"),
+            Err("no table with RGB_R, RGB_G and RGB_B fields".to_string())
+        );
+        assert_eq!(
+            parse_cgats(
+                "CTI1\nBEGIN_DATA_FORMAT\nRGB_R RGB_G RGB_B\nEND_DATA_FORMAT\nBEGIN_DATA\nEND_DATA\n"
+            ),
+            Err("the patch table is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn steps_between_the_ends() {
+        let mut patches = PatchSet::load(&testdata("measured.ti3")).unwrap();
+        assert_eq!(patches.label(), "1 / 3");
+        assert!(!patches.step(-1));
+        assert!(patches.step(5));
+        assert!(patches.is_last());
+        assert_eq!(patches.current(), rgb(51, 51, 51));
+        assert!(patches.step(-1));
+        assert_eq!(patches.label(), "2 / 3");
+    }
+}
@@ -0,0 +1,47 @@
+/// What the overlay draws with the current color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pattern {
+    #[default]
+    Solid,
+    /// Horizontal ramp from black to the color.
+    Gradient,
+    /// Alternating squares of the color and black.
+    Checkerboard,
+    /// Thin lines of the color on black.
+    Grid,
+}
+
+impl Pattern {
+    pub const ALL: &'static [Pattern] = &[
+        Pattern::Solid,
+        Pattern::Gradient,
+        Pattern::Checkerboard,
+        Pattern::Grid,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Solid => "solid",
+            Pattern::Gradient => "gradient",
+            Pattern::Checkerboard => "checkerboard",
+            Pattern::Grid => "grid",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|p| p.name().eq_ignore_ascii_case(s))
+    }
+
+    /// Comma-separated names, for error messages and help text.
+    pub fn names() -> String {
+        Self::ALL
+            .iter()
+            .map(|p| p.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,222 @@
+//! Timed color sequences for soak and burn-in tests. The player is pure:
+//! callers pass the current time, arm a timer for `deadline()` and call
+//! `tick` when it fires.
+
+use crate::pattern::Pattern;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub color: u32,
+    pub pattern: Pattern,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    pub name: String,
+    pub steps: Vec<Step>,
+    /// Start over after the last step instead of finishing.
+    pub looping: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Tick {
+    /// Still on the same step.
+    Unchanged,
+    /// Moved on; show `Player::step`.
+    Advanced,
+    /// A one-shot sequence ran out of steps.
+    Finished,
+}
+
+pub struct Player {
+    sequence: Sequence,
+    index: usize,
+    /// When the current step ends; `None` while paused or finished.
+    deadline: Option<Instant>,
+    /// What was left of the current step when it was paused.
+    remaining: Option<Duration>,
+}
+
+impl Player {
+    /// Starts at the first step. `sequence` must have at least one step.
+    pub fn start(sequence: Sequence, now: Instant) -> Self {
+        let first = sequence.steps[0].duration;
+        Player {
+            sequence,
+            index: 0,
+            deadline: Some(now + first),
+            remaining: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.sequence.name
+    }
+
+    pub fn step(&self) -> &Step {
+        &self.sequence.steps[self.index]
+    }
+
+    /// Position as `name 2 / 5`.
+    pub fn label(&self) -> String {
+        format!(
+            "{} {} / {}",
+            self.sequence.name,
+            self.index + 1,
+            self.sequence.steps.len()
+        )
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.remaining.is_some()
+    }
+
+    /// Freezes the current step, keeping the time it has left.
+    pub fn pause(&mut self, now: Instant) {
+        if let Some(deadline) = self.deadline.take() {
+            self.remaining = Some(deadline.saturating_duration_since(now));
+        }
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if let Some(remaining) = self.remaining.take() {
+            self.deadline = Some(now + remaining);
+        }
+    }
+
+    /// Moves past every step that has ended by `now`. A late tick skips
+    /// steps rather than showing each briefly.
+    pub fn tick(&mut self, now: Instant) -> Tick {
+        let Some(mut deadline) = self.deadline else {
+            return Tick::Unchanged;
+        };
+        let mut advanced = false;
+        while now >= deadline {
+            if self.index + 1 < self.sequence.steps.len() {
+                self.index += 1;
+            } else if self.sequence.looping {
+                self.index = 0;
+            } else {
+                self.deadline = None;
+                return Tick::Finished;
+            }
+            advanced = true;
+            deadline += self.step().duration;
+        }
+        self.deadline = Some(deadline);
+
+        if advanced {
+            Tick::Advanced
+        } else {
+            Tick::Unchanged
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn step(color: u32, seconds: u64) -> Step {
+        Step {
+            color,
+            pattern: Pattern::Solid,
+            duration: Duration::from_secs(seconds),
+        }
+    }
+
+    /// Red for 1s, green for 2s, blue for 3s.
+    fn sequence(looping: bool) -> Sequence {
+        Sequence {
+            name: "rgb".to_string(),
+            steps: vec![step(0x0000FF, 1), step(0x00FF00, 2), step(0xFF0000, 3)],
+            looping,
+        }
+    }
+
+    #[test]
+    fn steps_on_time() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(false), start);
+        assert_eq!(player.step().color, 0x0000FF);
+        assert_eq!(player.deadline(), Some(start + SECOND));
+        assert_eq!(player.label(), "rgb 1 / 3");
+
+        assert_eq!(player.tick(start + SECOND / 2), Tick::Unchanged);
+        assert_eq!(player.tick(start + SECOND), Tick::Advanced);
+        assert_eq!(player.step().color, 0x00FF00);
+        assert_eq!(player.label(), "rgb 2 / 3");
+        // Measured from the step's scheduled start, not from the tick
+        assert_eq!(player.deadline(), Some(start + 3 * SECOND));
+    }
+
+    #[test]
+    fn late_ticks_skip_steps() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(false), start);
+        assert_eq!(player.tick(start + 4 * SECOND), Tick::Advanced);
+        assert_eq!(player.step().color, 0xFF0000);
+        assert_eq!(player.deadline(), Some(start + 6 * SECOND));
+    }
+
+    #[test]
+    fn one_shot_sequences_finish() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(false), start);
+        assert_eq!(player.tick(start + 6 * SECOND), Tick::Finished);
+        assert_eq!(player.deadline(), None);
+        assert_eq!(player.step().color, 0xFF0000);
+        assert_eq!(player.tick(start + 60 * SECOND), Tick::Unchanged);
+    }
+
+    #[test]
+    fn looping_sequences_start_over() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(true), start);
+        assert_eq!(player.tick(start + 6 * SECOND), Tick::Advanced);
+        assert_eq!(player.step().color, 0x0000FF);
+        assert_eq!(player.deadline(), Some(start + 7 * SECOND));
+        // Many laps late still lands on the right step
+        assert_eq!(
+            player.tick(start + 600 * SECOND + 2 * SECOND),
+            Tick::Advanced
+        );
+        assert_eq!(player.step().color, 0x00FF00);
+    }
+
+    #[test]
+    fn pausing_keeps_the_time_left() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(false), start);
+        player.pause(start + SECOND / 4);
+        assert!(player.is_paused());
+        assert_eq!(player.deadline(), None);
+        assert_eq!(player.tick(start + 10 * SECOND), Tick::Unchanged);
+        assert_eq!(player.step().color, 0x0000FF);
+
+        let resumed = start + 10 * SECOND;
+        player.resume(resumed);
+        assert!(!player.is_paused());
+        assert_eq!(player.deadline(), Some(resumed + SECOND * 3 / 4));
+        assert_eq!(player.tick(resumed + SECOND), Tick::Advanced);
+    }
+
+    #[test]
+    fn stopped_players_ignore_pause_and_resume() {
+        let start = Instant::now();
+        let mut player = Player::start(sequence(false), start);
+        player.resume(start);
+        assert_eq!(player.deadline(), Some(start + SECOND));
+        assert_eq!(player.tick(start + 6 * SECOND), Tick::Finished);
+        player.pause(start + 7 * SECOND);
+        assert!(!player.is_paused());
+    }
+}
@@ -0,0 +1,21 @@
+//! Keeping the display on during long tests, when power management would
+//! otherwise blank the monitor partway through. Windows only; there is no
+//! DPMS or screensaver inhibit for Linux.
+
+use windows_sys::Win32::System::Power::{
+    SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+};
+
+/// Stops the display and the system from sleeping while `on`, until called
+/// again with `false`. The request belongs to the calling thread, so call it
+/// from the UI thread only.
+pub fn keep_display_on(on: bool) {
+    let state = if on {
+        ES_CONTINUOUS | ES_DISPLAY_REQUIRED | ES_SYSTEM_REQUIRED
+    } else {
+        ES_CONTINUOUS
+    };
+    unsafe {
+        SetThreadExecutionState(state);
+    }
+}
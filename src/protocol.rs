@@ -0,0 +1,458 @@
+//! Line-delimited JSON control protocol, shared by the control server and
+//! `blanqrctl`. Pure: encoding and decoding only, no transport.
+//!
+//! Each line is one JSON object. Requests carry a `cmd` and an optional
+//! `id` that is echoed in the response:
+//!
+//! ```text
+//! -> {"id":1,"cmd":"set_color","color":"#FF0000"}
+//! <- {"id":1,"ok":true}
+//! -> {"id":2,"cmd":"frobnicate"}
+//! <- {"id":2,"ok":false,"error":"unknown command `frobnicate`"}
+//! <- {"event":"color_changed","color":"#FF0000"}
+//! ```
+
+use crate::color;
+use crate::json::{self, Value};
+use crate::pattern::Pattern;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Show,
+    Hide,
+    Toggle,
+    SetColor(u32),
+    SetPattern(Pattern),
+    ListMonitors,
+    GetState,
+    /// Starts sending the given events on this connection.
+    Subscribe(Vec<EventKind>),
+    Unsubscribe(Vec<EventKind>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub id: Option<Value>,
+    pub command: Command,
+}
+
+/// A request that could not be decoded. `id` is kept when it was readable so
+/// the error response still correlates.
+#[derive(Debug, PartialEq)]
+pub struct RequestError {
+    pub id: Option<Value>,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Shown,
+    Hidden,
+    ColorChanged,
+}
+
+impl EventKind {
+    pub const ALL: &'static [EventKind] =
+        &[EventKind::Shown, EventKind::Hidden, EventKind::ColorChanged];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Shown => "shown",
+            EventKind::Hidden => "hidden",
+            EventKind::ColorChanged => "color_changed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|k| k.name() == s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Shown,
+    Hidden,
+    ColorChanged(u32),
+}
+
+impl Event {
+    pub fn kind(self) -> EventKind {
+        match self {
+            Event::Shown => EventKind::Shown,
+            Event::Hidden => EventKind::Hidden,
+            Event::ColorChanged(_) => EventKind::ColorChanged,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub visible: bool,
+    pub color: u32,
+    pub pattern: Pattern,
+    pub brightness: u8,
+    pub profile: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub number: u32,
+    pub name: String,
+    pub primary: bool,
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Successful results; `Done` has no payload.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    Done,
+    State(State),
+    Monitors(Vec<Monitor>),
+}
+
+pub fn parse_request(line: &str) -> Result<Request, RequestError> {
+    let value = json::parse(line).map_err(|err| RequestError {
+        id: None,
+        message: format!("invalid JSON: {}", err),
+    })?;
+    if !matches!(value, Value::Object(_)) {
+        return Err(RequestError {
+            id: None,
+            message: format!("expected an object, found {}", value.type_name()),
+        });
+    }
+
+    let id = value.get("id").cloned();
+    let fail = |message: String| RequestError {
+        id: id.clone(),
+        message,
+    };
+
+    let cmd = match value.get("cmd") {
+        Some(Value::String(cmd)) => cmd.as_str(),
+        Some(other) => {
+            return Err(fail(format!(
+                "`cmd` must be a string, found {}",
+                other.type_name()
+            )))
+        }
+        None => return Err(fail("missing `cmd`".to_string())),
+    };
+
+    let string_arg = |name: &str| match value.get(name) {
+        Some(Value::String(s)) => Ok(s.as_str()),
+        Some(other) => Err(fail(format!(
+            "`{}` must be a string, found {}",
+            name,
+            other.type_name()
+        ))),
+        None => Err(fail(format!("`{}` needs `{}`", cmd, name))),
+    };
+
+    let command = match cmd {
+        "show" => Command::Show,
+        "hide" => Command::Hide,
+        "toggle" => Command::Toggle,
+        "set_color" => {
+            let hex = string_arg("color")?;
+            let color = color::parse_hex(hex)
+                .ok_or_else(|| fail(format!("invalid color `{}`, expected #RRGGBB", hex)))?;
+            Command::SetColor(color)
+        }
+        "set_pattern" => {
+            let name = string_arg("pattern")?;
+            let pattern = Pattern::parse(name).ok_or_else(|| {
+                fail(format!(
+                    "invalid pattern `{}`, expected one of {}",
+                    name,
+                    Pattern::names()
+                ))
+            })?;
+            Command::SetPattern(pattern)
+        }
+        "list_monitors" => Command::ListMonitors,
+        "get_state" => Command::GetState,
+        "subscribe" | "unsubscribe" => {
+            let events = event_kinds(value.get("events")).map_err(fail)?;
+            if cmd == "subscribe" {
+                Command::Subscribe(events)
+            } else {
+                Command::Unsubscribe(events)
+            }
+        }
+        other => return Err(fail(format!("unknown command `{}`", other))),
+    };
+
+    Ok(Request { id, command })
+}
+
+/// A missing `events` list means every event.
+fn event_kinds(value: Option<&Value>) -> Result<Vec<EventKind>, String> {
+    let Some(value) = value else {
+        return Ok(EventKind::ALL.to_vec());
+    };
+    let items = value
+        .as_array()
+        .ok_or_else(|| format!("`events` must be an array, found {}", value.type_name()))?;
+    items
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .and_then(EventKind::parse)
+                .ok_or_else(|| format!("unknown event {}", item))
+        })
+        .collect()
+}
+
+// Only blanqrctl sends requests
+#[allow(dead_code)]
+pub fn encode_request(request: &Request) -> String {
+    let event_names =
+        |events: &[EventKind]| Value::Array(events.iter().map(|k| Value::from(k.name())).collect());
+    let mut fields = Vec::new();
+    if let Some(id) = &request.id {
+        fields.push(("id".to_string(), id.clone()));
+    }
+    let (cmd, arg) = match &request.command {
+        Command::Show => ("show", None),
+        Command::Hide => ("hide", None),
+        Command::Toggle => ("toggle", None),
+        Command::SetColor(c) => ("set_color", Some(("color", Value::from(color::to_hex(*c))))),
+        Command::SetPattern(p) => ("set_pattern", Some(("pattern", Value::from(p.name())))),
+        Command::ListMonitors => ("list_monitors", None),
+        Command::GetState => ("get_state", None),
+        Command::Subscribe(events) => ("subscribe", Some(("events", event_names(events)))),
+        Command::Unsubscribe(events) => ("unsubscribe", Some(("events", event_names(events)))),
+    };
+    fields.push(("cmd".to_string(), Value::from(cmd)));
+    if let Some((name, value)) = arg {
+        fields.push((name.to_string(), value));
+    }
+    Value::Object(fields).to_string()
+}
+
+pub fn encode_response(id: Option<&Value>, result: Result<Reply, String>) -> String {
+    let mut fields = Vec::new();
+    if let Some(id) = id {
+        fields.push(("id".to_string(), id.clone()));
+    }
+    match result {
+        Ok(reply) => {
+            fields.push(("ok".to_string(), Value::Bool(true)));
+            match reply {
+                Reply::Done => {}
+                Reply::State(state) => fields.push(("result".to_string(), state_value(&state))),
+                Reply::Monitors(monitors) => fields.push((
+                    "result".to_string(),
+                    Value::Array(monitors.iter().map(monitor_value).collect()),
+                )),
+            }
+        }
+        Err(message) => {
+            fields.push(("ok".to_string(), Value::Bool(false)));
+            fields.push(("error".to_string(), Value::from(message)));
+        }
+    }
+    Value::Object(fields).to_string()
+}
+
+pub fn encode_event(event: Event) -> String {
+    let mut fields = vec![("event".to_string(), Value::from(event.kind().name()))];
+    if let Event::ColorChanged(c) = event {
+        fields.push(("color".to_string(), Value::from(color::to_hex(c))));
+    }
+    Value::Object(fields).to_string()
+}
+
+pub fn state_value(state: &State) -> Value {
+    Value::object([
+        ("visible", Value::from(state.visible)),
+        ("color", Value::from(color::to_hex(state.color))),
+        ("pattern", Value::from(state.pattern.name())),
+        ("brightness", Value::from(state.brightness as u32)),
+        (
+            "profile",
+            state
+                .profile
+                .clone()
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+        ),
+    ])
+}
+
+pub fn monitor_value(monitor: &Monitor) -> Value {
+    Value::object([
+        ("number", Value::from(monitor.number)),
+        ("name", Value::from(monitor.name.as_str())),
+        ("primary", Value::from(monitor.primary)),
+        ("left", Value::from(monitor.left)),
+        ("top", Value::from(monitor.top)),
+        ("width", Value::from(monitor.width)),
+        ("height", Value::from(monitor.height)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: Option<Value>, command: Command) -> Request {
+        Request { id, command }
+    }
+
+    fn error(line: &str) -> RequestError {
+        parse_request(line).unwrap_err()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            request(Some(Value::Number(1.0)), Command::Show),
+            request(None, Command::Hide),
+            request(Some(Value::from("t")), Command::Toggle),
+            request(Some(Value::Number(2.0)), Command::SetColor(0x0000FF)),
+            request(None, Command::SetPattern(Pattern::Grid)),
+            request(None, Command::ListMonitors),
+            request(None, Command::GetState),
+            request(None, Command::Subscribe(vec![EventKind::Shown])),
+            request(None, Command::Unsubscribe(EventKind::ALL.to_vec())),
+        ];
+        for request in requests {
+            let line = encode_request(&request);
+            assert!(!line.contains('\n'));
+            assert_eq!(parse_request(&line), Ok(request));
+        }
+    }
+
+    #[test]
+    fn encodes_requests() {
+        assert_eq!(
+            encode_request(&request(
+                Some(Value::Number(1.0)),
+                Command::SetColor(0x0000FF)
+            )),
+            r##"{"id":1,"cmd":"set_color","color":"#FF0000"}"##
+        );
+        assert_eq!(
+            encode_request(&request(
+                None,
+                Command::Subscribe(vec![EventKind::ColorChanged])
+            )),
+            r#"{"cmd":"subscribe","events":["color_changed"]}"#
+        );
+    }
+
+    #[test]
+    fn subscribe_defaults_to_every_event() {
+        let parsed = parse_request(r#"{"cmd":"subscribe"}"#).unwrap();
+        assert_eq!(parsed.command, Command::Subscribe(EventKind::ALL.to_vec()));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let err = error(r#"{"cmd":"show""#);
+        assert_eq!(err.id, None);
+        assert!(err.message.starts_with("invalid JSON"), "{}", err.message);
+        assert!(error("").message.starts_with("invalid JSON"));
+        assert!(error(r#"{"cmd":"show"} x"#)
+            .message
+            .starts_with("invalid JSON"));
+        assert_eq!(
+            error(r#"["show"]"#),
+            RequestError {
+                id: None,
+                message: "expected an object, found array".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn errors_keep_the_id() {
+        let err = error(r#"{"id":7,"cmd":"frobnicate"}"#);
+        assert_eq!(err.id, Some(Value::Number(7.0)));
+        assert_eq!(err.message, "unknown command `frobnicate`");
+        assert_eq!(error(r#"{"id":"a"}"#).id, Some(Value::from("a")));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let cases = [
+            (r#"{}"#, "missing `cmd`"),
+            (r#"{"cmd":3}"#, "`cmd` must be a string, found number"),
+            (r#"{"cmd":"set_color"}"#, "`set_color` needs `color`"),
+            (
+                r#"{"cmd":"set_color","color":255}"#,
+                "`color` must be a string, found number",
+            ),
+            (
+                r#"{"cmd":"set_color","color":"red"}"#,
+                "invalid color `red`, expected #RRGGBB",
+            ),
+            (
+                r#"{"cmd":"subscribe","events":"shown"}"#,
+                "`events` must be an array, found string",
+            ),
+            (
+                r#"{"cmd":"subscribe","events":["gone"]}"#,
+                r#"unknown event "gone""#,
+            ),
+        ];
+        for (line, message) in cases {
+            assert_eq!(error(line).message, message, "{}", line);
+        }
+        assert!(error(r#"{"cmd":"set_pattern","pattern":"stripes"}"#)
+            .message
+            .starts_with("invalid pattern `stripes`"));
+    }
+
+    #[test]
+    fn encodes_responses() {
+        let id = Value::Number(3.0);
+        assert_eq!(
+            encode_response(Some(&id), Ok(Reply::Done)),
+            r#"{"id":3,"ok":true}"#
+        );
+        assert_eq!(
+            encode_response(None, Err("unknown command `x`".to_string())),
+            r#"{"ok":false,"error":"unknown command `x`"}"#
+        );
+        let state = State {
+            visible: true,
+            color: 0x00FF00,
+            pattern: Pattern::Solid,
+            brightness: 80,
+            profile: None,
+        };
+        assert_eq!(
+            encode_response(None, Ok(Reply::State(state))),
+            r##"{"ok":true,"result":{"visible":true,"color":"#00FF00","pattern":"solid","brightness":80,"profile":null}}"##
+        );
+        let monitor = Monitor {
+            number: 2,
+            name: "DISPLAY2".to_string(),
+            primary: false,
+            left: -1920,
+            top: 0,
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(
+            encode_response(None, Ok(Reply::Monitors(vec![monitor]))),
+            r#"{"ok":true,"result":[{"number":2,"name":"DISPLAY2","primary":false,"left":-1920,"top":0,"width":1920,"height":1080}]}"#
+        );
+    }
+
+    #[test]
+    fn encodes_events() {
+        assert_eq!(encode_event(Event::Shown), r#"{"event":"shown"}"#);
+        assert_eq!(encode_event(Event::Hidden), r#"{"event":"hidden"}"#);
+        assert_eq!(
+            encode_event(Event::ColorChanged(0xFFFFFF)),
+            r##"{"event":"color_changed","color":"#FFFFFF"}"##
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! The Resolve-style pattern generator protocol spoken by DisplayCAL
+//! (ArgyllCMS), HCFR and others. The calibration software listens; the
+//! generator connects and receives one frame per patch: a 4-byte big-endian
+//! length followed by an XML document such as
+//!
+//! ```text
+//! <?xml version="1.0" encoding="UTF-8" ?><calibration>
+//!   <color red="255" green="255" blue="255" bits="8"/>
+//!   <background red="0" green="0" blue="0" bits="8"/>
+//!   <geometry x="0.4500" y="0.4500" cx="0.1000" cy="0.1000"/>
+//! </calibration>
+//! ```
+//!
+//! Pure; `generator` owns the connection.
+
+use crate::color;
+use crate::patch::{Patch, Window};
+
+/// Port DisplayCAL listens on for Resolve.
+pub const DEFAULT_PORT: u16 = 20002;
+const MAX_FRAME: usize = 64 * 1024;
+
+/// Removes one complete frame from the front of `buf`. `Ok(None)` means more
+/// bytes are needed; an error means the stream is out of sync.
+pub fn take_frame(buf: &mut Vec<u8>) -> Result<Option<String>, String> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+    let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if len > MAX_FRAME {
+        return Err(format!("frame of {} bytes is too large", len));
+    }
+    if buf.len() < 4 + len {
+        return Ok(None);
+    }
+    let frame: Vec<u8> = buf.drain(..4 + len).skip(4).collect();
+    String::from_utf8(frame)
+        .map(Some)
+        .map_err(|_| "frame is not UTF-8".to_string())
+}
+
+/// Decodes a `<calibration>` document. Background defaults to black and
+/// geometry to the full screen.
+pub fn parse_calibration(xml: &str) -> Result<Patch, String> {
+    if element(xml, "calibration").is_none() {
+        return Err("missing <calibration>".to_string());
+    }
+    let foreground = match element(xml, "color") {
+        Some(attrs) => rgb(&attrs)?,
+        None => return Err("missing <color>".to_string()),
+    };
+    let background = match element(xml, "background") {
+        Some(attrs) => rgb(&attrs)?,
+        None => 0,
+    };
+    let window = match element(xml, "geometry") {
+        Some(attrs) => Window {
+            x: number(&attrs, "x")?,
+            y: number(&attrs, "y")?,
+            width: number(&attrs, "cx")?,
+            height: number(&attrs, "cy")?,
+        },
+        None => Window::FULL,
+    };
+    Ok(Patch {
+        foreground,
+        background,
+        window,
+    })
+}
+
+/// Converts `red`/`green`/`blue` at `bits` depth (default 8) to a COLORREF.
+fn rgb(attrs: &[(String, String)]) -> Result<u32, String> {
+    let bits = match attr(attrs, "bits") {
+        Some(_) => number(attrs, "bits")?,
+        None => 8.0,
+    };
+    if !(1.0..=16.0).contains(&bits) {
+        return Err(format!("unsupported bit depth {}", bits));
+    }
+    let max = (2f64.powi(bits as i32) - 1.0).max(1.0);
+    let channel = |name: &str| -> Result<u8, String> {
+        let value = number(attrs, name)?;
+        Ok((value.clamp(0.0, max) * 255.0 / max).round() as u8)
+    };
+    Ok(color::rgb_to_colorref(
+        channel("red")?,
+        channel("green")?,
+        channel("blue")?,
+    ))
+}
+
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+fn number(attrs: &[(String, String)], name: &str) -> Result<f64, String> {
+    let value = attr(attrs, name).ok_or_else(|| format!("missing attribute `{}`", name))?;
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| format!("invalid number `{}` for `{}`", value, name))
+}
+
+/// Attributes of the first `<name ...>` element, or `None` if there is none.
+/// Just enough XML for these documents: no namespaces, no entities beyond
+/// the predefined ones.
+fn element(xml: &str, name: &str) -> Option<Vec<(String, String)>> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        // Require a delimiter so `<color` doesn't match `<colorspace>`
+        if let Some(after) = rest.strip_prefix(name) {
+            if after.starts_with(|c: char| c.is_whitespace() || c == '/' || c == '>') {
+                return Some(attributes(after));
+            }
+        }
+    }
+    None
+}
+
+fn attributes(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start();
+        if s.is_empty() || s.starts_with('>') || s.starts_with("/>") {
+            return attrs;
+        }
+        let Some(eq) = s.find('=') else {
+            return attrs;
+        };
+        let name = s[..eq].trim().to_string();
+        s = s[eq + 1..].trim_start();
+        let Some(quote) = s.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            return attrs;
+        };
+        let Some(end) = s[1..].find(quote) else {
+            return attrs;
+        };
+        attrs.push((name, unescape(&s[1..1 + end])));
+        s = &s[end + 2..];
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE_PATCH: &str = r#"<?xml version="1.0" encoding="UTF-8" ?><calibration>
+  <color red="255" green="255" blue="255" bits="8"/>
+  <background red="0" green="0" blue="0" bits="8"/>
+  <geometry x="0.4500" y="0.4500" cx="0.1000" cy="0.1000"/>
+</calibration>"#;
+
+    fn frame(xml: &str) -> Vec<u8> {
+        let mut bytes = (xml.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(xml.as_bytes());
+        bytes
+    }
+
+    fn calibration(body: &str) -> Result<Patch, String> {
+        parse_calibration(&format!("<calibration>{}</calibration>", body))
+    }
+
+    #[test]
+    fn waits_for_split_frames() {
+        let bytes = frame(WHITE_PATCH);
+        let mut buf = Vec::new();
+        for (i, &byte) in bytes.iter().enumerate() {
+            assert_eq!(take_frame(&mut buf), Ok(None), "after {} bytes", i);
+            buf.push(byte);
+        }
+        assert_eq!(take_frame(&mut buf), Ok(Some(WHITE_PATCH.to_string())));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn takes_concatenated_frames_in_order() {
+        let mut buf = frame("first");
+        buf.extend(frame(""));
+        buf.extend(frame("second"));
+        buf.extend_from_slice(&[0, 0]);
+        assert_eq!(take_frame(&mut buf), Ok(Some("first".to_string())));
+        assert_eq!(take_frame(&mut buf), Ok(Some(String::new())));
+        assert_eq!(take_frame(&mut buf), Ok(Some("second".to_string())));
+        assert_eq!(take_frame(&mut buf), Ok(None));
+        assert_eq!(buf, [0, 0]);
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut oversized = ((MAX_FRAME + 1) as u32).to_be_bytes().to_vec();
+        assert_eq!(
+            take_frame(&mut oversized),
+            Err(format!("frame of {} bytes is too large", MAX_FRAME + 1))
+        );
+        let mut largest = (MAX_FRAME as u32).to_be_bytes().to_vec();
+        assert_eq!(take_frame(&mut largest), Ok(None));
+        let mut binary = vec![0, 0, 0, 2, 0xFF, 0xFE];
+        assert_eq!(
+            take_frame(&mut binary),
+            Err("frame is not UTF-8".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_a_patch() {
+        let patch = parse_calibration(WHITE_PATCH).unwrap();
+        assert_eq!(patch.foreground, 0xFFFFFF);
+        assert_eq!(patch.background, 0);
+        assert_eq!(
+            patch.window,
+            Window {
+                x: 0.45,
+                y: 0.45,
+                width: 0.1,
+                height: 0.1,
+            }
+        );
+    }
+
+    #[test]
+    fn scales_other_bit_depths() {
+        let patch = calibration(r#"<color red="1023" green="512" blue="0" bits="10"/>"#).unwrap();
+        assert_eq!(patch.foreground, color::rgb_to_colorref(255, 128, 0));
+        let patch =
+            calibration(r#"<color red="65535" green="0" blue="99999" bits="16"/>"#).unwrap();
+        assert_eq!(patch.foreground, color::rgb_to_colorref(255, 0, 255));
+        let patch = calibration(r#"<color red="1" green="0" blue="1" bits="1"/>"#).unwrap();
+        assert_eq!(patch.foreground, color::rgb_to_colorref(255, 0, 255));
+        assert_eq!(
+            calibration(r#"<color red="1" green="0" blue="1" bits="17"/>"#),
+            Err("unsupported bit depth 17".to_string())
+        );
+    }
+
+    #[test]
+    fn defaults_background_and_geometry() {
+        let patch = calibration(r#"<color red="16" green="32" blue="48"/>"#).unwrap();
+        assert_eq!(patch.foreground, color::rgb_to_colorref(16, 32, 48));
+        assert_eq!(patch.background, 0);
+        assert_eq!(patch.window, Window::FULL);
+    }
+
+    #[test]
+    fn colorspace_is_not_color() {
+        let patch = calibration(
+            r#"<colorspace red="1" green="2" blue="3"/><color red='9' green='8' blue='7'/>"#,
+        )
+        .unwrap();
+        assert_eq!(patch.foreground, color::rgb_to_colorref(9, 8, 7));
+        assert_eq!(
+            calibration(r#"<colorspace red="1" green="2" blue="3"/>"#),
+            Err("missing <color>".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_documents() {
+        assert_eq!(
+            parse_calibration(r#"<color red="1" green="2" blue="3"/>"#),
+            Err("missing <calibration>".to_string())
+        );
+        assert_eq!(
+            calibration(r#"<color red="1" green="2"/>"#),
+            Err("missing attribute `blue`".to_string())
+        );
+        assert_eq!(
+            calibration(r#"<color red="1" green="2" blue="x"/>"#),
+            Err("invalid number `x` for `blue`".to_string())
+        );
+        assert_eq!(
+            calibration(r#"<color red="1" green="2" blue="3"/><geometry x="0" y="0" cx="1"/>"#),
+            Err("missing attribute `cy`".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_attributes() {
+        assert_eq!(
+            element(r#"<a b="1 &amp; 2" c='&lt;&quot;&gt;'>"#, "a"),
+            Some(vec![
+                ("b".to_string(), "1 & 2".to_string()),
+                ("c".to_string(), "<\">".to_string()),
+            ])
+        );
+        assert_eq!(element("<ab/>", "a"), None);
+        assert_eq!(element("<a/>", "a"), Some(vec![]));
+    }
+}
@@ -0,0 +1,240 @@
+//! Time-of-day rules that show the overlay on a schedule, e.g. black on the
+//! signage monitor on weekday nights. Rules are checked against local wall
+//! clock time each time they are evaluated rather than by counting down to
+//! the next change, so daylight saving shifts don't move them: a rule from
+//! 22:00 starts at 22:00 local time on both sides of a change. On the night
+//! clocks skip an hour, a rule starting inside the gap starts when the gap
+//! ends.
+
+use std::mem::zeroed;
+use windows_sys::Win32::System::SystemInformation::GetLocalTime;
+
+const DAY_NAMES: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A moment in local time, as far as rules care.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalTime {
+    /// 0 is Sunday.
+    pub weekday: u8,
+    /// Minutes since midnight.
+    pub minute: u16,
+}
+
+impl LocalTime {
+    pub fn now() -> Self {
+        let time = unsafe {
+            let mut time = zeroed();
+            GetLocalTime(&mut time);
+            time
+        };
+        LocalTime {
+            weekday: (time.wDayOfWeek % 7) as u8,
+            minute: time.wHour * 60 + time.wMinute,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// Bit `n` set means the rule starts on weekday `n` (0 is Sunday).
+    pub days: u8,
+    /// Minutes since midnight. When `end` is not after `start` the rule runs
+    /// past midnight into the next day; equal times mean a full day.
+    pub start: u16,
+    pub end: u16,
+    /// Overlay color; black unless a color or profile is given.
+    pub color: Option<u32>,
+    /// Profile to show instead of `color`.
+    pub profile: Option<String>,
+    /// Monitors to cover; empty keeps the current selection.
+    pub monitors: Vec<u32>,
+}
+
+impl Rule {
+    pub const EVERY_DAY: u8 = 0x7F;
+
+    /// Whether the rule covers `now`. A rule that runs past midnight belongs
+    /// to the day it starts: a Friday 22:00-07:00 rule covers Saturday
+    /// morning but not Monday morning.
+    pub fn is_active(&self, now: LocalTime) -> bool {
+        let on = |weekday: u8| self.days & (1 << weekday) != 0;
+        let yesterday = (now.weekday + 6) % 7;
+        if self.start == self.end {
+            on(now.weekday)
+        } else if self.start < self.end {
+            on(now.weekday) && (self.start..self.end).contains(&now.minute)
+        } else {
+            (on(now.weekday) && now.minute >= self.start)
+                || (on(yesterday) && now.minute < self.end)
+        }
+    }
+}
+
+/// The rule in charge at `now`. Where rules overlap the one listed last
+/// wins, so specific exceptions go after general rules.
+pub fn active_rule(rules: &[Rule], now: LocalTime) -> Option<usize> {
+    rules.iter().rposition(|rule| rule.is_active(now))
+}
+
+/// `22:00` or `7:30`.
+pub fn parse_time(s: &str) -> Option<u16> {
+    let (h, m) = s.trim().split_once(':')?;
+    if m.len() != 2 {
+        return None;
+    }
+    let (h, m) = (h.parse::<u16>().ok()?, m.parse::<u16>().ok()?);
+    if h < 24 && m < 60 {
+        Some(h * 60 + m)
+    } else {
+        None
+    }
+}
+
+pub fn format_time(minute: u16) -> String {
+    let minute = minute % MINUTES_PER_DAY;
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Day bits for a day name (`mon`, `monday`, ...), `weekdays` or `weekends`.
+pub fn parse_days(s: &str) -> Option<u8> {
+    let s = s.trim().to_ascii_lowercase();
+    match s.as_str() {
+        "weekdays" => return Some(0b0111110),
+        "weekends" => return Some(0b1000001),
+        "daily" => return Some(Rule::EVERY_DAY),
+        _ => {}
+    }
+    // Any prefix of at least three letters, e.g. `tue`, `tues`
+    let index = DAY_NAMES
+        .iter()
+        .position(|day| s.len() >= 3 && day.starts_with(&s))?;
+    Some(1 << index)
+}
+
+/// Short day names for the set bits, Monday first.
+pub fn format_days(days: u8) -> Vec<&'static str> {
+    [1, 2, 3, 4, 5, 6, 0]
+        .into_iter()
+        .filter(|&i| days & (1 << i) != 0)
+        .map(|i| &DAY_NAMES[i][..3])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUNDAY: u8 = 0;
+    const MONDAY: u8 = 1;
+    const FRIDAY: u8 = 5;
+    const SATURDAY: u8 = 6;
+
+    fn at(weekday: u8, time: &str) -> LocalTime {
+        LocalTime {
+            weekday,
+            minute: parse_time(time).unwrap(),
+        }
+    }
+
+    fn rule(days: u8, start: &str, end: &str) -> Rule {
+        Rule {
+            days,
+            start: parse_time(start).unwrap(),
+            end: parse_time(end).unwrap(),
+            color: None,
+            profile: None,
+            monitors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn daytime_rules_end_at_the_end_time() {
+        let office = rule(parse_days("weekdays").unwrap(), "09:00", "17:30");
+        assert!(!office.is_active(at(MONDAY, "08:59")));
+        assert!(office.is_active(at(MONDAY, "09:00")));
+        assert!(office.is_active(at(MONDAY, "17:29")));
+        assert!(!office.is_active(at(MONDAY, "17:30")));
+        assert!(!office.is_active(at(SATURDAY, "12:00")));
+    }
+
+    #[test]
+    fn night_rules_run_past_midnight() {
+        let night = rule(1 << FRIDAY, "22:00", "07:00");
+        assert!(!night.is_active(at(FRIDAY, "21:59")));
+        assert!(night.is_active(at(FRIDAY, "22:00")));
+        assert!(night.is_active(at(FRIDAY, "23:59")));
+        // Saturday morning belongs to Friday's rule
+        assert!(night.is_active(at(SATURDAY, "00:00")));
+        assert!(night.is_active(at(SATURDAY, "06:59")));
+        assert!(!night.is_active(at(SATURDAY, "07:00")));
+        // Friday morning would belong to Thursday
+        assert!(!night.is_active(at(FRIDAY, "03:00")));
+        assert!(!night.is_active(at(SATURDAY, "22:00")));
+    }
+
+    #[test]
+    fn saturday_nights_run_into_sunday() {
+        let night = rule(1 << SATURDAY, "23:00", "01:00");
+        assert!(night.is_active(at(SUNDAY, "00:30")));
+        assert!(!night.is_active(at(MONDAY, "00:30")));
+    }
+
+    #[test]
+    fn equal_times_cover_the_whole_day() {
+        let sunday = rule(1 << SUNDAY, "00:00", "00:00");
+        assert!(sunday.is_active(at(SUNDAY, "00:00")));
+        assert!(sunday.is_active(at(SUNDAY, "23:59")));
+        assert!(!sunday.is_active(at(MONDAY, "00:00")));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = [
+            rule(Rule::EVERY_DAY, "20:00", "08:00"),
+            rule(1 << FRIDAY, "23:00", "02:00"),
+            rule(Rule::EVERY_DAY, "12:00", "13:00"),
+        ];
+        assert_eq!(active_rule(&rules, at(MONDAY, "21:00")), Some(0));
+        assert_eq!(active_rule(&rules, at(FRIDAY, "23:30")), Some(1));
+        assert_eq!(active_rule(&rules, at(SATURDAY, "01:00")), Some(1));
+        assert_eq!(active_rule(&rules, at(SATURDAY, "03:00")), Some(0));
+        assert_eq!(active_rule(&rules, at(SATURDAY, "12:00")), Some(2));
+        assert_eq!(active_rule(&rules, at(SATURDAY, "10:00")), None);
+        assert_eq!(active_rule(&[], at(SATURDAY, "10:00")), None);
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("7:30"), Some(450));
+        assert_eq!(parse_time(" 23:59 "), Some(1439));
+        for bad in ["24:00", "12:60", "12:5", "1230", "noon"] {
+            assert_eq!(parse_time(bad), None, "{}", bad);
+        }
+        assert_eq!(format_time(450), "07:30");
+        assert_eq!(format_time(MINUTES_PER_DAY), "00:00");
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(parse_days("Tue"), Some(1 << 2));
+        assert_eq!(parse_days("wednes"), Some(1 << 3));
+        assert_eq!(parse_days("weekends"), Some(0b1000001));
+        assert_eq!(parse_days("daily"), Some(Rule::EVERY_DAY));
+        assert_eq!(parse_days("su"), None);
+        assert_eq!(parse_days("sundays"), None);
+        assert_eq!(format_days(0b1000001), ["sat", "sun"]);
+        assert_eq!(
+            format_days(parse_days("weekdays").unwrap()),
+            ["mon", "tue", "wed", "thu", "fri"]
+        );
+    }
+}
@@ -0,0 +1,634 @@
+//! A small TOML reader covering the subset blanqr's config uses: top-level
+//! `[table]` and `[[array]]` headers, bare or quoted keys, strings, integers,
+//! floats, booleans, arrays and inline tables. Every key and value carries
+//! its line/column so config errors can point at the offending text.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Item>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub value: Value,
+    pub pos: Pos,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub name: String,
+    pub pos: Pos,
+}
+
+/// Key/value pairs in document order.
+pub type Table = Vec<(Key, Item)>;
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub pos: Pos,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.pos.line, self.pos.column, self.message)
+    }
+}
+
+pub fn parse(input: &str) -> Result<Table, Error> {
+    Parser::new(input).document()
+}
+
+/// Quotes a string as a TOML basic string.
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes `s` only if it is not a valid bare key.
+pub fn key(s: &str) -> String {
+    let bare = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        s.to_string()
+    } else {
+        quote(s)
+    }
+}
+
+/// Where the next key/value pair goes.
+enum Target {
+    Root,
+    Table(usize),
+    /// Last element of the array of tables at this root index.
+    ArrayTable(usize),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Parser {
+            chars: input.chars().collect(),
+            index: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn pos(&self) -> Pos {
+        Pos {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error<T>(&self, pos: Pos, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error {
+            pos,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.error(
+                self.pos(),
+                format!("expected `{}`, found `{}`", expected, c),
+            ),
+            None => self.error(
+                self.pos(),
+                format!("expected `{}`, found end of file", expected),
+            ),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Whitespace, comments and newlines (inside arrays and between lines).
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        self.skip_comment();
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some('\r') if self.peek_at(1) == Some('\n') => {
+                self.bump();
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.error(self.pos(), format!("unexpected `{}` after value", c)),
+        }
+    }
+
+    fn document(mut self) -> Result<Table, Error> {
+        let mut root: Table = Vec::new();
+        let mut target = Target::Root;
+
+        loop {
+            self.skip_blank();
+            let Some(c) = self.peek() else {
+                break;
+            };
+
+            if c == '[' {
+                target = self.header(&mut root)?;
+            } else {
+                let (key, item) = self.key_value()?;
+                let table = match target {
+                    Target::Root => &mut root,
+                    Target::Table(i) => match &mut root[i].1.value {
+                        Value::Table(t) => t,
+                        _ => unreachable!(),
+                    },
+                    Target::ArrayTable(i) => match &mut root[i].1.value {
+                        Value::Array(items) => match &mut items.last_mut().unwrap().value {
+                            Value::Table(t) => t,
+                            _ => unreachable!(),
+                        },
+                        _ => unreachable!(),
+                    },
+                };
+                insert(table, key, item)?;
+            }
+            self.end_of_line()?;
+        }
+
+        Ok(root)
+    }
+
+    fn header(&mut self, root: &mut Table) -> Result<Target, Error> {
+        let pos = self.pos();
+        self.expect('[')?;
+        let is_array = self.peek() == Some('[');
+        if is_array {
+            self.bump();
+        }
+        self.skip_whitespace();
+        let key = self.key()?;
+        self.skip_whitespace();
+        if self.peek() == Some('.') {
+            return self.error(self.pos(), "nested tables are not supported");
+        }
+        self.expect(']')?;
+        if is_array {
+            self.expect(']')?;
+        }
+
+        let existing = root.iter().position(|(k, _)| k.name == key.name);
+        let new_table = Item {
+            value: Value::Table(Vec::new()),
+            pos,
+        };
+
+        match (existing, is_array) {
+            (None, false) => {
+                root.push((key, new_table));
+                Ok(Target::Table(root.len() - 1))
+            }
+            (None, true) => {
+                let array = Item {
+                    value: Value::Array(vec![new_table]),
+                    pos,
+                };
+                root.push((key, array));
+                Ok(Target::ArrayTable(root.len() - 1))
+            }
+            (Some(i), true) => match &mut root[i].1.value {
+                Value::Array(items)
+                    if items
+                        .iter()
+                        .all(|item| matches!(item.value, Value::Table(_))) =>
+                {
+                    items.push(new_table);
+                    Ok(Target::ArrayTable(i))
+                }
+                _ => self.error(pos, format!("`{}` is not an array of tables", key.name)),
+            },
+            (Some(_), false) => self.error(pos, format!("table `{}` is defined twice", key.name)),
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(Key, Item), Error> {
+        let key = self.key()?;
+        self.skip_whitespace();
+        if self.peek() == Some('.') {
+            return self.error(self.pos(), "dotted keys are not supported");
+        }
+        self.expect('=')?;
+        self.skip_whitespace();
+        let item = self.value()?;
+        Ok((key, item))
+    }
+
+    fn key(&mut self) -> Result<Key, Error> {
+        let pos = self.pos();
+        let name = match self.peek() {
+            Some('"') => self.basic_string()?,
+            Some('\'') => self.literal_string()?,
+            _ => {
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        name.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                if name.is_empty() {
+                    return match self.peek() {
+                        Some('\n' | '\r') => self.error(pos, "expected a key, found end of line"),
+                        Some(c) => self.error(pos, format!("expected a key, found `{}`", c)),
+                        None => self.error(pos, "expected a key, found end of file"),
+                    };
+                }
+                name
+            }
+        };
+        Ok(Key { name, pos })
+    }
+
+    fn value(&mut self) -> Result<Item, Error> {
+        let pos = self.pos();
+        let value = match self.peek() {
+            Some('"') => Value::String(self.basic_string()?),
+            Some('\'') => Value::String(self.literal_string()?),
+            Some('[') => Value::Array(self.array()?),
+            Some('{') => Value::Table(self.inline_table()?),
+            Some('t') | Some('f') => Value::Boolean(self.boolean()?),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' => self.number()?,
+            Some('\n' | '\r') => return self.error(pos, "expected a value, found end of line"),
+            Some(c) => return self.error(pos, format!("expected a value, found `{}`", c)),
+            None => return self.error(pos, "expected a value, found end of file"),
+        };
+        Ok(Item { value, pos })
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        let start = self.pos();
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let pos = self.pos();
+            match self.bump() {
+                None | Some('\n') => return self.error(start, "unterminated string"),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.bump()).collect();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => return self.error(pos, "invalid unicode escape"),
+                        }
+                    }
+                    _ => return self.error(pos, "invalid escape sequence"),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        let start = self.pos();
+        self.expect('\'')?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return self.error(start, "unterminated string"),
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<Item>, Error> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(items);
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => {}
+                _ => return self.error(self.pos(), "expected `,` or `]` in array"),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Table, Error> {
+        self.expect('{')?;
+        let mut table = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(table);
+        }
+        loop {
+            self.skip_whitespace();
+            let (key, item) = self.key_value()?;
+            insert(&mut table, key, item)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some('}') => {
+                    self.bump();
+                    return Ok(table);
+                }
+                _ => return self.error(self.pos(), "expected `,` or `}` in inline table"),
+            }
+        }
+    }
+
+    fn boolean(&mut self) -> Result<bool, Error> {
+        let pos = self.pos();
+        let word = self.word();
+        match word.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => self.error(pos, format!("expected a value, found `{}`", word)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let pos = self.pos();
+        let word = self.word();
+        let digits = word.replace('_', "");
+        let is_float = digits.contains(['.', 'e', 'E']);
+        let parsed = if is_float {
+            digits.parse::<f64>().ok().map(Value::Float)
+        } else {
+            digits.parse::<i64>().ok().map(Value::Integer)
+        };
+        match parsed {
+            Some(value) => Ok(value),
+            None => self.error(pos, format!("invalid number `{}`", word)),
+        }
+    }
+
+    /// Characters up to the next delimiter, for numbers and booleans.
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '+' | '-') {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+}
+
+fn insert(table: &mut Table, key: Key, item: Item) -> Result<(), Error> {
+    if table.iter().any(|(k, _)| k.name == key.name) {
+        return Err(Error {
+            message: format!("duplicate key `{}`", key.name),
+            pos: key.pos,
+        });
+    }
+    table.push((key, item));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, column: usize) -> Pos {
+        Pos { line, column }
+    }
+
+    fn error(input: &str) -> Error {
+        parse(input).unwrap_err()
+    }
+
+    #[test]
+    fn parses_values_with_positions() {
+        let doc = parse(
+            "top = 'literal'\n\
+             [general]\n\
+             name = \"a \\\"b\\\" \\u00E9\"\n\
+             count = -1_000\n\
+             ratio = 2.5\n\
+             on = true\n\
+             list = [1, 2,\n  3, # comment\n]\n\
+             point = { x = 1, y = 2 }\n",
+        )
+        .unwrap();
+
+        assert_eq!(doc[0].0.name, "top");
+        assert_eq!(doc[0].1.value, Value::String("literal".into()));
+        assert_eq!(doc[1].0.pos, at(2, 2));
+        let Value::Table(general) = &doc[1].1.value else {
+            panic!("expected a table");
+        };
+        let values: Vec<(&str, &Value, Pos)> = general
+            .iter()
+            .map(|(k, item)| (k.name.as_str(), &item.value, item.pos))
+            .collect();
+        assert_eq!(values[0].1, &Value::String("a \"b\" \u{e9}".into()));
+        assert_eq!(values[0].2, at(3, 8));
+        assert_eq!(values[1].1, &Value::Integer(-1000));
+        assert_eq!(values[2].1, &Value::Float(2.5));
+        assert_eq!(values[3].1, &Value::Boolean(true));
+        let Value::Array(list) = values[4].1 else {
+            panic!("expected an array");
+        };
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[2].pos, at(8, 3));
+        let Value::Table(point) = values[5].1 else {
+            panic!("expected an inline table");
+        };
+        assert_eq!(point[1].0.name, "y");
+        assert_eq!(point[1].1.value, Value::Integer(2));
+    }
+
+    #[test]
+    fn arrays_of_tables_collect_in_order() {
+        let doc = parse("[[presets]]\nname = \"a\"\n\n[[presets]]\nname = \"b\"\n").unwrap();
+        assert_eq!(doc.len(), 1);
+        let Value::Array(items) = &doc[0].1.value else {
+            panic!("expected an array");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].pos, at(4, 1));
+        assert_eq!(
+            items[1].value,
+            Value::Table(vec![(
+                Key {
+                    name: "name".into(),
+                    pos: at(5, 1),
+                },
+                Item {
+                    value: Value::String("b".into()),
+                    pos: at(5, 8),
+                },
+            )])
+        );
+    }
+
+    #[test]
+    fn accepts_crlf_line_endings() {
+        let doc = parse("[a]\r\nx = 1\r\n").unwrap();
+        assert_eq!(doc[0].0.name, "a");
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_text() {
+        let cases = [
+            ("x = 1\nx = 2\n", at(2, 1), "duplicate key `x`"),
+            ("[a]\n[a]\n", at(2, 1), "table `a` is defined twice"),
+            ("a = 1\n[[a]]\n", at(2, 1), "`a` is not an array of tables"),
+            ("[a.b]\n", at(1, 3), "nested tables are not supported"),
+            ("a.b = 1\n", at(1, 2), "dotted keys are not supported"),
+            ("x = \"open\n", at(1, 5), "unterminated string"),
+            ("x = \"\\q\"\n", at(1, 6), "invalid escape sequence"),
+            ("x = 1 2\n", at(1, 7), "unexpected `2` after value"),
+            ("x =\n", at(1, 4), "expected a value, found end of line"),
+            ("x = yes\n", at(1, 5), "expected a value, found `y`"),
+            ("x = 12abc\n", at(1, 5), "invalid number `12abc`"),
+            ("x = [1 2]\n", at(1, 8), "expected `,` or `]` in array"),
+            ("= 1\n", at(1, 1), "expected a key, found `=`"),
+        ];
+        for (input, pos, message) in cases {
+            let err = error(input);
+            assert_eq!(
+                (err.pos, err.message.as_str()),
+                (pos, message),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn error_display_leads_with_line_and_column() {
+        assert_eq!(
+            error("\n\nkey = ?\n").to_string(),
+            "3:7: expected a value, found `?`"
+        );
+    }
+
+    #[test]
+    fn quote_and_key_round_trip() {
+        let s = "tab\there \"quoted\" back\\slash\nline\u{1}";
+        let doc = parse(&format!("{} = {}\n", key("Ctrl+B, W"), quote(s))).unwrap();
+        assert_eq!(doc[0].0.name, "Ctrl+B, W");
+        assert_eq!(doc[0].1.value, Value::String(s.into()));
+        assert_eq!(key("bare_key-1"), "bare_key-1");
+        assert_eq!(key(""), "\"\"");
+    }
+}
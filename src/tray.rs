@@ -1,18 +1,30 @@
+use crate::color;
+use crate::icon::{self, Badge};
+use crate::patch::PatchSize;
 use crate::startup;
+use std::cell::Cell;
 use std::mem::zeroed;
 use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 use std::sync::Mutex;
+use std::time::Duration;
 use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows_sys::Win32::Graphics::Gdi::{
+    CreateBitmap, CreateDIBSection, DeleteObject, GetDC, ReleaseDC, BITMAPINFO, BITMAPINFOHEADER,
+    BI_RGB, DIB_RGB_COLORS, HBITMAP,
+};
 use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows_sys::Win32::UI::Shell::{
-    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW,
+    Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO, NIM_ADD, NIM_DELETE,
+    NIM_MODIFY, NOTIFYICONDATAW,
 };
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
-    GetCursorPos, LoadIconW, PostQuitMessage, RegisterClassW, SetForegroundWindow, TrackPopupMenu,
-    CS_HREDRAW, CS_VREDRAW, IDI_APPLICATION, MF_CHECKED, MF_GRAYED, MF_SEPARATOR, MF_STRING,
-    TPM_BOTTOMALIGN, TPM_LEFTALIGN, WM_COMMAND, WM_DESTROY, WM_LBUTTONDBLCLK, WM_RBUTTONUP,
-    WM_USER, WNDCLASSW, WS_OVERLAPPEDWINDOW,
+    AppendMenuW, CreateIconIndirect, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyIcon,
+    DestroyMenu, DestroyWindow, GetCursorPos, GetSystemMetrics, LoadIconW, PostQuitMessage,
+    RegisterClassW, SetForegroundWindow, SetMenuItemInfoW, TrackPopupMenu, CS_HREDRAW, CS_VREDRAW,
+    HICON, HMENU, ICONINFO, IDI_APPLICATION, MENUITEMINFOW, MF_CHECKED, MF_GRAYED, MF_POPUP,
+    MF_SEPARATOR, MF_STRING, MIIM_BITMAP, SM_CXSMICON, TPM_BOTTOMALIGN, TPM_LEFTALIGN, WM_COMMAND,
+    WM_DESTROY, WM_LBUTTONDBLCLK, WM_RBUTTONUP, WM_USER, WNDCLASSW, WS_OVERLAPPEDWINDOW,
 };
 
 const WM_TRAYICON: u32 = WM_USER + 1;
@@ -20,7 +32,35 @@ const WM_TRAYICON: u32 = WM_USER + 1;
 pub const MENU_SELECT_COLOR: u16 = 101;
 pub const MENU_CONFIGURE_HOTKEY: u16 = 102;
 pub const MENU_STARTUP: u16 = 103;
+pub const MENU_SAVE_PROFILE: u16 = 104;
+pub const MENU_GENERATOR: u16 = 105;
+pub const MENU_WINDOW_FULL: u16 = 106;
+pub const MENU_LOAD_PATCHES: u16 = 107;
+pub const MENU_AUTO_HIDE_OFF: u16 = 108;
+pub const MENU_COUNTDOWN: u16 = 109;
+pub const MENU_DIMMER: u16 = 110;
+pub const MENU_IMPORT_PALETTE: u16 = 111;
+pub const MENU_EXPORT_PALETTE: u16 = 112;
 pub const MENU_EXIT: u16 = 199;
+/// Profile `i` in the Profiles submenu has id `MENU_PROFILE_BASE + i`.
+pub const MENU_PROFILE_BASE: u16 = 1000;
+const MAX_PROFILES: usize = 1000;
+/// Sequence `i` in the Sequences submenu has id `MENU_SEQUENCE_BASE + i`.
+pub const MENU_SEQUENCE_BASE: u16 = 3000;
+const MAX_SEQUENCES: usize = 1000;
+/// Choice `i` in the Auto-Hide submenu has id `MENU_AUTO_HIDE_BASE + i`.
+pub const MENU_AUTO_HIDE_BASE: u16 = 2100;
+/// Auto-hide choices in minutes.
+const AUTO_HIDE_MINUTES: &[u32] = &[5, 15, 30, 60];
+/// Area preset `i` in the Window submenu has id `MENU_WINDOW_BASE + i`.
+pub const MENU_WINDOW_BASE: u16 = 2000;
+/// Preset `i` in the Colors submenu has id `MENU_PRESET_BASE + i`.
+pub const MENU_PRESET_BASE: u16 = 4000;
+/// Presets that fit in the Colors submenu before `MENU_RECENT_BASE`.
+pub const MAX_PRESETS: usize = 500;
+/// Recent color `i` in the Colors submenu has id `MENU_RECENT_BASE + i`.
+pub const MENU_RECENT_BASE: u16 = 4500;
+const MAX_RECENT: usize = 100;
 
 static CLASS_NAME: &[u16] = &[
     'B' as u16, 'l' as u16, 'a' as u16, 'n' as u16, 'q' as u16, 'r' as u16, 'T' as u16, 'r' as u16,
@@ -29,18 +69,57 @@ static CLASS_NAME: &[u16] = &[
 
 static mut TRAY_CALLBACK: Option<Box<dyn Fn(TrayEvent)>> = None;
 static HOTKEY_DISPLAY: Mutex<String> = Mutex::new(String::new());
+/// Profile names and whether each is the active one.
+static PROFILES: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+/// Sequence names and whether each is playing.
+static SEQUENCES: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+/// Preset names and colors.
+static PRESETS: Mutex<Vec<(String, u32)>> = Mutex::new(Vec::new());
+/// Recently picked colors, newest first.
+static RECENT_COLORS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+/// Overlay color, checked wherever it appears in the Colors submenu.
+static ACTIVE_COLOR: AtomicU32 = AtomicU32::new(0);
+static GENERATOR_ACTIVE: AtomicBool = AtomicBool::new(false);
+static DIMMER_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Window area in percent, 0 for full screen, `WINDOW_CUSTOM` for any size
+/// the submenu doesn't list.
+static WINDOW_AREA: AtomicU8 = AtomicU8::new(0);
+const WINDOW_CUSTOM: u8 = u8::MAX;
+/// Auto-hide delay in whole minutes, 0 when off, `u32::MAX` for any other
+/// delay.
+static AUTO_HIDE: AtomicU32 = AtomicU32::new(0);
+static COUNTDOWN: AtomicBool = AtomicBool::new(false);
 
 pub enum TrayEvent {
     DoubleClick,
     SelectColor,
+    /// A preset or recent color from the Colors submenu.
+    SetColor(u32),
+    ImportPalette,
+    ExportPalette,
+    LoadPatchSet,
     ConfigureHotkey,
     ToggleStartup,
+    SelectProfile(usize),
+    SaveProfile,
+    SelectSequence(usize),
+    ToggleGenerator,
+    ToggleDimmer,
+    /// Window area in percent, or `None` for full screen.
+    SelectWindow(Option<u8>),
+    /// Auto-hide delay, or `None` to turn it off.
+    SetAutoHide(Option<Duration>),
+    ToggleCountdown,
     Exit,
 }
 
 pub struct TrayIcon {
     hwnd: HWND,
     nid: NOTIFYICONDATAW,
+    /// Generated icon shown now, destroyed when replaced.
+    icon: Cell<HICON>,
+    /// What `icon` was drawn for.
+    shown: Cell<(u32, Badge)>,
 }
 
 impl TrayIcon {
@@ -93,7 +172,13 @@ impl TrayIcon {
                 return None;
             }
 
-            let icon = LoadIconW(null_mut(), IDI_APPLICATION);
+            let shown = (0x00000000, Badge::None);
+            let icon = create_icon(shown.0, shown.1);
+            let icon_or_default = if icon.is_null() {
+                LoadIconW(null_mut(), IDI_APPLICATION)
+            } else {
+                icon
+            };
 
             let mut nid: NOTIFYICONDATAW = zeroed();
             nid.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
@@ -101,17 +186,56 @@ impl TrayIcon {
             nid.uID = 1;
             nid.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
             nid.uCallbackMessage = WM_TRAYICON;
-            nid.hIcon = icon;
+            nid.hIcon = icon_or_default;
 
-            let tip = "Blanqr";
-            let tip_wide: Vec<u16> = tip.encode_utf16().chain(std::iter::once(0)).collect();
-            nid.szTip[..tip_wide.len().min(128)]
-                .copy_from_slice(&tip_wide[..tip_wide.len().min(128)]);
+            copy_wide(&mut nid.szTip, "Blanqr");
 
             Shell_NotifyIconW(NIM_ADD, &nid);
 
-            Some(TrayIcon { hwnd, nid })
+            Some(TrayIcon {
+                hwnd,
+                nid,
+                icon: Cell::new(icon),
+                shown: Cell::new(shown),
+            })
+        }
+    }
+}
+
+impl TrayIcon {
+    /// Shows a balloon notification next to the tray icon.
+    pub fn notify(&self, title: &str, text: &str) {
+        let mut nid = self.nid;
+        nid.uFlags = NIF_INFO;
+        nid.dwInfoFlags = NIIF_INFO;
+        copy_wide(&mut nid.szInfoTitle, title);
+        copy_wide(&mut nid.szInfo, text);
+        unsafe {
+            Shell_NotifyIconW(NIM_MODIFY, &nid);
+        }
+    }
+
+    /// Redraws the icon as a swatch of `color` with `badge`, if either
+    /// changed.
+    pub fn set_state(&self, color: u32, badge: Badge) {
+        if self.shown.get() == (color, badge) {
+            return;
+        }
+        let icon = unsafe { create_icon(color, badge) };
+        if icon.is_null() {
+            return;
+        }
+        let mut nid = self.nid;
+        nid.uFlags = NIF_ICON;
+        nid.hIcon = icon;
+        unsafe {
+            Shell_NotifyIconW(NIM_MODIFY, &nid);
+            let old = self.icon.replace(icon);
+            if !old.is_null() {
+                DestroyIcon(old);
+            }
         }
+        self.shown.set((color, badge));
     }
 }
 
@@ -120,6 +244,10 @@ impl Drop for TrayIcon {
         unsafe {
             Shell_NotifyIconW(NIM_DELETE, &self.nid);
             DestroyWindow(self.hwnd);
+            let icon = self.icon.get();
+            if !icon.is_null() {
+                DestroyIcon(icon);
+            }
         }
     }
 }
@@ -130,6 +258,155 @@ pub fn update_hotkey_display(display: &str) {
     }
 }
 
+pub fn update_profiles(names: &[String], active: Option<&str>) {
+    if let Ok(mut profiles) = PROFILES.lock() {
+        *profiles = names
+            .iter()
+            .take(MAX_PROFILES)
+            .map(|name| (name.clone(), Some(name.as_str()) == active))
+            .collect();
+    }
+}
+
+pub fn update_sequences(names: &[String], playing: Option<&str>) {
+    if let Ok(mut sequences) = SEQUENCES.lock() {
+        *sequences = names
+            .iter()
+            .take(MAX_SEQUENCES)
+            .map(|name| (name.clone(), Some(name.as_str()) == playing))
+            .collect();
+    }
+}
+
+/// Fills the Colors submenu and checks the entries matching `active`.
+pub fn update_colors(presets: &[(String, u32)], recent: &[u32], active: u32) {
+    if let Ok(mut list) = PRESETS.lock() {
+        *list = presets.iter().take(MAX_PRESETS).cloned().collect();
+    }
+    if let Ok(mut list) = RECENT_COLORS.lock() {
+        *list = recent.iter().take(MAX_RECENT).copied().collect();
+    }
+    ACTIVE_COLOR.store(active, Ordering::Relaxed);
+}
+
+/// Checks the current choices in the Auto-Hide submenu.
+pub fn update_auto_hide(auto_hide: Option<Duration>, countdown: bool) {
+    let minutes = match auto_hide {
+        None => 0,
+        Some(d) if d.as_secs() % 60 == 0 && d.subsec_nanos() == 0 => {
+            u32::try_from(d.as_secs() / 60).unwrap_or(u32::MAX)
+        }
+        Some(_) => u32::MAX,
+    };
+    AUTO_HIDE.store(minutes, Ordering::Relaxed);
+    COUNTDOWN.store(countdown, Ordering::Relaxed);
+}
+
+/// Checks the Pattern Generator item while a session is running.
+pub fn update_generator(active: bool) {
+    GENERATOR_ACTIVE.store(active, Ordering::Relaxed);
+}
+
+/// Checks the Dimmer item while the dimmer is on.
+pub fn update_dimmer(active: bool) {
+    DIMMER_ACTIVE.store(active, Ordering::Relaxed);
+}
+
+/// Checks the matching entry in the Window submenu.
+pub fn update_window(size: Option<PatchSize>) {
+    let area = match size {
+        None => 0,
+        Some(PatchSize::Area(percent)) if PatchSize::AREAS.contains(&percent) => percent,
+        Some(_) => WINDOW_CUSTOM,
+    };
+    WINDOW_AREA.store(area, Ordering::Relaxed);
+}
+
+/// Side of the generated tray icon and menu swatches.
+unsafe fn small_icon_size() -> u32 {
+    GetSystemMetrics(SM_CXSMICON).max(16) as u32
+}
+
+/// Builds a 32bpp top-down bitmap from pixels in `icon::render` layout.
+/// Returns null on failure.
+unsafe fn create_bitmap(size: u32, pixels: &[u32]) -> HBITMAP {
+    let mut info: BITMAPINFO = zeroed();
+    info.bmiHeader.biSize = std::mem::size_of::<BITMAPINFOHEADER>() as u32;
+    info.bmiHeader.biWidth = size as i32;
+    // Negative height: rows top to bottom, as rendered
+    info.bmiHeader.biHeight = -(size as i32);
+    info.bmiHeader.biPlanes = 1;
+    info.bmiHeader.biBitCount = 32;
+    info.bmiHeader.biCompression = BI_RGB;
+
+    let mut bits = null_mut();
+    let hdc = GetDC(null_mut());
+    let color_bitmap = CreateDIBSection(hdc, &info, DIB_RGB_COLORS, &mut bits, null_mut(), 0);
+    ReleaseDC(null_mut(), hdc);
+    if color_bitmap.is_null() || bits.is_null() {
+        return null_mut();
+    }
+    std::ptr::copy_nonoverlapping(pixels.as_ptr(), bits as *mut u32, pixels.len());
+    color_bitmap
+}
+
+/// Builds an `HICON` at the small icon size from `icon::render`. Returns
+/// null on failure.
+unsafe fn create_icon(color: u32, badge: Badge) -> HICON {
+    let size = small_icon_size();
+    let color_bitmap = create_bitmap(size, &icon::render(size, color, badge));
+    if color_bitmap.is_null() {
+        return null_mut();
+    }
+
+    // The alpha channel decides transparency; the mask only has to exist.
+    // Its rows are padded to 16 bits.
+    let mask_bits = vec![0u8; size.div_ceil(16) as usize * 2 * size as usize];
+    let mask = CreateBitmap(size as i32, size as i32, 1, 1, mask_bits.as_ptr() as _);
+
+    let info = ICONINFO {
+        fIcon: 1,
+        xHotspot: 0,
+        yHotspot: 0,
+        hbmMask: mask,
+        hbmColor: color_bitmap,
+    };
+    let icon = CreateIconIndirect(&info);
+    DeleteObject(color_bitmap as _);
+    DeleteObject(mask as _);
+    icon
+}
+
+/// Appends a color item showing a swatch of `color` next to `label`. The
+/// swatch is pushed onto `bitmaps`, to be deleted with the menu.
+unsafe fn append_color_item(
+    menu: HMENU,
+    id: usize,
+    label: &[u16],
+    color: u32,
+    checked: bool,
+    bitmaps: &mut Vec<HBITMAP>,
+) {
+    let flags = if checked {
+        MF_STRING | MF_CHECKED
+    } else {
+        MF_STRING
+    };
+    AppendMenuW(menu, flags, id, label.as_ptr());
+
+    let size = small_icon_size();
+    let bitmap = create_bitmap(size, &icon::render(size, color, Badge::None));
+    if bitmap.is_null() {
+        return;
+    }
+    let mut info: MENUITEMINFOW = zeroed();
+    info.cbSize = std::mem::size_of::<MENUITEMINFOW>() as u32;
+    info.fMask = MIIM_BITMAP;
+    info.hbmpItem = bitmap;
+    SetMenuItemInfoW(menu, id as u32, 0, &info);
+    bitmaps.push(bitmap);
+}
+
 fn show_context_menu(hwnd: HWND) {
     unsafe {
         let menu = CreatePopupMenu();
@@ -147,6 +424,72 @@ fn show_context_menu(hwnd: HWND) {
 
         AppendMenuW(menu, MF_SEPARATOR, 0, null_mut());
 
+        // Presets and recent colors, with swatches
+        let colors_menu = CreatePopupMenu();
+        let presets = PRESETS.lock().map(|p| p.clone()).unwrap_or_default();
+        let recent = RECENT_COLORS.lock().map(|r| r.clone()).unwrap_or_default();
+        let active = ACTIVE_COLOR.load(Ordering::Relaxed);
+        let mut swatches = Vec::new();
+        let mut color_labels = Vec::new();
+        for (i, (name, color)) in presets.iter().enumerate() {
+            let label = wide_str(name);
+            append_color_item(
+                colors_menu,
+                (MENU_PRESET_BASE as usize) + i,
+                &label,
+                *color,
+                *color == active,
+                &mut swatches,
+            );
+            color_labels.push(label);
+        }
+        if !recent.is_empty() {
+            if !presets.is_empty() {
+                AppendMenuW(colors_menu, MF_SEPARATOR, 0, null_mut());
+            }
+            let recent_text = wide_str("Recent");
+            AppendMenuW(colors_menu, MF_STRING | MF_GRAYED, 0, recent_text.as_ptr());
+            color_labels.push(recent_text);
+            for (i, &color) in recent.iter().enumerate() {
+                let label = wide_str(&color::to_hex(color));
+                append_color_item(
+                    colors_menu,
+                    (MENU_RECENT_BASE as usize) + i,
+                    &label,
+                    color,
+                    color == active,
+                    &mut swatches,
+                );
+                color_labels.push(label);
+            }
+        }
+        if presets.is_empty() && recent.is_empty() {
+            let none = wide_str("(none)");
+            AppendMenuW(colors_menu, MF_STRING | MF_GRAYED, 0, none.as_ptr());
+        }
+        AppendMenuW(colors_menu, MF_SEPARATOR, 0, null_mut());
+        let import_text = wide_str("Import Palette...");
+        AppendMenuW(
+            colors_menu,
+            MF_STRING,
+            MENU_IMPORT_PALETTE as usize,
+            import_text.as_ptr(),
+        );
+        let export_text = wide_str("Export Presets...");
+        AppendMenuW(
+            colors_menu,
+            MF_STRING,
+            MENU_EXPORT_PALETTE as usize,
+            export_text.as_ptr(),
+        );
+        let colors_text = wide_str("Colors");
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            colors_menu as usize,
+            colors_text.as_ptr(),
+        );
+
         // Color selection
         let select_color = wide_str("Select Color...");
         AppendMenuW(
@@ -156,6 +499,15 @@ fn show_context_menu(hwnd: HWND) {
             select_color.as_ptr(),
         );
 
+        // Patch set to step through
+        let load_patches = wide_str("Load Patch Set...");
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            MENU_LOAD_PATCHES as usize,
+            load_patches.as_ptr(),
+        );
+
         // Configure hotkey
         let configure_hotkey = wide_str("Hotkey...");
         AppendMenuW(
@@ -165,6 +517,178 @@ fn show_context_menu(hwnd: HWND) {
             configure_hotkey.as_ptr(),
         );
 
+        // Profiles
+        let profiles_menu = CreatePopupMenu();
+        let profiles = PROFILES.lock().map(|p| p.clone()).unwrap_or_default();
+        let mut labels = Vec::new();
+        for (i, (name, active)) in profiles.iter().enumerate() {
+            let label = wide_str(name);
+            let flags = if *active {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(
+                profiles_menu,
+                flags,
+                (MENU_PROFILE_BASE as usize) + i,
+                label.as_ptr(),
+            );
+            labels.push(label);
+        }
+        if profiles.is_empty() {
+            let none = wide_str("(none)");
+            AppendMenuW(profiles_menu, MF_STRING | MF_GRAYED, 0, none.as_ptr());
+        }
+        AppendMenuW(profiles_menu, MF_SEPARATOR, 0, null_mut());
+        let save_profile = wide_str("Save Current as Profile...");
+        AppendMenuW(
+            profiles_menu,
+            MF_STRING,
+            MENU_SAVE_PROFILE as usize,
+            save_profile.as_ptr(),
+        );
+        let profiles_text = wide_str("Profiles");
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            profiles_menu as usize,
+            profiles_text.as_ptr(),
+        );
+
+        // Sequences; picking the one that is playing stops it
+        let sequences_menu = CreatePopupMenu();
+        let sequences = SEQUENCES.lock().map(|s| s.clone()).unwrap_or_default();
+        let mut sequence_labels = Vec::new();
+        for (i, (name, playing)) in sequences.iter().enumerate() {
+            let label = wide_str(name);
+            let flags = if *playing {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            };
+            AppendMenuW(
+                sequences_menu,
+                flags,
+                (MENU_SEQUENCE_BASE as usize) + i,
+                label.as_ptr(),
+            );
+            sequence_labels.push(label);
+        }
+        if sequences.is_empty() {
+            let none = wide_str("(none)");
+            AppendMenuW(sequences_menu, MF_STRING | MF_GRAYED, 0, none.as_ptr());
+        }
+        let sequences_text = wide_str("Sequences");
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            sequences_menu as usize,
+            sequences_text.as_ptr(),
+        );
+
+        // Window mode
+        let window_menu = CreatePopupMenu();
+        let area = WINDOW_AREA.load(Ordering::Relaxed);
+        let checked = |on: bool| {
+            if on {
+                MF_STRING | MF_CHECKED
+            } else {
+                MF_STRING
+            }
+        };
+        let full_text = wide_str("Full Screen");
+        AppendMenuW(
+            window_menu,
+            checked(area == 0),
+            MENU_WINDOW_FULL as usize,
+            full_text.as_ptr(),
+        );
+        AppendMenuW(window_menu, MF_SEPARATOR, 0, null_mut());
+        let mut window_labels = Vec::new();
+        for (i, &percent) in PatchSize::AREAS.iter().enumerate() {
+            let label = wide_str(&format!("{}% Window", percent));
+            AppendMenuW(
+                window_menu,
+                checked(area == percent),
+                (MENU_WINDOW_BASE as usize) + i,
+                label.as_ptr(),
+            );
+            window_labels.push(label);
+        }
+        let window_text = wide_str("Window");
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            window_menu as usize,
+            window_text.as_ptr(),
+        );
+
+        // Auto-hide
+        let auto_hide_menu = CreatePopupMenu();
+        let minutes = AUTO_HIDE.load(Ordering::Relaxed);
+        let off_text = wide_str("Off");
+        AppendMenuW(
+            auto_hide_menu,
+            checked(minutes == 0),
+            MENU_AUTO_HIDE_OFF as usize,
+            off_text.as_ptr(),
+        );
+        let mut auto_hide_labels = Vec::new();
+        for (i, &choice) in AUTO_HIDE_MINUTES.iter().enumerate() {
+            let label = wide_str(&format!("After {} Minutes", choice));
+            AppendMenuW(
+                auto_hide_menu,
+                checked(minutes == choice),
+                (MENU_AUTO_HIDE_BASE as usize) + i,
+                label.as_ptr(),
+            );
+            auto_hide_labels.push(label);
+        }
+        AppendMenuW(auto_hide_menu, MF_SEPARATOR, 0, null_mut());
+        let countdown_text = wide_str("Show Countdown");
+        AppendMenuW(
+            auto_hide_menu,
+            checked(COUNTDOWN.load(Ordering::Relaxed)),
+            MENU_COUNTDOWN as usize,
+            countdown_text.as_ptr(),
+        );
+        let auto_hide_text = wide_str("Auto-Hide");
+        AppendMenuW(
+            menu,
+            MF_STRING | MF_POPUP,
+            auto_hide_menu as usize,
+            auto_hide_text.as_ptr(),
+        );
+
+        // Pattern generator session
+        let generator_text = wide_str("Pattern Generator");
+        let generator_flags = if GENERATOR_ACTIVE.load(Ordering::Relaxed) {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        AppendMenuW(
+            menu,
+            generator_flags,
+            MENU_GENERATOR as usize,
+            generator_text.as_ptr(),
+        );
+
+        // Click-through dimmer
+        let dimmer_text = wide_str("Dimmer");
+        let dimmer_flags = if DIMMER_ACTIVE.load(Ordering::Relaxed) {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        AppendMenuW(
+            menu,
+            dimmer_flags,
+            MENU_DIMMER as usize,
+            dimmer_text.as_ptr(),
+        );
+
         AppendMenuW(menu, MF_SEPARATOR, 0, null_mut());
 
         // Run at startup
@@ -201,7 +725,21 @@ fn show_context_menu(hwnd: HWND) {
             null_mut(),
         );
         DestroyMenu(menu);
+        for bitmap in swatches {
+            DeleteObject(bitmap as _);
+        }
+    }
+}
+
+/// Copies `s` into a fixed-size, nul-terminated UTF-16 buffer, truncating.
+fn copy_wide(dst: &mut [u16], s: &str) {
+    let len = dst.len() - 1;
+    let mut n = 0;
+    for (slot, c) in dst[..len].iter_mut().zip(s.encode_utf16()) {
+        *slot = c;
+        n += 1;
     }
+    dst[n] = 0;
 }
 
 fn wide_str(s: &str) -> Vec<u16> {
@@ -231,9 +769,67 @@ unsafe extern "system" fn tray_window_proc(
             if let Some(ref cb) = TRAY_CALLBACK {
                 match menu_id {
                     MENU_SELECT_COLOR => cb(TrayEvent::SelectColor),
+                    MENU_LOAD_PATCHES => cb(TrayEvent::LoadPatchSet),
                     MENU_CONFIGURE_HOTKEY => cb(TrayEvent::ConfigureHotkey),
                     MENU_STARTUP => cb(TrayEvent::ToggleStartup),
+                    MENU_SAVE_PROFILE => cb(TrayEvent::SaveProfile),
+                    MENU_GENERATOR => cb(TrayEvent::ToggleGenerator),
+                    MENU_DIMMER => cb(TrayEvent::ToggleDimmer),
+                    MENU_IMPORT_PALETTE => cb(TrayEvent::ImportPalette),
+                    MENU_EXPORT_PALETTE => cb(TrayEvent::ExportPalette),
+                    MENU_WINDOW_FULL => cb(TrayEvent::SelectWindow(None)),
+                    MENU_AUTO_HIDE_OFF => cb(TrayEvent::SetAutoHide(None)),
+                    MENU_COUNTDOWN => cb(TrayEvent::ToggleCountdown),
                     MENU_EXIT => cb(TrayEvent::Exit),
+                    id if id >= MENU_PROFILE_BASE
+                        && ((id - MENU_PROFILE_BASE) as usize) < MAX_PROFILES =>
+                    {
+                        cb(TrayEvent::SelectProfile((id - MENU_PROFILE_BASE) as usize))
+                    }
+                    id if id >= MENU_SEQUENCE_BASE
+                        && ((id - MENU_SEQUENCE_BASE) as usize) < MAX_SEQUENCES =>
+                    {
+                        cb(TrayEvent::SelectSequence(
+                            (id - MENU_SEQUENCE_BASE) as usize,
+                        ))
+                    }
+                    id if id >= MENU_AUTO_HIDE_BASE
+                        && ((id - MENU_AUTO_HIDE_BASE) as usize) < AUTO_HIDE_MINUTES.len() =>
+                    {
+                        let minutes = AUTO_HIDE_MINUTES[(id - MENU_AUTO_HIDE_BASE) as usize];
+                        let delay = Duration::from_secs(minutes as u64 * 60);
+                        cb(TrayEvent::SetAutoHide(Some(delay)))
+                    }
+                    id if id >= MENU_PRESET_BASE
+                        && ((id - MENU_PRESET_BASE) as usize) < MAX_PRESETS =>
+                    {
+                        let index = (id - MENU_PRESET_BASE) as usize;
+                        let color = PRESETS
+                            .lock()
+                            .ok()
+                            .and_then(|p| p.get(index).map(|&(_, c)| c));
+                        if let Some(color) = color {
+                            cb(TrayEvent::SetColor(color))
+                        }
+                    }
+                    id if id >= MENU_RECENT_BASE
+                        && ((id - MENU_RECENT_BASE) as usize) < MAX_RECENT =>
+                    {
+                        let index = (id - MENU_RECENT_BASE) as usize;
+                        let color = RECENT_COLORS
+                            .lock()
+                            .ok()
+                            .and_then(|r| r.get(index).copied());
+                        if let Some(color) = color {
+                            cb(TrayEvent::SetColor(color))
+                        }
+                    }
+                    id if id >= MENU_WINDOW_BASE
+                        && ((id - MENU_WINDOW_BASE) as usize) < PatchSize::AREAS.len() =>
+                    {
+                        let percent = PatchSize::AREAS[(id - MENU_WINDOW_BASE) as usize];
+                        cb(TrayEvent::SelectWindow(Some(percent)))
+                    }
                     _ => {}
                 }
             }
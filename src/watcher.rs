@@ -0,0 +1,47 @@
+use std::path::Path;
+use windows_sys::Win32::Foundation::{INVALID_HANDLE_VALUE, WAIT_OBJECT_0};
+use windows_sys::Win32::Storage::FileSystem::{
+    FindCloseChangeNotification, FindFirstChangeNotificationW, FindNextChangeNotification,
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE,
+};
+use windows_sys::Win32::System::Threading::{GetCurrentThreadId, WaitForSingleObject, INFINITE};
+use windows_sys::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_APP};
+
+/// Posted to the thread that called `watch_dir` whenever a file in the
+/// directory is written, created, renamed or deleted.
+pub const WM_DIR_CHANGED: u32 = WM_APP + 1;
+
+/// Watches `dir` on a background thread. Editors typically touch a file
+/// several times per save, so the receiver should debounce.
+pub fn watch_dir(dir: &Path) {
+    let path: Vec<u16> = dir
+        .as_os_str()
+        .to_string_lossy()
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let thread_id = unsafe { GetCurrentThreadId() };
+
+    std::thread::spawn(move || unsafe {
+        let handle = FindFirstChangeNotificationW(
+            path.as_ptr(),
+            0,
+            FILE_NOTIFY_CHANGE_LAST_WRITE | FILE_NOTIFY_CHANGE_FILE_NAME,
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return;
+        }
+
+        while WaitForSingleObject(handle, INFINITE) == WAIT_OBJECT_0 {
+            if PostThreadMessageW(thread_id, WM_DIR_CHANGED, 0, 0) == 0 {
+                // The UI thread is gone
+                break;
+            }
+            if FindNextChangeNotification(handle) == 0 {
+                break;
+            }
+        }
+
+        FindCloseChangeNotification(handle);
+    });
+}